@@ -1,5 +1,7 @@
 use clap::Parser;
 
+use crate::editor::theme::{Background, ColorSupport};
+
 //
 
 #[derive(Debug, Parser)]
@@ -7,4 +9,12 @@ use clap::Parser;
 pub struct Args {
     /// an initial file to be opened
     pub file: Option<String>,
+
+    /// the colors to use, RGB theme colors are converted for terminals without truecolor
+    #[arg(long, value_enum, default_value_t = ColorSupport::Auto)]
+    pub color: ColorSupport,
+
+    /// the theme, `auto` picks the light theme if the terminal background is light
+    #[arg(long, value_enum, default_value_t = Background::Auto)]
+    pub theme: Background,
 }
@@ -1,17 +1,24 @@
 use std::{
     borrow::Cow,
+    collections::BTreeSet,
     fs,
     io::{self, BufWriter, Read, Seek, Write},
+    mem,
     ops::Range,
     path::{Path, PathBuf},
     sync::{mpsc::Sender, Arc, LazyLock},
+    time::{Duration, Instant, SystemTime},
 };
 
-use eyre::{bail, Result};
-use ropey::{Rope, RopeSlice};
-use tree_sitter::{InputEdit, Language, Parser, Point, Tree};
+use eyre::{bail, eyre, Result};
+use ropey::{str_utils::byte_to_char_idx, Rope, RopeSlice};
+use tree_sitter::{InputEdit, Language, Parser, Point, Query, Tree};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
 
-use crate::tramp::{Connection, ConnectionPool, Part};
+use crate::{
+    editor::options::BufferOptions,
+    tramp::{Connection, ConnectionPool, Part},
+};
 
 //
 
@@ -19,22 +26,30 @@ pub struct Syntax {
     pub parser: Parser,
     pub tree: Tree,
     pub lang: Lang,
+    /// the language highlight query, `None` if there is none
+    pub highlights: Option<&'static Query>,
 }
 
 impl Syntax {
     pub fn try_from_ext(path: &str, rope: RopeSlice) -> Option<Syntax> {
-        Path::extension(path.as_ref())
-            .and_then(|s| s.to_str())
-            .and_then(|s| Lang::try_from(s).ok())
-            .map(|lang| {
-                let mut parser = Parser::new();
-                parser.set_logger(crate::ts_logger());
-                parser.set_language(&lang.ts_language()).unwrap();
+        Self::try_from_lang(Lang::detect(path)?, rope)
+    }
 
-                let tree = Self::parse(&mut parser, rope, None);
+    pub fn try_from_lang(lang: Lang, rope: RopeSlice) -> Option<Syntax> {
+        let language = lang.ts_language()?;
 
-                Syntax { parser, tree, lang }
-            })
+        let mut parser = Parser::new();
+        parser.set_logger(crate::ts_logger());
+        parser.set_language(&language).unwrap();
+
+        let tree = Self::parse(&mut parser, rope, None);
+
+        Some(Syntax {
+            parser,
+            tree,
+            lang,
+            highlights: lang.highlights(),
+        })
     }
 
     pub fn update(&mut self, rope: RopeSlice) {
@@ -66,19 +81,202 @@ impl Syntax {
 
 //
 
+/// file name patterns, checked before the file extension,
+/// a `*` at the start or the end matches anything
+const FILE_NAME_PATTERNS: &[(&str, Lang)] = &[
+    ("Makefile", Lang::Make),
+    ("makefile", Lang::Make),
+    ("GNUmakefile", Lang::Make),
+    ("Dockerfile", Lang::Dockerfile),
+    ("Dockerfile.*", Lang::Dockerfile),
+    ("*.dockerfile", Lang::Dockerfile),
+    ("Cargo.toml", Lang::Toml),
+    ("*.lock", Lang::Toml),
+    (".bashrc", Lang::Sh),
+    (".bash_profile", Lang::Sh),
+    (".bash_aliases", Lang::Sh),
+    (".profile", Lang::Sh),
+    (".zshrc", Lang::Sh),
+];
+
+/// how many lines at the start and at the end are checked for a modeline, same as vim
+const MODELINES: usize = 5;
+
 #[derive(Debug, Clone, Copy)]
 pub enum Lang {
     Rust,
     Zig,
     C,
+    Go,
+    Make,
+    Markdown,
+    Dockerfile,
+    Toml,
+    Sh,
+    Json,
+    Python,
 }
 
 impl Lang {
-    pub fn ts_language(self) -> Language {
+    /// detect the filetype from the file name or the file extension
+    pub fn detect(path: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let file_name = path.file_name().and_then(|s| s.to_str())?;
+
+        let by_name = FILE_NAME_PATTERNS.iter().find(|(pattern, _)| {
+            if let Some(suffix) = pattern.strip_prefix('*') {
+                file_name.ends_with(suffix)
+            } else if let Some(prefix) = pattern.strip_suffix('*') {
+                file_name.starts_with(prefix)
+            } else {
+                file_name == *pattern
+            }
+        });
+        if let Some((_, lang)) = by_name {
+            return Some(*lang);
+        }
+
+        path.extension()
+            .and_then(|s| s.to_str())
+            .and_then(|s| Self::try_from(s).ok())
+    }
+
+    /// detect the filetype from a vim modeline in the first or the last lines,
+    /// or from the interpreter in a `#!` line
+    pub fn detect_contents(rope: RopeSlice) -> Option<Self> {
+        let lines = rope.len_lines();
+        let head = 0..lines.min(MODELINES);
+        let tail = lines.saturating_sub(MODELINES).max(head.end)..lines;
+
+        head.chain(tail)
+            .find_map(|i| Self::from_modeline(&Cow::from(rope.line(i))))
+            .or_else(|| Self::from_shebang(&Cow::from(rope.line(0))))
+    }
+
+    /// the filetype of a modeline like `// vim: ft=rust` or `# vim: set filetype=sh :`
+    fn from_modeline(line: &str) -> Option<Self> {
+        // the marker has to be at the start or after a whitespace, `index: ft=c` is not one
+        let options = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+            line.match_indices(marker)
+                .find(|(i, _)| line[..*i].is_empty() || line[..*i].ends_with(char::is_whitespace))
+                .map(|(i, marker)| &line[i + marker.len()..])
+        })?;
+
+        options.split([' ', '\t', ':']).find_map(|option| {
+            let (name, value) = option.trim().split_once('=')?;
+            matches!(name, "ft" | "filetype" | "syn" | "syntax")
+                .then(|| Self::from_name(value))
+                .flatten()
+        })
+    }
+
+    /// the filetype of the interpreter in a `#!` line, `#!/usr/bin/env bash` is `sh`
+    fn from_shebang(line: &str) -> Option<Self> {
+        let mut words = line.strip_prefix("#!")?.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            // skip the env flags and variables, like `env -S FOO=1 cargo -Zscript`
+            program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        }
+
+        match program {
+            "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" => Some(Self::Sh),
+            "make" | "gmake" => Some(Self::Make),
+            "python" | "python3" => Some(Self::Python),
+            "cargo" | "rust-script" => Some(Self::Rust),
+            _ => None,
+        }
+    }
+
+    /// the filetype from its name or its file extension, `rust` or `rs`
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rust" => Some(Self::Rust),
+            "c" => Some(Self::C),
+            "make" => Some(Self::Make),
+            "markdown" => Some(Self::Markdown),
+            "dockerfile" => Some(Self::Dockerfile),
+            "zsh" => Some(Self::Sh),
+            "json" => Some(Self::Json),
+            "python" => Some(Self::Python),
+            _ => Self::try_from(name).ok(),
+        }
+    }
+
+    /// the filetype name, used in the filetype options
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Zig => "zig",
+            Self::C => "c",
+            Self::Go => "go",
+            Self::Make => "make",
+            Self::Markdown => "markdown",
+            Self::Dockerfile => "dockerfile",
+            Self::Toml => "toml",
+            Self::Sh => "sh",
+            Self::Json => "json",
+            Self::Python => "python",
+        }
+    }
+
+    /// the tree-sitter grammar, if there is one
+    pub fn ts_language(self) -> Option<Language> {
+        match self {
+            Self::Rust => Some(tree_sitter_rust::LANGUAGE.into()),
+            Self::Zig => Some(tree_sitter_zig::LANGUAGE.into()),
+            Self::C => Some(tree_sitter_c::LANGUAGE.into()),
+            Self::Markdown => Some(tree_sitter_md::LANGUAGE.into()),
+            Self::Toml => Some(tree_sitter_toml_ng::LANGUAGE.into()),
+            Self::Json => Some(tree_sitter_json::LANGUAGE.into()),
+            Self::Python => Some(tree_sitter_python::LANGUAGE.into()),
+            _ => None,
+        }
+    }
+
+    /// the `highlights.scm` query of the language, compiled once
+    pub fn highlights(self) -> Option<&'static Query> {
+        static RUST: LazyLock<Option<Query>> = LazyLock::new(|| Lang::Rust.compile_highlights());
+        static ZIG: LazyLock<Option<Query>> = LazyLock::new(|| Lang::Zig.compile_highlights());
+        static C: LazyLock<Option<Query>> = LazyLock::new(|| Lang::C.compile_highlights());
+        static MARKDOWN: LazyLock<Option<Query>> =
+            LazyLock::new(|| Lang::Markdown.compile_highlights());
+        static TOML: LazyLock<Option<Query>> = LazyLock::new(|| Lang::Toml.compile_highlights());
+        static JSON: LazyLock<Option<Query>> = LazyLock::new(|| Lang::Json.compile_highlights());
+        static PYTHON: LazyLock<Option<Query>> =
+            LazyLock::new(|| Lang::Python.compile_highlights());
+
         match self {
-            Self::Rust => tree_sitter_rust::LANGUAGE.into(),
-            Self::Zig => tree_sitter_zig::LANGUAGE.into(),
-            Self::C => tree_sitter_c::LANGUAGE.into(),
+            Self::Rust => RUST.as_ref(),
+            Self::Zig => ZIG.as_ref(),
+            Self::C => C.as_ref(),
+            Self::Markdown => MARKDOWN.as_ref(),
+            Self::Toml => TOML.as_ref(),
+            Self::Json => JSON.as_ref(),
+            Self::Python => PYTHON.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn compile_highlights(self) -> Option<Query> {
+        let source = match self {
+            Self::Rust => tree_sitter_rust::HIGHLIGHTS_QUERY,
+            Self::Zig => tree_sitter_zig::HIGHLIGHTS_QUERY,
+            Self::C => tree_sitter_c::HIGHLIGHT_QUERY,
+            // only the block grammar, inline markup like `*emphasis*` is not parsed
+            Self::Markdown => tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+            Self::Toml => tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+            Self::Json => tree_sitter_json::HIGHLIGHTS_QUERY,
+            Self::Python => tree_sitter_python::HIGHLIGHTS_QUERY,
+            _ => return None,
+        };
+
+        match Query::new(&self.ts_language()?, source) {
+            Ok(query) => Some(query),
+            Err(err) => {
+                tracing::error!("invalid {} highlight query: {err}", self.name());
+                None
+            }
         }
     }
 }
@@ -89,10 +287,19 @@ pub struct UnknownLanguage;
 impl TryFrom<&str> for Lang {
     type Error = UnknownLanguage;
 
+    /// the file extension, in any case
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value {
+        match value.to_ascii_lowercase().as_str() {
             "rs" => Ok(Self::Rust),
             "zig" => Ok(Self::Zig),
+            "c" | "h" => Ok(Self::C),
+            "go" => Ok(Self::Go),
+            "mk" => Ok(Self::Make),
+            "md" => Ok(Self::Markdown),
+            "toml" => Ok(Self::Toml),
+            "sh" | "bash" => Ok(Self::Sh),
+            "json" => Ok(Self::Json),
+            "py" | "pyi" => Ok(Self::Python),
             _ => Err(UnknownLanguage),
         }
     }
@@ -100,6 +307,9 @@ impl TryFrom<&str> for Lang {
 
 //
 
+/// the name suffix of read-only copies of saved files
+pub const SNAPSHOT_SUFFIX: &str = " [disk]";
+
 pub struct Buffer {
     pub contents: Rope,
     pub name: Cow<'static, str>,
@@ -108,67 +318,266 @@ pub struct Buffer {
     pub inner: BufferInner,
     pub modified: bool,
     pub syntax: Option<Syntax>,
+    pub options: BufferOptions,
+    /// incremented on every edit
+    pub revision: u64,
+    /// where insert mode was last left, for `gi`
+    pub last_insert: Option<usize>,
+    /// bookmarked lines, shown in the gutter, they move with the text around them
+    pub bookmarks: BTreeSet<usize>,
+    /// too big for syntax highlighting, so it is opened read-only just for browsing
+    pub huge: bool,
+    /// the file had more than one kind of line ending, they are all saved as the fileformat's
+    pub mixed_line_endings: bool,
+    history: History,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum ContentTransform {
-    Utf8,
+    Utf8(FileFormat),
     Hex,
     Nbt,
 }
 
+impl ContentTransform {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            ContentTransform::Utf8(_) => "utf-8",
+            ContentTransform::Hex => "hex",
+            ContentTransform::Nbt => "nbt",
+        }
+    }
+}
+
+/// the line endings of a text file, the buffer itself always uses `\n`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl FileFormat {
+    /// the most common line ending decides, unix if there are none or on a tie
+    pub fn detect(s: &str) -> Self {
+        let [unix, dos, mac] = Self::count(s.as_bytes());
+        if dos > unix && dos >= mac {
+            FileFormat::Dos
+        } else if mac > unix && mac > dos {
+            FileFormat::Mac
+        } else {
+            FileFormat::Unix
+        }
+    }
+
+    /// true if there is more than one kind of line ending
+    pub fn is_mixed(bytes: &[u8]) -> bool {
+        Self::count(bytes)
+            .iter()
+            .filter(|&&count| count != 0)
+            .count()
+            > 1
+    }
+
+    /// how many `\n`, `\r\n` and lone `\r` line endings there are
+    fn count(bytes: &[u8]) -> [usize; 3] {
+        let mut counts = [0; 3];
+        let mut iter = bytes.iter().peekable();
+        while let Some(&byte) = iter.next() {
+            match byte {
+                b'\n' => counts[0] += 1,
+                b'\r' if iter.next_if_eq(&&b'\n').is_some() => counts[1] += 1,
+                b'\r' => counts[2] += 1,
+                _ => {}
+            }
+        }
+        counts
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            "mac" => Some(FileFormat::Mac),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        }
+    }
+
+    /// what each `\n` is written as
+    pub const fn line_ending(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+            FileFormat::Mac => "\r",
+        }
+    }
+
+    /// convert the line endings of `s` into `\n`,
+    /// the `\r\n`s of a mixed file too, so they are saved as this format
+    fn normalize(self, s: &str) -> Cow<'_, str> {
+        match self {
+            FileFormat::Mac => Cow::Owned(s.replace("\r\n", "\n").replace('\r', "\n")),
+            _ if s.contains("\r\n") => Cow::Owned(s.replace("\r\n", "\n")),
+            _ => Cow::Borrowed(s),
+        }
+    }
+}
+
 pub enum BufferInner {
-    File { inner: fs::File, readonly: bool },
-    NewFile { inner: PathBuf },
-    Remote { remote: Arc<[Part]>, readonly: bool },
-    Scratch { show_welcome: bool },
+    File {
+        inner: fs::File,
+        readonly: bool,
+        /// the file on disk when it was last read or saved
+        stamp: DiskStamp,
+    },
+    NewFile {
+        inner: PathBuf,
+    },
+    Remote {
+        remote: Arc<[Part]>,
+        readonly: bool,
+    },
+    Scratch {
+        show_welcome: bool,
+    },
+    // a read-only placeholder, while the real file is loaded in the background
+    Loading,
 }
 
 impl Buffer {
     pub fn new() -> Self {
         Self {
             contents: Rope::new(),
-            ty: ContentTransform::Utf8,
+            ty: ContentTransform::Utf8(FileFormat::Unix),
             name: Cow::Borrowed("[scratch]"),
             inner: BufferInner::Scratch {
                 show_welcome: false,
             },
             modified: false,
             syntax: None,
+            options: BufferOptions::default(),
+            revision: 0,
+            last_insert: None,
+            bookmarks: BTreeSet::new(),
+            huge: false,
+            mixed_line_endings: false,
+            history: History::default(),
         }
     }
 
     pub fn new_welcome() -> Self {
         Self {
             contents: Rope::new(),
-            ty: ContentTransform::Utf8,
+            ty: ContentTransform::Utf8(FileFormat::Unix),
             name: Cow::Borrowed("[scratch]"),
             inner: BufferInner::Scratch { show_welcome: true },
             modified: false,
             syntax: None,
+            options: BufferOptions::default(),
+            revision: 0,
+            last_insert: None,
+            bookmarks: BTreeSet::new(),
+            huge: false,
+            mixed_line_endings: false,
+            history: History::default(),
         }
     }
 
-    pub fn open(path: &str, askpw_tx: Sender<(String, Sender<String>)>) -> Result<Self> {
-        if let Some((parts, file)) = path.rsplit_once(':') {
-            Ok(Self::open_remote(parts, file, path, askpw_tx)?)
-        } else {
-            Ok(Self::open_local(path)?)
+    pub fn new_loading(path: &str) -> Self {
+        Self {
+            contents: Rope::from(format!("loading {path}…")),
+            ty: ContentTransform::Utf8(FileFormat::Unix),
+            name: path.to_string().into(),
+            inner: BufferInner::Loading,
+            modified: false,
+            syntax: None,
+            options: BufferOptions::default(),
+            revision: 0,
+            last_insert: None,
+            bookmarks: BTreeSet::new(),
+            huge: false,
+            mixed_line_endings: false,
+            history: History::default(),
+        }
+    }
+
+    /// the filetype, detected from the file name
+    pub fn lang(&self) -> Option<Lang> {
+        let name = self
+            .name
+            .strip_suffix(SNAPSHOT_SUFFIX)
+            .unwrap_or(&self.name);
+        let path = Part::split_path(name).map_or(name, |(_, path)| path);
+        Lang::detect(path).or_else(|| Lang::detect_contents(self.contents.slice(..)))
+    }
+
+    /// returns true if the buffer can't be written
+    pub const fn is_readonly(&self) -> bool {
+        match self.inner {
+            BufferInner::File { readonly, .. } | BufferInner::Remote { readonly, .. } => readonly,
+            BufferInner::NewFile { .. } | BufferInner::Scratch { .. } => false,
+            BufferInner::Loading => true,
         }
     }
 
+    /// open a read-only copy of the saved contents of `path`, named `path [disk]`
+    pub fn open_snapshot(
+        path: &str,
+        huge_file: usize,
+        askpw_tx: Sender<(String, Sender<String>)>,
+    ) -> Result<Self> {
+        let mut buf = Self::open(path, huge_file, askpw_tx)?;
+        buf.name = format!("{path}{SNAPSHOT_SUFFIX}").into();
+
+        match &mut buf.inner {
+            BufferInner::File { readonly, .. } | BufferInner::Remote { readonly, .. } => {
+                *readonly = true;
+            }
+            _ => bail!("`{path}` is not saved"),
+        }
+
+        Ok(buf)
+    }
+
+    /// open a local or a remote file, files over `huge_file` bytes are opened as [`Buffer::huge`]
+    pub fn open(
+        path: &str,
+        huge_file: usize,
+        askpw_tx: Sender<(String, Sender<String>)>,
+    ) -> Result<Self> {
+        if let Some((parts, file)) = Part::split_path(path) {
+            return Self::open_remote(parts, file, path, huge_file, askpw_tx);
+        }
+
+        Self::open_local(path, huge_file)
+    }
+
     pub fn open_remote(
         parts: &str,
         path: &str,
         name: &str,
+        huge_file: usize,
         askpw_tx: Sender<(String, Sender<String>)>,
     ) -> Result<Self> {
         let mut conn = CONN_POOL.connect(parts, askpw_tx)?;
-        let buf = Self::open_remote_with(&mut conn, path, name)?;
+        let buf = Self::open_remote_with(&mut conn, path, name, huge_file)?;
         Ok(buf)
     }
 
-    pub fn open_remote_with(conn: &mut Connection, path: &str, name: &str) -> Result<Self> {
+    pub fn open_remote_with(
+        conn: &mut Connection,
+        path: &str,
+        name: &str,
+        huge_file: usize,
+    ) -> Result<Self> {
         let name = name.to_string().into();
 
         let mut file = conn.read_file(path)?;
@@ -180,7 +589,9 @@ impl Buffer {
 
         let remote = conn.remote();
 
-        let (contents, syntax, ty) = Self::read_from(&contents, path);
+        let huge = contents.len() > huge_file;
+        let mixed = !huge && FileFormat::is_mixed(&contents);
+        let (contents, syntax, ty) = Self::read_from(&contents, path, huge);
 
         Ok(Self {
             contents,
@@ -188,14 +599,21 @@ impl Buffer {
             name,
             inner: BufferInner::Remote {
                 remote,
-                readonly: false,
+                readonly: huge,
             },
             modified: false,
             syntax,
+            options: BufferOptions::default(),
+            revision: 0,
+            last_insert: None,
+            bookmarks: BTreeSet::new(),
+            huge,
+            mixed_line_endings: mixed && matches!(ty, ContentTransform::Utf8(_)),
+            history: History::default(),
         })
     }
 
-    pub fn open_local(path: &str) -> Result<Self> {
+    pub fn open_local(path: &str, huge_file: usize) -> Result<Self> {
         let name = path.to_string().into();
 
         // first try opening in RW mode
@@ -211,18 +629,28 @@ impl Buffer {
                 let mut contents = Vec::new();
                 file.read_to_end(&mut contents)?;
 
-                let (contents, syntax, ty) = Self::read_from(&contents, path);
+                let huge = contents.len() > huge_file;
+                let mixed = !huge && FileFormat::is_mixed(&contents);
+                let (contents, syntax, ty) = Self::read_from(&contents, path, huge);
 
                 return Ok(Self {
                     contents,
                     ty,
                     name,
                     inner: BufferInner::File {
+                        stamp: DiskStamp::of(&file)?,
                         inner: file,
-                        readonly: false,
+                        readonly: huge,
                     },
                     modified: false,
                     syntax,
+                    options: BufferOptions::default(),
+                    revision: 0,
+                    last_insert: None,
+                    bookmarks: BTreeSet::new(),
+                    huge,
+                    mixed_line_endings: mixed && matches!(ty, ContentTransform::Utf8(_)),
+                    history: History::default(),
                 });
             }
         };
@@ -240,23 +668,33 @@ impl Buffer {
                 let mut contents = Vec::new();
                 file.read_to_end(&mut contents)?;
 
-                let (contents, syntax, ty) = Self::read_from(&contents, path);
+                let huge = contents.len() > huge_file;
+                let mixed = !huge && FileFormat::is_mixed(&contents);
+                let (contents, syntax, ty) = Self::read_from(&contents, path, huge);
 
                 return Ok(Self {
                     contents,
                     ty,
                     name,
                     inner: BufferInner::File {
+                        stamp: DiskStamp::of(&file)?,
                         inner: file,
                         readonly: true,
                     },
                     modified: false,
                     syntax,
+                    options: BufferOptions::default(),
+                    revision: 0,
+                    last_insert: None,
+                    bookmarks: BTreeSet::new(),
+                    huge,
+                    mixed_line_endings: mixed && matches!(ty, ContentTransform::Utf8(_)),
+                    history: History::default(),
                 });
             }
         };
 
-        let (contents, syntax, ty) = Self::read_from(&[], path);
+        let (contents, syntax, ty) = Self::read_from(&[], path, false);
 
         // finally open it as a new file, without creating the file yet
         Ok(Self {
@@ -266,12 +704,34 @@ impl Buffer {
             inner: BufferInner::NewFile { inner: path.into() },
             modified: false,
             syntax,
+            options: BufferOptions::default(),
+            revision: 0,
+            last_insert: None,
+            bookmarks: BTreeSet::new(),
+            huge: false,
+            mixed_line_endings: false,
+            history: History::default(),
         })
     }
 
-    fn read_from(contents: &[u8], path: &str) -> (Rope, Option<Syntax>, ContentTransform) {
-        if let Some(result) = Self::try_read_utf8(contents, path) {
-            return result;
+    fn read_from(
+        contents: &[u8],
+        path: &str,
+        huge: bool,
+    ) -> (Rope, Option<Syntax>, ContentTransform) {
+        if huge {
+            // skip the slow parts, huge files are read-only so the lossy conversion is fine
+            let contents = Rope::from_str(&String::from_utf8_lossy(contents));
+            return (contents, None, ContentTransform::Utf8(FileFormat::Unix));
+        }
+
+        if let Some((contents, mut syntax, ty)) = Self::try_read_utf8(contents, path) {
+            // extensionless scripts, `#!/bin/sh` or `# vim: ft=sh`
+            if Lang::detect(path).is_none() {
+                syntax = Lang::detect_contents(contents.slice(..))
+                    .and_then(|lang| Syntax::try_from_lang(lang, contents.slice(..)));
+            }
+            return (contents, syntax, ty);
         }
 
         if let Some(result) = Self::try_read_nbt(contents, path) {
@@ -289,10 +749,11 @@ impl Buffer {
             return None;
         };
 
-        let contents = Rope::from_str(s);
+        let format = FileFormat::detect(s);
+        let contents = Rope::from_str(&format.normalize(s));
         let syntax = Syntax::try_from_ext(path, contents.slice(..));
 
-        Some((contents, syntax, ContentTransform::Utf8))
+        Some((contents, syntax, ContentTransform::Utf8(format)))
     }
 
     fn try_read_nbt(
@@ -426,17 +887,60 @@ impl Buffer {
         (contents, syntax, ContentTransform::Hex)
     }
 
+    /// true if another program changed the file since it was last read or saved,
+    /// a deleted file doesn't count, saving creates it again
+    pub fn changed_on_disk(&self) -> bool {
+        let BufferInner::File { stamp, .. } = &self.inner else {
+            return false;
+        };
+        fs::metadata(&*self.name).is_ok_and(|meta| DiskStamp::new(&meta) != *stamp)
+    }
+
+    /// treat the file on disk as the one last read, so saving overwrites the other changes
+    pub fn ignore_disk_changes(&mut self) {
+        let path = Path::new(&*self.name);
+        if let BufferInner::File { stamp, .. } = &mut self.inner {
+            if let Ok(meta) = fs::metadata(path) {
+                *stamp = DiskStamp::new(&meta);
+            }
+        }
+    }
+
+    /// read the file again, the undo history and bookmarks are dropped
+    /// as they don't match the new text
+    pub fn reload(&mut self, huge_file: usize) -> Result<()> {
+        let BufferInner::File { .. } = self.inner else {
+            bail!("only local files can be reloaded");
+        };
+
+        let buf = Self::open_local(&self.name, huge_file)?;
+        *self = Self {
+            options: mem::take(&mut self.options),
+            // edited, as far as views and diffs are concerned
+            revision: self.revision + 1,
+            ..buf
+        };
+        Ok(())
+    }
+
     pub fn write(&mut self, askpw_tx: Sender<(String, Sender<String>)>) -> Result<()> {
+        if self.changed_on_disk() {
+            bail!("the file changed on disk, `reload!` it or overwrite it with `w!`");
+        }
+
         match self.inner {
             BufferInner::File {
                 ref mut inner,
                 readonly,
+                ref mut stamp,
             } => {
                 if readonly {
                     bail!("readonly");
                 }
 
-                Self::write_to_file(&self.contents, self.ty, &mut self.modified, inner)?;
+                let path = Path::new(&*self.name);
+                *inner = Self::write_atomic(&self.contents, self.ty, path)?;
+                *stamp = DiskStamp::of(inner)?;
             }
             BufferInner::NewFile { ref inner } => {
                 let mut new_file = fs::OpenOptions::new()
@@ -444,9 +948,10 @@ impl Buffer {
                     .create_new(true)
                     .open(inner)?;
 
-                Self::write_to_file(&self.contents, self.ty, &mut self.modified, &mut new_file)?;
+                Self::write_to_file(&self.contents, self.ty, &mut new_file)?;
 
                 self.inner = BufferInner::File {
+                    stamp: DiskStamp::of(&new_file)?,
                     inner: new_file,
                     readonly: false,
                 };
@@ -459,14 +964,13 @@ impl Buffer {
                     bail!("readonly");
                 }
 
-                let (_, filename) = self.name.rsplit_once(':').unwrap();
-
-                let mut conn = CONN_POOL.connect_to(remote.clone(), askpw_tx)?;
-                let writer = conn.write_file(filename)?;
-
-                Self::write_to(&self.contents, self.ty, &mut self.modified, writer)?;
-
-                conn.finish_write_file(filename)?;
+                Self::write_remote(
+                    remote.clone(),
+                    &self.name,
+                    &self.contents,
+                    self.ty,
+                    askpw_tx,
+                )?;
             }
             BufferInner::Scratch {
                 ref mut show_welcome,
@@ -474,6 +978,9 @@ impl Buffer {
                 *show_welcome = false;
                 bail!("no file path set");
             }
+            BufferInner::Loading => {
+                bail!("still loading");
+            }
         };
 
         self.modified = false;
@@ -481,29 +988,106 @@ impl Buffer {
         Ok(())
     }
 
-    fn write_to_file(
+    /// write `contents` into a remote file, this doesn't need the [`Buffer`]
+    /// so that slow remote writes can run on a worker thread
+    pub fn write_remote(
+        remote: Arc<[Part]>,
+        name: &str,
         contents: &Rope,
         ty: ContentTransform,
-        modified: &mut bool,
-        output: &mut fs::File,
+        askpw_tx: Sender<(String, Sender<String>)>,
     ) -> Result<()> {
+        let (_, filename) = Part::split_path(name).unwrap();
+
+        let mut conn = CONN_POOL.connect_to(remote.clone(), askpw_tx)?;
+        let writer = conn.write_file(filename)?;
+
+        Self::write_to(contents, ty, writer)?;
+
+        conn.finish_write_file(filename)?;
+        drop(conn);
+
+        // the file might be new, so the directory listing is outdated
+        if let Some(dir) = Path::new(filename).parent() {
+            CONN_POOL.invalidate(&remote, dir);
+        }
+
+        Ok(())
+    }
+
+    /// write `contents` into a temporary file next to `path` and rename it over `path`,
+    /// so a failed save never leaves a half written file, returns the new file
+    fn write_atomic(contents: &Rope, ty: ContentTransform, path: &Path) -> Result<fs::File> {
+        // a symlink stays a symlink, the file it points to is replaced instead
+        let path = fs::canonicalize(path)?;
+        let (tmp_path, mut tmp) = Self::create_tmp(&path)?;
+
+        let result = (|| -> Result<()> {
+            if let Ok(meta) = fs::metadata(&path) {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::{fchown, MetadataExt};
+                    // only root can give files away, keeping the owner is best effort
+                    _ = fchown(&tmp, Some(meta.uid()), Some(meta.gid()));
+                }
+                tmp.set_permissions(meta.permissions())?;
+            }
+            Self::write_to(contents, ty, &mut tmp)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_path, &path)?;
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        Ok(tmp)
+    }
+
+    /// create a new hidden file next to `path`, in the same filesystem so it can be renamed over it
+    fn create_tmp(path: &Path) -> Result<(PathBuf, fs::File)> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let pid = std::process::id();
+        let mut i = 0usize;
+        loop {
+            let tmp_path = path.with_file_name(format!(".{name}.{pid}-{i}.tmp"));
+            match fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&tmp_path)
+            {
+                Ok(file) => return Ok((tmp_path, file)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => i += 1,
+                Err(err) => bail!(err),
+            }
+        }
+    }
+
+    fn write_to_file(contents: &Rope, ty: ContentTransform, output: &mut fs::File) -> Result<()> {
         output.seek(io::SeekFrom::Start(0))?;
         output.set_len(0)?;
 
-        Self::write_to(contents, ty, modified, output)?;
+        Self::write_to(contents, ty, output)?;
 
         Ok(())
     }
 
-    fn write_to(
-        contents: &Rope,
-        ty: ContentTransform,
-        modified: &mut bool,
-        mut output: impl Write,
-    ) -> Result<()> {
+    /// write and flush `contents`, the caller clears `modified` once the file is in place
+    fn write_to(contents: &Rope, ty: ContentTransform, mut output: impl Write) -> Result<()> {
         match ty {
-            ContentTransform::Utf8 => {
-                contents.write_to(BufWriter::new(output))?;
+            ContentTransform::Utf8(FileFormat::Unix) => {
+                let mut output = BufWriter::new(output);
+                contents.write_to(&mut output)?;
+                output.flush()?;
+            }
+            ContentTransform::Utf8(format) => {
+                let mut output = BufWriter::new(output);
+                for chunk in contents.chunks() {
+                    output.write_all(chunk.replace('\n', format.line_ending()).as_bytes())?;
+                }
+                output.flush()?;
             }
             ContentTransform::Hex => {
                 let mut buf = Vec::new();
@@ -534,6 +1118,7 @@ impl Buffer {
                 }
 
                 output.write_all(&buf)?;
+                output.flush()?;
             }
             ContentTransform::Nbt => {
                 /* struct RopeReader<'a> {
@@ -565,15 +1150,47 @@ impl Buffer {
 
                 let contents = contents.to_string(); // TODO: implement Read for fastsnbt
 
-                let encoder = flate2::GzBuilder::new()
+                let mut encoder = flate2::GzBuilder::new()
                     .write(BufWriter::new(output), flate2::Compression::best());
 
                 let val: fastnbt::Value = fastsnbt::from_str(&contents)?;
-                fastnbt::to_writer(encoder, &val)?;
+                fastnbt::to_writer(&mut encoder, &val)?;
+                // the gzip trailer is only written by `finish`
+                encoder.finish()?.flush()?;
             }
         }
 
-        *modified = false;
+        Ok(())
+    }
+
+    /// the line endings it is saved with, `None` if it isn't a text file
+    pub const fn fileformat(&self) -> Option<FileFormat> {
+        match self.ty {
+            ContentTransform::Utf8(format) => Some(format),
+            _ => None,
+        }
+    }
+
+    /// save with `format` line endings from now on, stray `\r`s are removed
+    pub fn set_fileformat(&mut self, format: FileFormat) -> Result<()> {
+        let Some(old) = self.fileformat() else {
+            bail!("not a text file, it is {}", self.ty.as_str());
+        };
+        if self.is_readonly() {
+            bail!("readonly");
+        }
+
+        // one replace, so undo brings all of them back at once
+        let text = self.contents.to_string();
+        if text.contains('\r') {
+            let len = self.contents.len_chars();
+            self.replace_text_at(0..len, &text.replace("\r\n", "\n").replace('\r', "\n"));
+        }
+
+        if old != format {
+            self.ty = ContentTransform::Utf8(format);
+            self.modified = true;
+        }
 
         Ok(())
     }
@@ -606,19 +1223,248 @@ impl Buffer {
         self.replace_text_at(cursor..text.len(), text);
     }
 
-    /// delete range `cursor` and replace it with `text`
-    pub fn replace_text_at(&mut self, mut cursor: Range<usize>, text: &str) {
-        if cursor.start > self.contents.len_chars() {
-            cursor.start = self.contents.len_chars();
-            tracing::warn!("replace_text_at cursor.start out of bounds");
+    /// delete range `cursor` and replace it with `text`,
+    /// an out of bounds range is only logged, [`Buffer::replace`] returns it as an error
+    pub fn replace_text_at(&mut self, cursor: Range<usize>, text: &str) {
+        if matches!(self.inner, BufferInner::Loading) {
             return;
         }
-        if cursor.end > self.contents.len_chars() {
-            cursor.end = self.contents.len_chars();
-            tracing::warn!("replace_text_at cursor.end out of bounds");
-            return;
+
+        if let Err(err) = self.replace(cursor, text) {
+            tracing::warn!("replace_text_at: {err}");
+        }
+    }
+
+    /// replace the chars in `range` with `text`, as one undoable edit
+    pub fn replace(&mut self, range: Range<usize>, text: &str) -> Result<()> {
+        if matches!(self.inner, BufferInner::Loading) {
+            bail!("still loading");
+        }
+        let len = self.contents.len_chars();
+        if range.start > range.end || range.end > len {
+            bail!("char range {range:?} is out of bounds, the buffer has {len} chars");
+        }
+
+        if range.is_empty() && text.is_empty() {
+            return Ok(());
+        }
+
+        // huge files keep no undo history, it would hold on to every deleted chunk of them
+        if !self.huge {
+            let old = self.contents.slice(range.clone()).to_string();
+            let timeout = Duration::from_millis(self.options.undotimeout as u64);
+            self.history.record(
+                Edit {
+                    at: range.start,
+                    old,
+                    new: text.to_string(),
+                },
+                Instant::now(),
+                timeout,
+            );
+        }
+
+        self.apply_edit(range, text);
+        self.reparse();
+        Ok(())
+    }
+
+    /// insert `text` before the char at `at`
+    pub fn insert(&mut self, at: usize, text: &str) -> Result<()> {
+        self.replace(at..at, text)
+    }
+
+    /// delete the chars in `range`
+    pub fn delete(&mut self, range: Range<usize>) -> Result<()> {
+        self.replace(range, "")
+    }
+
+    /// apply every edit `f` adds to the transaction at once, as one undo step and one re-parse,
+    /// the ranges are in the text before the transaction, see [`Transaction`] for overlaps
+    ///
+    /// nothing is changed if any range is out of bounds or overlaps another one
+    pub fn edit(&mut self, f: impl FnOnce(&mut Transaction)) -> Result<()> {
+        if matches!(self.inner, BufferInner::Loading) {
+            bail!("still loading");
         }
 
+        let mut tx = Transaction::default();
+        f(&mut tx);
+        let mut edits = tx.edits;
+        // stable, so inserts at the same char keep the order they were added in
+        edits.sort_by_key(|(range, _)| (range.start, range.end));
+
+        let len = self.contents.len_chars();
+        let mut prev: Option<&Range<usize>> = None;
+        for (range, _) in &edits {
+            if range.start > range.end || range.end > len {
+                bail!("char range {range:?} is out of bounds, the buffer has {len} chars");
+            }
+            if let Some(prev) = prev.filter(|prev| range.start < prev.end) {
+                bail!("char range {range:?} overlaps the char range {prev:?} in the same edit");
+            }
+            prev = Some(range);
+        }
+
+        // back to front, so the ranges before each edit stay where they were
+        let mut group = Vec::with_capacity(edits.len());
+        for (range, text) in edits.into_iter().rev() {
+            if range.is_empty() && text.is_empty() {
+                continue;
+            }
+            let old = self.contents.slice(range.clone()).to_string();
+            self.apply_edit(range.clone(), &text);
+            group.push(Edit {
+                at: range.start,
+                old,
+                new: text,
+            });
+        }
+
+        if !group.is_empty() {
+            if !self.huge {
+                self.history.record_group(group, Instant::now());
+            }
+            self.reparse();
+        }
+        Ok(())
+    }
+
+    /// line `line` with its newline
+    pub fn line(&self, line: usize) -> Result<RopeSlice<'_>> {
+        let count = self.line_count();
+        if line >= count {
+            bail!("line {line} is out of bounds, the buffer has {count} lines");
+        }
+        Ok(self.contents.line(line))
+    }
+
+    /// the number of lines, text ending with a newline has an empty line after it
+    pub fn line_count(&self) -> usize {
+        self.contents.len_lines()
+    }
+
+    pub fn char_at(&self, at: usize) -> Result<char> {
+        self.contents.get_char(at).ok_or_else(|| {
+            let len = self.contents.len_chars();
+            eyre!("char {at} is out of bounds, the buffer has {len} chars")
+        })
+    }
+
+    /// the whole text
+    pub fn text(&self) -> String {
+        self.contents.to_string()
+    }
+
+    /// every line with its newline
+    pub fn lines(&self) -> ropey::iter::Lines<'_> {
+        self.contents.lines()
+    }
+
+    pub fn chars(&self) -> ropey::iter::Chars<'_> {
+        self.contents.chars()
+    }
+
+    /// undo the last group of edits, returns the start of the change for the cursor
+    pub fn undo(&mut self) -> Option<usize> {
+        let group = self.history.undo.pop()?;
+        for edit in group.edits.iter().rev() {
+            self.apply_edit(edit.at..edit.at + edit.new.chars().count(), &edit.old);
+        }
+        self.reparse();
+        let cursor = group.start;
+        self.history.redo.push(group);
+        self.history.typing = false;
+        Some(cursor)
+    }
+
+    /// redo the last undone group of edits, returns the end of the change for the cursor
+    pub fn redo(&mut self) -> Option<usize> {
+        let group = self.history.redo.pop()?;
+        for edit in group.edits.iter() {
+            self.apply_edit(edit.at..edit.at + edit.old.chars().count(), &edit.new);
+        }
+        self.reparse();
+        // on the last changed char, or where the text was if it was only deleted
+        let cursor = group.end.saturating_sub(1).max(group.start);
+        self.history.undo.push(group);
+        self.history.typing = false;
+        Some(cursor)
+    }
+
+    /// stop typed characters from joining the last undo group, like when leaving insert mode
+    pub fn end_undo_group(&mut self) {
+        self.history.typing = false;
+    }
+
+    /// the char index after the grapheme cluster at `at`,
+    /// a char and its combining marks, or a flag, are one cluster
+    pub fn next_grapheme(&self, at: usize) -> usize {
+        let rope = &self.contents;
+        let at = at.min(rope.len_chars());
+        let byte = rope.char_to_byte(at);
+        let (mut chunk, mut chunk_byte, mut chunk_char, _) = rope.chunk_at_byte(byte);
+        let mut cursor = GraphemeCursor::new(byte, rope.len_bytes(), true);
+
+        loop {
+            match cursor.next_boundary(chunk, chunk_byte) {
+                Ok(None) => return rope.len_chars(),
+                Ok(Some(n)) => return chunk_char + byte_to_char_idx(chunk, n - chunk_byte),
+                Err(GraphemeIncomplete::NextChunk) => {
+                    chunk_byte += chunk.len();
+                    (chunk, _, chunk_char, _) = rope.chunk_at_byte(chunk_byte);
+                }
+                Err(GraphemeIncomplete::PreContext(n)) => {
+                    let (context, context_byte, _, _) = rope.chunk_at_byte(n - 1);
+                    cursor.provide_context(context, context_byte);
+                }
+                Err(_) => return (at + 1).min(rope.len_chars()),
+            }
+        }
+    }
+
+    /// the char index where the grapheme cluster before `at` starts
+    pub fn prev_grapheme(&self, at: usize) -> usize {
+        let rope = &self.contents;
+        let at = at.min(rope.len_chars());
+        let byte = rope.char_to_byte(at);
+        let (mut chunk, mut chunk_byte, mut chunk_char, _) = rope.chunk_at_byte(byte);
+        let mut cursor = GraphemeCursor::new(byte, rope.len_bytes(), true);
+
+        loop {
+            match cursor.prev_boundary(chunk, chunk_byte) {
+                Ok(None) => return 0,
+                Ok(Some(n)) => return chunk_char + byte_to_char_idx(chunk, n - chunk_byte),
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    (chunk, chunk_byte, chunk_char, _) = rope.chunk_at_byte(chunk_byte - 1);
+                }
+                Err(GraphemeIncomplete::PreContext(n)) => {
+                    let (context, context_byte, _, _) = rope.chunk_at_byte(n - 1);
+                    cursor.provide_context(context, context_byte);
+                }
+                Err(_) => return at.saturating_sub(1),
+            }
+        }
+    }
+
+    /// the char index where the grapheme cluster containing `at` starts
+    pub fn grapheme_start(&self, at: usize) -> usize {
+        if at >= self.contents.len_chars() {
+            return at;
+        }
+        self.prev_grapheme(self.next_grapheme(at))
+    }
+
+    /// replace text without recording it in the undo history
+    fn apply_edit(&mut self, cursor: Range<usize>, text: &str) {
+        self.shift_bookmarks(cursor.clone(), text);
+
+        // the start and the old end have to be read from the text before the edit
+        let start_byte = self.contents.char_to_byte(cursor.start);
+        let old_end_byte = self.contents.char_to_byte(cursor.end);
+        let start_position = self.byte_to_point(start_byte);
+        let old_end_position = self.byte_to_point(old_end_byte);
+
         if !cursor.is_empty() {
             self.contents.remove(cursor.clone());
             self.modified = true;
@@ -627,46 +1473,80 @@ impl Buffer {
             self.contents.insert(cursor.start, text);
             self.modified = true;
         }
+        if !cursor.is_empty() || !text.is_empty() {
+            self.revision += 1;
+        }
 
         // update syntax highlighting
-        let Some(syntax) = self.syntax.as_mut() else {
+        if self.syntax.is_none() {
             return;
-        };
+        }
 
-        let start_byte_idx = self.contents.char_to_byte(cursor.start);
-        let end_byte_idx = if cursor.is_empty() {
-            start_byte_idx
-        } else {
-            self.contents.char_to_byte(cursor.end)
-        };
+        let new_end_byte = start_byte + text.len();
+        let new_end_position = self.byte_to_point(new_end_byte);
+
+        if let Some(syntax) = self.syntax.as_mut() {
+            syntax.tree.edit(&InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position,
+            });
+        }
+    }
 
-        let old_start_line_idx = self.contents.byte_to_line(start_byte_idx);
-        let old_start_col_idx = start_byte_idx - self.contents.line_to_byte(old_start_line_idx);
-        let old_end_line_idx = if cursor.is_empty() {
-            old_start_line_idx
-        } else {
-            self.contents.byte_to_line(end_byte_idx)
-        };
-        let old_end_col_idx = if cursor.is_empty() {
-            old_start_col_idx
-        } else {
-            end_byte_idx - self.contents.line_to_byte(old_end_line_idx)
-        };
+    /// the tree-sitter row and byte column of `byte`
+    fn byte_to_point(&self, byte: usize) -> Point {
+        let line = self.contents.byte_to_line(byte);
+        Point::new(line, byte - self.contents.line_to_byte(line))
+    }
 
-        let new_end = start_byte_idx + text.len();
-        let new_end_line_idx = self.contents.byte_to_line(new_end);
-        let new_end_col_idx = new_end - self.contents.line_to_byte(old_end_line_idx);
+    /// parse the syntax tree again after edits, only the edited parts are parsed
+    fn reparse(&mut self) {
+        if let Some(syntax) = self.syntax.as_mut() {
+            syntax.update(self.contents.slice(..));
+        }
+    }
 
-        syntax.tree.edit(&InputEdit {
-            start_byte: start_byte_idx,
-            old_end_byte: end_byte_idx,
+    /// move the bookmarks of lines starting at or after an edit by the lines it adds or removes,
+    /// bookmarks on lines the edit removes with their line break are dropped
+    fn shift_bookmarks(&mut self, cursor: Range<usize>, text: &str) {
+        if self.bookmarks.is_empty() {
+            return;
+        }
 
-            new_end_byte: new_end,
-            start_position: Point::new(old_start_line_idx, old_start_col_idx),
-            old_end_position: Point::new(old_end_line_idx, old_end_col_idx),
-            new_end_position: Point::new(new_end_line_idx, new_end_col_idx),
-        });
-        syntax.update(self.contents.slice(..));
+        let first = self.contents.char_to_line(cursor.start);
+        let removed = self.contents.char_to_line(cursor.end) - first;
+        let inserted = text.matches('\n').count();
+        if removed == 0 && inserted == 0 {
+            return;
+        }
+
+        let lines = self.contents.len_lines();
+        let len = self.contents.len_chars();
+        self.bookmarks = self
+            .bookmarks
+            .iter()
+            .filter_map(|&line| {
+                let start = self.contents.line_to_char(line.min(lines - 1));
+                let end = if line + 1 < lines {
+                    self.contents.line_to_char(line + 1)
+                } else {
+                    len
+                };
+
+                if start < cursor.start {
+                    // the line break of the edited line is still where it was
+                    Some(line)
+                } else if start < cursor.end && end <= cursor.end {
+                    None
+                } else {
+                    Some(line - removed + inserted)
+                }
+            })
+            .collect();
     }
 }
 
@@ -678,4 +1558,435 @@ impl Default for Buffer {
 
 //
 
+/// the most undo groups a buffer remembers
+const UNDO_LEN: usize = 1000;
+
+/// one change made with [`Buffer::replace_text_at`]
+#[derive(Debug, Clone)]
+struct Edit {
+    /// the char index where the change starts
+    at: usize,
+    /// the replaced text
+    old: String,
+    /// the text that replaced it
+    new: String,
+}
+
+/// edits collected for [`Buffer::edit`], applied together when it returns
+///
+/// ranges that share chars are an error and the whole transaction is rejected,
+/// ranges that only touch are fine: inserts at the same char end up in the order
+/// they were added in, and an insert at the start or the end of a replaced range
+/// goes before or after the new text
+#[derive(Debug, Default)]
+pub struct Transaction {
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl Transaction {
+    /// replace the chars in `range` with `text`
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.edits.push((range, text.to_string()));
+    }
+
+    /// insert `text` before the char at `at`
+    pub fn insert(&mut self, at: usize, text: &str) {
+        self.replace(at..at, text);
+    }
+
+    /// delete the chars in `range`
+    pub fn delete(&mut self, range: Range<usize>) {
+        self.replace(range, "");
+    }
+}
+
+/// when a file was last modified and its size, to notice other programs changing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl DiskStamp {
+    fn new(meta: &fs::Metadata) -> Self {
+        Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        }
+    }
+
+    fn of(file: &fs::File) -> Result<Self> {
+        Ok(Self::new(&file.metadata()?))
+    }
+}
+
+//
+
+/// edits undone and redone at once
+#[derive(Debug)]
+struct UndoGroup {
+    edits: Vec<Edit>,
+    /// when the last edit joined the group
+    time: Instant,
+    /// the first changed char, undo puts the cursor here
+    start: usize,
+    /// the end of the change in the text after it, redo puts the cursor before it
+    end: usize,
+}
+
+impl UndoGroup {
+    fn new(edits: Vec<Edit>, time: Instant) -> Self {
+        let mut group = Self {
+            edits: Vec::with_capacity(edits.len()),
+            time,
+            start: usize::MAX,
+            end: 0,
+        };
+        for edit in edits {
+            group.push(edit);
+        }
+        group
+    }
+
+    /// add `edit`, made after the edits already in the group
+    fn push(&mut self, edit: Edit) {
+        self.track(&edit);
+        self.edits.push(edit);
+    }
+
+    /// move the change bounds over `edit`
+    fn track(&mut self, edit: &Edit) {
+        let old_end = edit.at + edit.old.chars().count();
+        let new_end = edit.at + edit.new.chars().count();
+        self.start = self.start.min(edit.at);
+        // the earlier changes move with the text before them
+        if old_end <= self.end {
+            self.end = self.end - old_end + new_end;
+        }
+        self.end = self.end.max(new_end);
+    }
+}
+
+/// undo and redo stacks of edit groups, each group is undone at once
+#[derive(Debug, Default)]
+struct History {
+    undo: Vec<UndoGroup>,
+    redo: Vec<UndoGroup>,
+    /// the last group is typed characters, and more typing can join it
+    typing: bool,
+}
+
+impl History {
+    /// record one edit made at `now`, typed characters join the last group
+    /// unless typing paused for longer than `timeout`, a zero `timeout` never splits them
+    fn record(&mut self, edit: Edit, now: Instant, timeout: Duration) {
+        self.redo.clear();
+
+        let is_typed = edit.old.is_empty() && edit.new.chars().count() == 1;
+        let joins = is_typed && self.typing;
+        let last = self.undo.last_mut().filter(|group| {
+            joins && (timeout.is_zero() || now.saturating_duration_since(group.time) <= timeout)
+        });
+        if let Some(last) = last {
+            last.time = now;
+            last.track(&edit);
+            match last.edits.last_mut() {
+                Some(prev)
+                    if prev.old.is_empty() && prev.at + prev.new.chars().count() == edit.at =>
+                {
+                    prev.new.push_str(&edit.new);
+                }
+                // typing somewhere else without leaving insert mode
+                _ => last.edits.push(edit),
+            }
+            return;
+        }
+
+        self.push(UndoGroup::new(vec![edit], now));
+        self.typing = is_typed;
+    }
+
+    /// record edits undone together, like the ones of a [`Transaction`]
+    fn record_group(&mut self, edits: Vec<Edit>, now: Instant) {
+        self.redo.clear();
+        self.push(UndoGroup::new(edits, now));
+        self.typing = false;
+    }
+
+    fn push(&mut self, group: UndoGroup) {
+        self.undo.push(group);
+        if self.undo.len() > UNDO_LEN {
+            self.undo.remove(0);
+        }
+    }
+}
+
+//
+
 pub static CONN_POOL: LazyLock<ConnectionPool> = LazyLock::new(ConnectionPool::new);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNDO_TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn buffer(text: &str) -> Buffer {
+        let mut buf = Buffer::new();
+        buf.contents = Rope::from_str(text);
+        buf
+    }
+
+    /// type `text` at the end one char at a time, like insert mode at time `now`
+    fn type_chars(buf: &mut Buffer, text: &str, now: Instant, timeout: Duration) {
+        for ch in text.chars() {
+            let at = buf.contents.len_chars();
+            let new = ch.to_string();
+            buf.apply_edit(at..at, &new);
+            let old = String::new();
+            buf.history.record(Edit { at, old, new }, now, timeout);
+        }
+    }
+
+    #[test]
+    fn edit_replaces_ranges_of_the_text_before_the_transaction() {
+        let mut buf = buffer("one two three four");
+        buf.edit(|tx| {
+            tx.replace(0..3, "1");
+            tx.replace(4..7, "twenty two");
+            tx.delete(8..14);
+            tx.insert(18, "!");
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "1 twenty two four!");
+    }
+
+    #[test]
+    fn a_transaction_is_undone_in_one_step() {
+        let mut buf = buffer("a b c");
+        buf.edit(|tx| {
+            tx.replace(0..1, "x");
+            tx.replace(2..3, "y");
+            tx.replace(4..5, "z");
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "x y z");
+
+        buf.undo();
+        assert_eq!(buf.text(), "a b c");
+        buf.redo();
+        assert_eq!(buf.text(), "x y z");
+    }
+
+    #[test]
+    fn edits_apply_in_text_order_whatever_order_they_were_added_in() {
+        let mut buf = buffer("abc");
+        buf.edit(|tx| {
+            tx.replace(2..3, "C");
+            tx.replace(0..1, "A");
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "AbC");
+    }
+
+    #[test]
+    fn inserts_at_the_same_char_keep_their_order() {
+        let mut buf = buffer("ab");
+        buf.edit(|tx| {
+            tx.insert(1, "1");
+            tx.insert(1, "2");
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "a12b");
+    }
+
+    #[test]
+    fn touching_ranges_are_not_overlapping() {
+        let mut buf = buffer("abcd");
+        buf.edit(|tx| {
+            tx.replace(0..2, "x");
+            tx.replace(2..4, "y");
+            tx.insert(2, "-");
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "x-y");
+    }
+
+    #[test]
+    fn overlapping_ranges_reject_the_whole_transaction() {
+        let mut buf = buffer("abcd");
+        let result = buf.edit(|tx| {
+            tx.replace(0..1, "x");
+            tx.replace(1..3, "y");
+            tx.replace(2..4, "z");
+        });
+        assert!(result.is_err());
+        assert_eq!(buf.text(), "abcd");
+        assert_eq!(buf.undo(), None);
+    }
+
+    #[test]
+    fn an_out_of_bounds_range_rejects_the_whole_transaction() {
+        let mut buf = buffer("ab");
+        let result = buf.edit(|tx| {
+            tx.replace(0..1, "x");
+            tx.insert(3, "y");
+        });
+        assert!(result.is_err());
+        assert_eq!(buf.text(), "ab");
+    }
+
+    #[test]
+    fn deleting_a_line_drops_its_bookmark_and_moves_the_next_up() {
+        let mut buf = buffer("0\n1\n2\n3\n");
+        buf.bookmarks = BTreeSet::from([1, 2]);
+        // `dd` on line 1
+        buf.delete(2..4).unwrap();
+        assert_eq!(buf.text(), "0\n2\n3\n");
+        assert_eq!(buf.bookmarks, BTreeSet::from([1]));
+    }
+
+    #[test]
+    fn pasting_lines_above_moves_the_bookmark_down() {
+        let mut buf = buffer("0\n1\n2\n");
+        buf.bookmarks = BTreeSet::from([0, 1]);
+        // linewise `P` on line 1
+        buf.insert(2, "x\ny\n").unwrap();
+        assert_eq!(buf.text(), "0\nx\ny\n1\n2\n");
+        assert_eq!(buf.bookmarks, BTreeSet::from([0, 3]));
+    }
+
+    #[test]
+    fn joining_lines_moves_the_bookmarks_below_up() {
+        let mut buf = buffer("0\n1\n2\n");
+        buf.bookmarks = BTreeSet::from([0, 2]);
+        buf.delete(1..2).unwrap();
+        assert_eq!(buf.text(), "01\n2\n");
+        assert_eq!(buf.bookmarks, BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn deleting_the_last_line_drops_its_bookmark() {
+        let mut buf = buffer("0\n1");
+        buf.bookmarks = BTreeSet::from([0, 1]);
+        // `dd` on the last line takes the line break before it
+        buf.delete(1..3).unwrap();
+        assert_eq!(buf.text(), "0");
+        assert_eq!(buf.bookmarks, BTreeSet::from([0]));
+    }
+
+    #[test]
+    fn typing_after_a_pause_is_undone_separately() {
+        let mut buf = buffer("");
+        let start = Instant::now();
+        type_chars(&mut buf, "hello", start, UNDO_TIMEOUT);
+        type_chars(&mut buf, " world", start + 2 * UNDO_TIMEOUT, UNDO_TIMEOUT);
+
+        buf.undo();
+        assert_eq!(buf.text(), "hello");
+        buf.undo();
+        assert_eq!(buf.text(), "");
+    }
+
+    #[test]
+    fn fast_typing_is_undone_at_once() {
+        let mut buf = buffer("");
+        let start = Instant::now();
+        type_chars(&mut buf, "hello", start, UNDO_TIMEOUT);
+        type_chars(&mut buf, " world", start + UNDO_TIMEOUT / 2, UNDO_TIMEOUT);
+
+        buf.undo();
+        assert_eq!(buf.text(), "");
+    }
+
+    #[test]
+    fn zero_undo_timeout_never_splits_typing() {
+        let mut buf = buffer("");
+        let start = Instant::now();
+        type_chars(&mut buf, "hello", start, Duration::ZERO);
+        type_chars(
+            &mut buf,
+            " world",
+            start + Duration::from_secs(60),
+            Duration::ZERO,
+        );
+
+        buf.undo();
+        assert_eq!(buf.text(), "");
+    }
+
+    #[test]
+    fn leaving_insert_mode_splits_typing() {
+        let mut buf = buffer("");
+        let now = Instant::now();
+        type_chars(&mut buf, "a", now, UNDO_TIMEOUT);
+        buf.end_undo_group();
+        type_chars(&mut buf, "b", now, UNDO_TIMEOUT);
+
+        buf.undo();
+        assert_eq!(buf.text(), "a");
+    }
+
+    #[test]
+    fn undo_a_deletion_restores_the_cursor_at_its_start() {
+        let mut buf = buffer("one\ntwo\nthree\n");
+        buf.delete(4..8).unwrap();
+
+        assert_eq!(buf.undo(), Some(4));
+        assert_eq!(buf.text(), "one\ntwo\nthree\n");
+        assert_eq!(buf.redo(), Some(4));
+        assert_eq!(buf.text(), "one\nthree\n");
+    }
+
+    #[test]
+    fn undo_a_multi_line_insert_restores_the_cursor_at_its_start() {
+        let mut buf = buffer("one\nthree\n");
+        buf.insert(4, "two\n2\n").unwrap();
+
+        assert_eq!(buf.undo(), Some(4));
+        assert_eq!(buf.text(), "one\nthree\n");
+        // on the last inserted char
+        assert_eq!(buf.redo(), Some(9));
+        assert_eq!(buf.text(), "one\ntwo\n2\nthree\n");
+    }
+
+    #[test]
+    fn undo_a_substitute_restores_the_cursor_at_the_first_match() {
+        let mut buf = buffer("foo boo\n");
+        buf.edit(|tx| {
+            for range in [1..2, 2..3, 5..6, 6..7] {
+                tx.replace(range, "0");
+            }
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "f00 b00\n");
+
+        assert_eq!(buf.undo(), Some(1));
+        assert_eq!(buf.text(), "foo boo\n");
+        // on the last replaced char
+        assert_eq!(buf.redo(), Some(6));
+        assert_eq!(buf.text(), "f00 b00\n");
+    }
+
+    #[test]
+    fn undo_with_nothing_to_undo() {
+        let mut buf = buffer("text");
+        assert_eq!(buf.undo(), None);
+        assert_eq!(buf.redo(), None);
+    }
+
+    #[test]
+    fn huge_buffers_keep_no_undo_history() {
+        let mut buf = buffer("one\ntwo\n");
+        buf.huge = true;
+        buf.replace(0..3, "1").unwrap();
+        buf.edit(|tx| {
+            tx.insert(0, "0");
+            tx.delete(1..2);
+        })
+        .unwrap();
+        assert_eq!(buf.text(), "01two\n");
+        assert_eq!(buf.undo(), None);
+        assert_eq!(buf.text(), "01two\n");
+    }
+}
@@ -1,14 +1,24 @@
-use std::{env, path::PathBuf, sync::Arc};
+use std::{cmp::Reverse, env, mem, ops::Range, path::PathBuf, sync::Arc, thread};
 
 use crossterm::event::{KeyCode, KeyModifiers};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ratatui::layout::Rect;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    buffer::{Buffer, BufferInner, CONN_POOL},
+    buffer::{Buffer, BufferInner, FileFormat, CONN_POOL, SNAPSHOT_SUFFIX},
     editor::{
-        keymap::{Code, Entry, Layer},
+        diff::DiffView,
+        keymap::{Code, Entry, Layer, Motion},
         popup::Popup,
+        registers::{LastPaste, Register, Registers},
+        search::{self, SearchOrigin, Substitution},
+        split::Direction,
+        view::{visual_range, BufferView},
+        BufferViewMut,
     },
     mode::Mode,
+    tramp::Part,
 };
 
 use super::{
@@ -21,6 +31,7 @@ use super::{
 pub fn all_actions() -> impl IntoIterator<Item = Arc<dyn Action>> {
     [
         Escape::arc(),
+        Noop::arc(),
         //
         MoveLeft::arc(),
         MoveRight::arc(),
@@ -29,41 +40,99 @@ pub fn all_actions() -> impl IntoIterator<Item = Arc<dyn Action>> {
         MovePageUp::arc(),
         MovePageDown::arc(),
         MoveLineBeg::arc(),
+        MoveLineHome::arc(),
         MoveLineEnd::arc(),
         MoveBufferBeg::arc(),
         MoveBufferEnd::arc(),
+        MatchBracket::arc(),
         //
         NextWordBeg::arc(),
         NextWordEnd::arc(),
         PrevWordBeg::arc(),
         //
         SwitchToInsert::arc(),
+        SwitchToInsertLast::arc(),
         SwitchToInsertLineBeg::arc(),
         SwitchToAppend::arc(),
         SwitchToAppendLineEnd::arc(),
+        SwitchToReplace::arc(),
+        ToggleReplace::arc(),
+        SwitchToVisual::arc(),
+        SwitchToVisualLine::arc(),
+        ReselectVisual::arc(),
         SwitchToCommand::arc(),
+        CommandPalette::arc(),
+        Search::arc(),
+        SearchNext::arc(),
+        SearchPrev::arc(),
+        Substitute::arc(),
+        SubstituteAll::arc(),
         InsertLineAbove::arc(),
         InsertLineBelow::arc(),
         //
         Delete::arc(),
+        ChangeLineEnd::arc(),
+        ChangeSelection::arc(),
         Backspace::arc(),
+        Undo::arc(),
+        Redo::arc(),
+        Repeat::arc(),
+        Yank::arc(),
+        YankLine::arc(),
+        IndentSelection::arc(),
+        DedentSelection::arc(),
+        DuplicateLine::arc(),
+        MoveLinesDown::arc(),
+        MoveLinesUp::arc(),
+        PasteAfter::arc(),
+        PasteBefore::arc(),
+        CyclePaste::arc(),
         //
         Quit::arc(),
         QuitForce::arc(),
         Write::arc(),
+        WriteForce::arc(),
+        Reload::arc(),
+        ReloadForce::arc(),
         WriteQuit::arc(),
         WriteQuitForce::arc(),
         //
         ClearLog::arc(),
         RefreshSuggestions::arc(),
         NextSuggestion::arc(),
+        HistoryPrev::arc(),
+        HistoryNext::arc(),
         PrevSuggestion::arc(),
         //
         New::arc(),
         Open::arc(),
+        DiffSplit::arc(),
+        DiffOff::arc(),
+        NextHunk::arc(),
+        PrevHunk::arc(),
+        ToggleBookmark::arc(),
+        NextBookmark::arc(),
+        PrevBookmark::arc(),
+        Set::arc(),
+        Dos2Unix::arc(),
+        Unix2Dos::arc(),
+        Retab::arc(),
+        RetabBang::arc(),
+        FileInfo::arc(),
+        ShowRegisters::arc(),
+        ShowRegistersShort::arc(),
+        Verbose::arc(),
+        ActionLog::arc(),
         BufferClose::arc(),
         BufferNext::arc(),
         BufferPrev::arc(),
+        SplitHorizontal::arc(),
+        SplitVertical::arc(),
+        SplitClose::arc(),
+        FocusLeft::arc(),
+        FocusDown::arc(),
+        FocusUp::arc(),
+        FocusRight::arc(),
         //
         FileExplorer::arc(),
         BufferPicker::arc(),
@@ -72,6 +141,9 @@ pub fn all_actions() -> impl IntoIterator<Item = Arc<dyn Action>> {
     ]
 }
 
+/// other names typed commands can use, the palette lists each action once by its own name
+pub const COMMAND_ALIASES: &[(&str, &str)] = &[("entab", "retab!")];
+
 //
 
 #[derive(Debug, Default)]
@@ -87,15 +159,62 @@ impl Action for Escape {
     }
 
     fn run(&self, editor: &mut Editor) {
-        if let Mode::Insert { append: true } = editor.mode {
+        if editor.mode.is_insert() {
+            let cur = editor.current_mut();
+            cur.buffer.last_insert = Some(cur.view.cursor);
+        }
+        // a cancelled search goes back to where it started
+        if let Some(origin) = editor.search_origin.take() {
+            editor.view.cursor = origin.cursor;
+            editor.view.view_line = origin.view_line;
+            editor.search = origin.search;
+            editor.status.clear();
+        }
+        if let Mode::Insert { append: true, .. } = editor.mode {
+            // back on the last typed char, but not onto the line above
             let cur = editor.current_mut();
-            cur.view.cursor = cur.view.cursor.saturating_sub(1);
+            let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+            let line_start = cur.buffer.contents.line_to_char(line);
+            cur.view.cursor = cur.buffer.prev_grapheme(cur.view.cursor).max(line_start);
         }
+        // the next insert session is undone separately
+        editor.current_mut().buffer.end_undo_group();
         editor.mode = Mode::Normal;
         editor.command.clear();
         editor.command_suggestions.clear();
         editor.command_suggestion_index = None;
+        editor.command_palette = false;
+        editor.last_paste = None;
+        editor.count = None;
+    }
+}
+
+/// the count typed before the action, 1 if there was none,
+/// times the count typed before the operator running it, like `2d3w` deletes 6 words
+fn take_count(editor: &mut Editor) -> usize {
+    let count = editor.count.take().unwrap_or(1);
+    let operator_count = editor.operator_count.take().unwrap_or(1);
+    // cursor moves are signed
+    count
+        .saturating_mul(operator_count)
+        .min(isize::MAX as usize)
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Noop;
+
+impl Action for Noop {
+    fn name(&self) -> &str {
+        "nop"
+    }
+
+    fn description(&self) -> &str {
+        "do nothing, used to disable keys"
     }
+
+    fn run(&self, _: &mut Editor) {}
 }
 
 //
@@ -113,7 +232,12 @@ impl Action for MoveLeft {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.current_mut().jump_cursor(-1, 0);
+        let count = take_count(editor) as isize;
+        editor.current_mut().jump_graphemes(-count);
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
     }
 }
 
@@ -132,7 +256,12 @@ impl Action for MoveRight {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.current_mut().jump_cursor(1, 0);
+        let count = take_count(editor) as isize;
+        editor.current_mut().jump_graphemes(count);
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
     }
 }
 
@@ -151,7 +280,12 @@ impl Action for MoveUp {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.current_mut().jump_cursor(0, -1);
+        let count = take_count(editor) as isize;
+        editor.current_mut().jump_cursor(0, -count);
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
     }
 }
 
@@ -170,7 +304,12 @@ impl Action for MoveDown {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.current_mut().jump_cursor(0, 1);
+        let count = take_count(editor) as isize;
+        editor.current_mut().jump_cursor(0, count);
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
     }
 }
 
@@ -190,7 +329,14 @@ impl Action for MovePageUp {
 
     fn run(&self, editor: &mut Editor) {
         let size = editor.size;
-        editor.current_mut().jump_cursor(0, -(size.1 as isize - 1))
+        let count = take_count(editor) as isize;
+        editor
+            .current_mut()
+            .jump_cursor(0, (size.1 as isize - 1).saturating_mul(-count))
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
     }
 }
 
@@ -210,7 +356,14 @@ impl Action for MovePageDown {
 
     fn run(&self, editor: &mut Editor) {
         let size = editor.size;
-        editor.current_mut().jump_cursor(0, size.1 as isize - 3)
+        let count = take_count(editor) as isize;
+        editor
+            .current_mut()
+            .jump_cursor(0, (size.1 as isize - 3).saturating_mul(count))
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
     }
 }
 
@@ -231,6 +384,46 @@ impl Action for MoveLineBeg {
     fn run(&self, editor: &mut Editor) {
         editor.current_mut().jump_line_beg()
     }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct MoveLineHome;
+
+impl Action for MoveLineHome {
+    fn name(&self) -> &str {
+        "move-line-home"
+    }
+
+    fn description(&self) -> &str {
+        "move to the first non-blank char, or the line beginning if already there"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let smarthome = editor.options.smarthome;
+        let mut cur = editor.current_mut();
+        let cursor = cur.view.cursor;
+        cur.jump_line_beg();
+        if !smarthome {
+            return;
+        }
+
+        let line_start = cur.view.cursor;
+        let first_non_blank =
+            line_start + cur.count_matching(line_start, |ch| ch == ' ' || ch == '\t');
+        if cursor != first_non_blank {
+            cur.view.cursor = first_non_blank;
+        }
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
+    }
 }
 
 //
@@ -248,7 +441,16 @@ impl Action for MoveLineEnd {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.current_mut().jump_line_end()
+        // insert mode types after the last char, not before it
+        if editor.mode.is_insert() {
+            editor.current_mut().jump_line_append()
+        } else {
+            editor.current_mut().jump_line_end()
+        }
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Inclusive)
     }
 }
 
@@ -269,6 +471,10 @@ impl Action for MoveBufferBeg {
     fn run(&self, editor: &mut Editor) {
         editor.current_mut().jump_beg();
     }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
+    }
 }
 
 //
@@ -288,6 +494,37 @@ impl Action for MoveBufferEnd {
     fn run(&self, editor: &mut Editor) {
         editor.current_mut().jump_end();
     }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Linewise)
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct MatchBracket;
+
+impl Action for MatchBracket {
+    fn name(&self) -> &str {
+        "match-bracket"
+    }
+
+    fn description(&self) -> &str {
+        "move to the matching bracket, from the next bracket on the line"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let cur = editor.current_mut();
+        if let Some(at) = cur.view.match_bracket(cur.buffer, cur.view.cursor) {
+            cur.view.cursor = at;
+            cur.view.cursor_x_unclamp = 0;
+        }
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Inclusive)
+    }
 }
 
 //
@@ -305,15 +542,16 @@ impl Action for NextWordBeg {
     }
 
     fn run(&self, editor: &mut Editor) {
+        let count = take_count(editor);
         let cur = editor.current_mut();
 
-        if cur.view.cursor + 1 >= cur.buffer.contents.len_chars() {
-            return;
+        for _ in 0..count {
+            cur.view.cursor = cur.next_word_beg(cur.view.cursor);
         }
+    }
 
-        cur.view.cursor += 1;
-        cur.view.cursor += cur.find_boundary(cur.view.cursor);
-        cur.view.cursor += cur.count_matching(cur.view.cursor + 1, |ch| ch.is_whitespace());
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
     }
 }
 
@@ -328,18 +566,20 @@ impl Action for NextWordEnd {
     }
 
     fn description(&self) -> &str {
-        "move to the start of next word"
+        "move to the end of next word"
     }
 
     fn run(&self, editor: &mut Editor) {
+        let count = take_count(editor);
         let cur = editor.current_mut();
 
-        if cur.view.cursor + 1 >= cur.buffer.contents.len_chars() {
-            return;
+        for _ in 0..count {
+            cur.view.cursor = cur.next_word_end(cur.view.cursor);
         }
+    }
 
-        cur.view.cursor += 1;
-        cur.view.cursor += cur.find_boundary(cur.view.cursor);
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Inclusive)
     }
 }
 
@@ -358,14 +598,16 @@ impl Action for PrevWordBeg {
     }
 
     fn run(&self, editor: &mut Editor) {
+        let count = take_count(editor);
         let cur = editor.current_mut();
 
-        if cur.view.cursor == 0 {
-            return;
+        for _ in 0..count {
+            cur.view.cursor = cur.prev_word_beg(cur.view.cursor);
         }
+    }
 
-        cur.view.cursor -= 1;
-        cur.view.cursor -= cur.rfind_boundary(cur.view.cursor);
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
     }
 }
 
@@ -384,7 +626,10 @@ impl Action for SwitchToInsert {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: false };
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
     }
 }
 
@@ -403,13 +648,44 @@ impl Action for SwitchToInsertLineBeg {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: false };
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
         editor.current_mut().jump_line_beg();
     }
 }
 
 //
 
+#[derive(Debug, Default)]
+pub struct SwitchToInsertLast;
+
+impl Action for SwitchToInsertLast {
+    fn name(&self) -> &str {
+        "switch-to-insert-last"
+    }
+
+    fn description(&self) -> &str {
+        "switch to insert mode where it was last left"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
+        let cur = editor.current_mut();
+        if let Some(at) = cur.buffer.last_insert {
+            // later edits can leave it past the end
+            cur.view.cursor = at.min(cur.buffer.contents.len_chars());
+            cur.view.cursor_x_unclamp = 0;
+        }
+    }
+}
+
+//
+
 #[derive(Debug, Default)]
 pub struct SwitchToAppend;
 
@@ -423,8 +699,11 @@ impl Action for SwitchToAppend {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: true };
-        editor.current_mut().jump_cursor(1, 0);
+        editor.mode = Mode::Insert {
+            append: true,
+            replace: false,
+        };
+        editor.current_mut().jump_append();
     }
 }
 
@@ -443,303 +722,2750 @@ impl Action for SwitchToAppendLineEnd {
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: true };
-        editor.current_mut().jump_line_end();
+        editor.mode = Mode::Insert {
+            append: true,
+            replace: false,
+        };
+        editor.current_mut().jump_line_append();
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct SwitchToCommand;
+pub struct SwitchToReplace;
 
-impl Action for SwitchToCommand {
+impl Action for SwitchToReplace {
     fn name(&self) -> &str {
-        "switch-to-command"
+        "switch-to-replace"
     }
 
     fn description(&self) -> &str {
-        "switch to command mode"
+        "switch to replace mode, typing overwrites characters"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Command;
-        editor.command.clear();
-        editor.command.push(':');
-        RefreshSuggestions.run(editor);
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: true,
+        };
+        editor.replaced.clear();
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct InsertLineBelow;
+pub struct ToggleReplace;
 
-impl Action for InsertLineBelow {
+impl Action for ToggleReplace {
     fn name(&self) -> &str {
-        "insert-line-below"
+        "toggle-replace"
     }
 
     fn description(&self) -> &str {
-        "insert a line below and switch to insert mode"
+        "toggle between insert and replace mode"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: true };
-        let mut cur = editor.current_mut();
-        cur.jump_line_end();
-        cur.jump_cursor(1, 0);
-        cur.buffer.insert_text_at(cur.view.cursor, "\n");
-        cur.jump_cursor(1, 0);
+        if let Mode::Insert { replace, .. } = &mut editor.mode {
+            *replace = !*replace;
+        }
+        editor.replaced.clear();
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct InsertLineAbove;
+pub struct SwitchToVisual;
 
-impl Action for InsertLineAbove {
+impl Action for SwitchToVisual {
     fn name(&self) -> &str {
-        "insert-line-above"
+        "switch-to-visual"
     }
 
     fn description(&self) -> &str {
-        "insert a line above and switch to insert mode"
+        "start selecting text, or stop if already selecting"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.mode = Mode::Insert { append: true };
-        let mut cur = editor.current_mut();
-        cur.jump_line_beg();
-        cur.buffer.insert_text_at(cur.view.cursor, "\n");
+        switch_visual(editor, false);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct JumpForwardsTo;
+pub struct SwitchToVisualLine;
 
-impl Layer for JumpForwardsTo {
+impl Action for SwitchToVisualLine {
     fn name(&self) -> &str {
-        "jump-forwards-to"
+        "switch-to-visual-line"
     }
 
     fn description(&self) -> &str {
-        "jump forwards to the next matching character"
+        "start selecting whole lines, or stop if already selecting lines"
     }
 
-    fn get(&self, _: Code) -> Option<Entry> {
-        None
+    fn run(&self, editor: &mut Editor) {
+        switch_visual(editor, true);
     }
+}
 
-    fn entries(&self) -> Arc<[(Code, Entry)]> {
-        <_>::default()
+#[derive(Debug, Default)]
+pub struct ReselectVisual;
+
+impl Action for ReselectVisual {
+    fn name(&self) -> &str {
+        "reselect-visual"
     }
 
-    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
-        let KeyCode::Char(ch) = keycode.keycode else {
-            return false;
+    fn description(&self) -> &str {
+        "select the last visual selection again"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let Some(last) = editor
+            .last_visual
+            .filter(|last| last.buffer_index == editor.view.buffer_index)
+        else {
+            editor.status.clear();
+            editor.status.push_str("no previous selection");
+            editor.status_is_error = true;
+            return;
         };
 
+        // the buffer might have been edited since
         let cur = editor.current_mut();
-        cur.view.cursor += cur
-            .find(cur.view.cursor + 1, |cur_ch| cur_ch == ch)
-            .map_or(0, |n| n + 1);
-        editor.mode = Mode::Normal;
-        true
+        let len = cur.buffer.contents.len_chars();
+        cur.view.cursor = last.cursor.min(len);
+        cur.view.cursor_x_unclamp = 0;
+        editor.mode = Mode::Visual {
+            anchor: last.anchor.min(len),
+            linewise: last.linewise,
+        };
     }
 }
 
+/// start selecting, switch between selecting chars and lines, or stop selecting
+fn switch_visual(editor: &mut Editor, linewise: bool) {
+    editor.mode = match editor.mode {
+        Mode::Visual {
+            linewise: current, ..
+        } if current == linewise => Mode::Normal,
+        Mode::Visual { anchor, .. } => Mode::Visual { anchor, linewise },
+        _ => Mode::Visual {
+            anchor: editor.current().view.cursor,
+            linewise,
+        },
+    };
+}
+
+/// the chars between the visual mode anchor and the cursor, both included,
+/// or their whole lines in visual line mode
+fn visual_selection(editor: &Editor) -> Option<Range<usize>> {
+    let Mode::Visual { anchor, linewise } = editor.mode else {
+        return None;
+    };
+    let cur = editor.current();
+    Some(visual_range(cur.buffer, anchor, cur.view.cursor, linewise))
+}
+
+/// the first and the last selected line in visual line mode
+fn visual_lines(editor: &Editor) -> Option<(usize, usize)> {
+    let Mode::Visual { linewise: true, .. } = editor.mode else {
+        return None;
+    };
+    let selection = visual_selection(editor)?;
+    let contents = &editor.current().buffer.contents;
+    let first = contents.char_to_line(selection.start);
+    let last = contents.char_to_line(selection.end.saturating_sub(1).max(selection.start));
+    Some((first, last))
+}
+
 //
 
 #[derive(Debug, Default)]
-pub struct JumpForwardsUntil;
+pub struct SwitchToCommand;
 
-impl Layer for JumpForwardsUntil {
+impl Action for SwitchToCommand {
     fn name(&self) -> &str {
-        "jump-forwards-until"
+        "switch-to-command"
     }
 
     fn description(&self) -> &str {
-        "jump forwards until the next character is matching"
+        "switch to command mode"
     }
 
-    fn get(&self, _: Code) -> Option<Entry> {
-        None
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Command;
+        editor.command.clear();
+        editor.command.push(':');
+        editor.command_palette = false;
+        editor.command_history_index = None;
+        RefreshSuggestions.run(editor);
     }
+}
 
-    fn entries(&self) -> Arc<[(Code, Entry)]> {
-        <_>::default()
-    }
+//
 
-    fn wildcard(&self) -> Option<&dyn Layer> {
-        Some(self)
+#[derive(Debug, Default)]
+pub struct CommandPalette;
+
+impl Action for CommandPalette {
+    fn name(&self) -> &str {
+        "command-palette"
     }
 
-    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
-        let KeyCode::Char(ch) = keycode.keycode else {
-            return false;
-        };
+    fn description(&self) -> &str {
+        "search actions by name or description, and run one"
+    }
 
-        let cur = editor.current_mut();
-        cur.view.cursor += cur
-            .find(cur.view.cursor + 2, |cur_ch| cur_ch == ch)
-            .map_or(0, |n| n + 1);
-        editor.mode = Mode::Normal;
-        true
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Command;
+        editor.command.clear();
+        editor.command_palette = true;
+        RefreshSuggestions.run(editor);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct JumpBackwardsTo;
+pub struct Search;
 
-impl Layer for JumpBackwardsTo {
+impl Action for Search {
     fn name(&self) -> &str {
-        "jump-backwards-to"
+        "search"
     }
 
     fn description(&self) -> &str {
-        "jump backwards to the next matching character"
+        "search forwards for the typed text"
     }
 
-    fn get(&self, _: Code) -> Option<Entry> {
-        None
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Command;
+        editor.command.clear();
+        editor.command.push('/');
+        editor.command_palette = false;
+        editor.command_suggestions.clear();
+        editor.command_suggestion_index = None;
+        editor.search_origin = Some(SearchOrigin {
+            cursor: editor.view.cursor,
+            view_line: editor.view.view_line,
+            search: editor.search.clone(),
+        });
     }
+}
 
-    fn entries(&self) -> Arc<[(Code, Entry)]> {
-        <_>::default()
-    }
+//
 
-    fn wildcard(&self) -> Option<&dyn Layer> {
-        Some(self)
+#[derive(Debug, Default)]
+pub struct SearchNext;
+
+impl Action for SearchNext {
+    fn name(&self) -> &str {
+        "search-next"
     }
 
-    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
-        let KeyCode::Char(ch) = keycode.keycode else {
-            return false;
-        };
+    fn description(&self) -> &str {
+        "jump to the next search match"
+    }
 
-        let cur = editor.current_mut();
+    fn run(&self, editor: &mut Editor) {
+        jump_to_match(editor, true);
+    }
 
-        if cur.view.cursor == 0 {
-            editor.mode = Mode::Normal;
-            return false;
-        }
-        cur.view.cursor -= cur
-            .rfind(cur.view.cursor - 1, |cur_ch| cur_ch == ch)
-            .map_or(0, |n| n + 1);
-        editor.mode = Mode::Normal;
-        true
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct JumpBackwardsUntil;
+pub struct SearchPrev;
 
-impl Layer for JumpBackwardsUntil {
+impl Action for SearchPrev {
     fn name(&self) -> &str {
-        "jump-backwards-until"
+        "search-prev"
     }
 
     fn description(&self) -> &str {
-        "jump backwards until the next character is matching"
+        "jump to the previous search match"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        jump_to_match(editor, false);
+    }
+
+    fn motion(&self) -> Option<Motion> {
+        Some(Motion::Exclusive)
+    }
+}
+
+/// move the cursor to the next or previous match of the last search
+fn jump_to_match(editor: &mut Editor, forwards: bool) {
+    use std::fmt::Write;
+    editor.status.clear();
+    editor.status_is_error = false;
+
+    if editor.search.is_empty() {
+        editor.status.push_str("no previous search");
+        editor.status_is_error = true;
+        return;
+    }
+
+    let cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+    let Some((at, wrapped)) = search::find(
+        &cur.buffer.contents,
+        &editor.search,
+        cur.view.cursor,
+        forwards,
+    ) else {
+        _ = write!(&mut editor.status, "pattern not found: {}", editor.search);
+        editor.status_is_error = true;
+        return;
+    };
+
+    cur.view.cursor = at;
+    if wrapped {
+        editor.status.push_str(if forwards {
+            "search hit the end, continuing at the start"
+        } else {
+            "search hit the start, continuing at the end"
+        });
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Substitute;
+
+impl Action for Substitute {
+    fn name(&self) -> &str {
+        "s"
+    }
+
+    fn description(&self) -> &str {
+        "replace the first match on the current line, like `s/old/new/`, `g` replaces all and `c` asks"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let cur = editor.current();
+        let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+        substitute(editor, line..line + 1);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct SubstituteAll;
+
+impl Action for SubstituteAll {
+    fn name(&self) -> &str {
+        "%s"
+    }
+
+    fn description(&self) -> &str {
+        "replace the first match on every line, like `%s/old/new/`, `g` replaces all and `c` asks"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let lines = editor.current().buffer.contents.len_lines();
+        substitute(editor, 0..lines);
+    }
+}
+
+/// run the `:s/old/new/flags` on the command line for `lines`
+fn substitute(editor: &mut Editor, lines: Range<usize>) {
+    use std::fmt::Write;
+    editor.status.clear();
+    editor.status_is_error = true;
+
+    let command = editor.command.trim_start_matches(':');
+    let args = command.strip_prefix('%').unwrap_or(command);
+    let args = args.strip_prefix('s').unwrap_or(args);
+    let mut sub = match Substitution::parse(args) {
+        Ok(sub) => sub,
+        Err(err) => {
+            _ = write!(&mut editor.status, "{err}");
+            return;
+        }
+    };
+
+    // an empty pattern repeats the last search, the new one is highlighted like a search
+    if sub.pattern.is_empty() {
+        if editor.search.is_empty() {
+            editor.status.push_str("no previous search");
+            return;
+        }
+        sub.pattern.clone_from(&editor.search);
+    } else {
+        editor.search.clone_from(&sub.pattern);
+    }
+
+    let cur = editor.current();
+    if cur.buffer.is_readonly() {
+        editor.status.push_str("readonly");
+        return;
+    }
+    let contents = &cur.buffer.contents;
+    let from = contents.line_to_char(lines.start);
+    if search::next_match(contents, &sub.pattern, from, lines.clone()).is_none() {
+        _ = write!(&mut editor.status, "pattern not found: {}", sub.pattern);
+        return;
+    }
+
+    editor.status_is_error = false;
+    editor.popup = editor.substitute(sub, lines, from, 0);
+}
+
+/// jump to the first match while the search is typed, before enter is pressed
+fn incremental_search(editor: &mut Editor) {
+    if !editor.is_search_prompt() {
+        return;
+    }
+    let Some(origin) = &editor.search_origin else {
+        return;
+    };
+
+    editor.view.cursor = origin.cursor;
+    editor.view.view_line = origin.view_line;
+    if editor.command.len() > 1 {
+        editor.search.clear();
+        editor.search.push_str(&editor.command[1..]);
+        jump_to_match(editor, true);
+    } else {
+        // nothing typed yet, enter would repeat the last search
+        editor.search.clone_from(&origin.search);
+        editor.status.clear();
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct InsertLineBelow;
+
+impl Action for InsertLineBelow {
+    fn name(&self) -> &str {
+        "insert-line-below"
+    }
+
+    fn description(&self) -> &str {
+        "insert a line below and switch to insert mode"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Insert {
+            append: true,
+            replace: false,
+        };
+        let mut cur = editor.current_mut();
+        cur.jump_line_append();
+        cur.buffer.insert_text_at(cur.view.cursor, "\n");
+        cur.jump_cursor(1, 0);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct InsertLineAbove;
+
+impl Action for InsertLineAbove {
+    fn name(&self) -> &str {
+        "insert-line-above"
+    }
+
+    fn description(&self) -> &str {
+        "insert a line above and switch to insert mode"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.mode = Mode::Insert {
+            append: true,
+            replace: false,
+        };
+        let mut cur = editor.current_mut();
+        cur.jump_line_beg();
+        cur.buffer.insert_text_at(cur.view.cursor, "\n");
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct JumpForwardsTo;
+
+impl Layer for JumpForwardsTo {
+    fn name(&self) -> &str {
+        "jump-forwards-to"
+    }
+
+    fn description(&self) -> &str {
+        "jump forwards to the next matching character"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let KeyCode::Char(ch) = keycode.keycode else {
+            return false;
+        };
+
+        let cur = editor.current_mut();
+        cur.view.cursor += cur
+            .find(cur.view.cursor + 1, |cur_ch| cur_ch == ch)
+            .map_or(0, |n| n + 1);
+        editor.mode = editor.mode.prev().mode();
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct JumpForwardsUntil;
+
+impl Layer for JumpForwardsUntil {
+    fn name(&self) -> &str {
+        "jump-forwards-until"
+    }
+
+    fn description(&self) -> &str {
+        "jump forwards until the next character is matching"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn wildcard(&self) -> Option<&dyn Layer> {
+        Some(self)
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let KeyCode::Char(ch) = keycode.keycode else {
+            return false;
+        };
+
+        let cur = editor.current_mut();
+        cur.view.cursor += cur
+            .find(cur.view.cursor + 2, |cur_ch| cur_ch == ch)
+            .map_or(0, |n| n + 1);
+        editor.mode = editor.mode.prev().mode();
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct JumpBackwardsTo;
+
+impl Layer for JumpBackwardsTo {
+    fn name(&self) -> &str {
+        "jump-backwards-to"
+    }
+
+    fn description(&self) -> &str {
+        "jump backwards to the next matching character"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn wildcard(&self) -> Option<&dyn Layer> {
+        Some(self)
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let KeyCode::Char(ch) = keycode.keycode else {
+            return false;
+        };
+
+        let cur = editor.current_mut();
+
+        if cur.view.cursor == 0 {
+            editor.mode = editor.mode.prev().mode();
+            return false;
+        }
+        cur.view.cursor -= cur
+            .rfind(cur.view.cursor - 1, |cur_ch| cur_ch == ch)
+            .map_or(0, |n| n + 1);
+        editor.mode = editor.mode.prev().mode();
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct JumpBackwardsUntil;
+
+impl Layer for JumpBackwardsUntil {
+    fn name(&self) -> &str {
+        "jump-backwards-until"
+    }
+
+    fn description(&self) -> &str {
+        "jump backwards until the next character is matching"
     }
 
     fn get(&self, _: Code) -> Option<Entry> {
         None
     }
 
-    fn entries(&self) -> Arc<[(Code, Entry)]> {
-        <_>::default()
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn wildcard(&self) -> Option<&dyn Layer> {
+        Some(self)
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let KeyCode::Char(ch) = keycode.keycode else {
+            return false;
+        };
+
+        let cur = editor.current_mut();
+
+        if cur.view.cursor <= 1 {
+            editor.mode = editor.mode.prev().mode();
+            return false;
+        }
+        cur.view.cursor -= cur
+            .rfind(cur.view.cursor - 2, |cur_ch| cur_ch == ch)
+            .map_or(0, |n| n + 1);
+        editor.mode = editor.mode.prev().mode();
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Delete;
+
+impl Action for Delete {
+    fn name(&self) -> &str {
+        "delete"
+    }
+
+    fn description(&self) -> &str {
+        "delete the current selection"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if let Some((first, last)) = visual_lines(editor) {
+            delete_lines(editor, first, last);
+            editor.mode = Mode::Normal;
+            return;
+        }
+
+        if let Some(selection) = visual_selection(editor) {
+            let cur = editor.current_mut();
+            let text = cur.buffer.contents.slice(selection.clone()).to_string();
+            cur.buffer.replace_text_at(selection.clone(), "");
+            cur.view.cursor = selection
+                .start
+                .min(cur.buffer.contents.len_chars().saturating_sub(1));
+            editor.registers.delete(Register::new(text, false));
+            editor.mode = Mode::Normal;
+            return;
+        }
+
+        let is_insert = editor.mode.is_insert();
+        let cur = editor.current_mut();
+        // the whole grapheme, so an emoji and its modifier aren't split
+        let end = cur.buffer.next_grapheme(cur.view.cursor);
+        if end == cur.view.cursor {
+            return;
+        }
+        let text = cur.buffer.contents.slice(cur.view.cursor..end).to_string();
+
+        cur.buffer.replace_text_at(cur.view.cursor..end, "");
+        // the delete key in insert mode doesn't clobber the registers
+        if !is_insert {
+            editor.registers.delete(Register::new(text, false));
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DeleteMotion;
+
+impl Layer for DeleteMotion {
+    fn name(&self) -> &str {
+        "delete-motion"
+    }
+
+    fn description(&self) -> &str {
+        "delete the text a motion moves over, `d` deletes whole lines"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn is_operator(&self) -> bool {
+        true
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        editor.mode = editor.mode.prev().mode();
+
+        match operator_range(editor, keycode, const { Code::from_str("d") }) {
+            Some(OperatorRange::Chars(range)) => {
+                delete_chars(editor, range);
+                let cur = editor.current_mut();
+                let last = cur.buffer.contents.len_chars().saturating_sub(1);
+                cur.view.cursor = cur.view.cursor.min(last);
+            }
+            Some(OperatorRange::Lines(first, last)) => delete_lines(editor, first, last),
+            None => return false,
+        }
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct ChangeMotion;
+
+impl Layer for ChangeMotion {
+    fn name(&self) -> &str {
+        "change-motion"
+    }
+
+    fn description(&self) -> &str {
+        "replace the text a motion moves over, `c` replaces the line contents"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn is_operator(&self) -> bool {
+        true
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        editor.mode = editor.mode.prev().mode();
+
+        match operator_range(editor, keycode, const { Code::from_str("c") }) {
+            Some(OperatorRange::Chars(mut range)) => {
+                // like vim, `cw` on a word keeps the whitespace after it
+                let cur = editor.current();
+                let contents = &cur.buffer.contents;
+                if contents
+                    .get_char(range.start)
+                    .is_some_and(|ch| !ch.is_whitespace())
+                {
+                    while range.end > range.start && contents.char(range.end - 1).is_whitespace() {
+                        range.end -= 1;
+                    }
+                }
+                delete_chars(editor, range);
+            }
+            Some(OperatorRange::Lines(first, last)) => clear_lines(editor, first, last),
+            None => return false,
+        }
+
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct ChangeLineEnd;
+
+impl Action for ChangeLineEnd {
+    fn name(&self) -> &str {
+        "change-line-end"
+    }
+
+    fn description(&self) -> &str {
+        "replace the text from the cursor to the line end"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let mut cur = editor.current_mut();
+        let start = cur.view.cursor;
+        cur.jump_line_end();
+        // the cursor is on the last char, unless the line is empty
+        let end = match cur.buffer.contents.get_char(start) {
+            None | Some('\n') => start,
+            Some(_) => cur.view.cursor + 1,
+        };
+
+        delete_chars(editor, start..end);
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct ChangeSelection;
+
+impl Action for ChangeSelection {
+    fn name(&self) -> &str {
+        "change-selection"
+    }
+
+    fn description(&self) -> &str {
+        "replace the selected text"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if let Some((first, last)) = visual_lines(editor) {
+            clear_lines(editor, first, last);
+        } else if let Some(selection) = visual_selection(editor) {
+            delete_chars(editor, selection);
+        } else {
+            return;
+        }
+        editor.mode = Mode::Insert {
+            append: false,
+            replace: false,
+        };
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct YankMotion;
+
+impl Layer for YankMotion {
+    fn name(&self) -> &str {
+        "yank-motion"
+    }
+
+    fn description(&self) -> &str {
+        "copy the text a motion moves over, `y` copies whole lines"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn is_operator(&self) -> bool {
+        true
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        editor.mode = editor.mode.prev().mode();
+
+        let before = editor.current().view.cursor;
+        let register = match operator_range(editor, keycode, const { Code::from_str("y") }) {
+            Some(OperatorRange::Chars(range)) => {
+                let text = editor.current().buffer.contents.slice(range).to_string();
+                Register::new(text, false)
+            }
+            Some(OperatorRange::Lines(first, last)) => {
+                let contents = &editor.current().buffer.contents;
+                let last = last.min(contents.len_lines() - 1);
+                let range = contents.line_to_char(first)..contents.line_to_char(last + 1);
+                let mut text = contents.slice(range).to_string();
+                // the last line has no newline, but pasting lines needs one
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                Register::new(text, true)
+            }
+            None => return false,
+        };
+
+        // like vim, the cursor only moves if the motion went backwards
+        let cur = editor.current_mut();
+        cur.view.cursor = cur.view.cursor.min(before);
+        editor.registers.yank(register);
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct IndentMotion;
+
+impl Layer for IndentMotion {
+    fn name(&self) -> &str {
+        "indent-motion"
+    }
+
+    fn description(&self) -> &str {
+        "indent the lines a motion moves over, `>` indents the line"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn is_operator(&self) -> bool {
+        true
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        editor.mode = editor.mode.prev().mode();
+
+        let Some(range) = operator_range(editor, keycode, const { Code::from_str(">") }) else {
+            return false;
+        };
+        let (first, last) = operator_lines(editor, range);
+        shift_lines(editor, first, last, false);
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DedentMotion;
+
+impl Layer for DedentMotion {
+    fn name(&self) -> &str {
+        "dedent-motion"
+    }
+
+    fn description(&self) -> &str {
+        "dedent the lines a motion moves over, `<` dedents the line"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn is_operator(&self) -> bool {
+        true
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        editor.mode = editor.mode.prev().mode();
+
+        let Some(range) = operator_range(editor, keycode, const { Code::from_str("<") }) else {
+            return false;
+        };
+        let (first, last) = operator_lines(editor, range);
+        shift_lines(editor, first, last, true);
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct IndentSelection;
+
+impl Action for IndentSelection {
+    fn name(&self) -> &str {
+        "indent-selection"
+    }
+
+    fn description(&self) -> &str {
+        "indent the selected lines"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        shift_selection(editor, false);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DedentSelection;
+
+impl Action for DedentSelection {
+    fn name(&self) -> &str {
+        "dedent-selection"
+    }
+
+    fn description(&self) -> &str {
+        "dedent the selected lines"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        shift_selection(editor, true);
+    }
+}
+
+/// indent or dedent every line the selection touches
+fn shift_selection(editor: &mut Editor, dedent: bool) {
+    let Some(selection) = visual_selection(editor) else {
+        return;
+    };
+    let (first, last) = operator_lines(editor, OperatorRange::Chars(selection));
+    shift_lines(editor, first, last, dedent);
+    editor.mode = Mode::Normal;
+}
+
+/// indent or dedent the lines `first..=last` by one `shiftwidth`, empty lines aren't indented
+fn shift_lines(editor: &mut Editor, first: usize, last: usize, dedent: bool) {
+    let cur = editor.current_mut();
+    let contents = &cur.buffer.contents;
+    let last = last.min(contents.len_lines() - 1);
+    let indent = cur.buffer.options.indent();
+    let shiftwidth = cur.buffer.options.shiftwidth;
+
+    // only the indentation changes, so each line is its own edit
+    let ranges: Vec<Range<usize>> = (first..=last)
+        .filter_map(|line| {
+            let start = contents.line_to_char(line);
+            let mut chars = contents.line(line).chars();
+            if dedent {
+                // one tab, or up to a shiftwidth of spaces
+                let strip = if contents.get_char(start) == Some('\t') {
+                    1
+                } else {
+                    chars.take(shiftwidth).take_while(|&ch| ch == ' ').count()
+                };
+                (strip != 0).then_some(start..start + strip)
+            } else {
+                chars
+                    .next()
+                    .is_some_and(|ch| ch != '\n')
+                    .then_some(start..start)
+            }
+        })
+        .collect();
+
+    let text = if dedent { "" } else { indent.as_str() };
+    let result = cur.buffer.edit(|tx| {
+        for range in ranges {
+            tx.replace(range, text);
+        }
+    });
+    if let Err(err) = result {
+        tracing::warn!("failed to shift lines: {err}");
+    }
+
+    // like vim, the cursor goes to the first non-blank of the first line
+    let start = cur.buffer.contents.line_to_char(first);
+    cur.view.cursor = start + cur.count_matching(start, |ch| ch == ' ' || ch == '\t');
+}
+
+/// the text an operator like `d` or `c` acts on
+enum OperatorRange {
+    Chars(Range<usize>),
+    /// the lines `first..=last`
+    Lines(usize, usize),
+}
+
+/// the first and the last line an operator range touches
+fn operator_lines(editor: &Editor, range: OperatorRange) -> (usize, usize) {
+    match range {
+        OperatorRange::Chars(range) => {
+            let contents = &editor.current().buffer.contents;
+            let first = contents.char_to_line(range.start);
+            let last = contents.char_to_line(range.end.saturating_sub(1).max(range.start));
+            (first, last)
+        }
+        OperatorRange::Lines(first, last) => (first, last),
+    }
+}
+
+/// run the motion bound to `keycode` and return the text it moved over,
+/// the `operator` key again or `_` is `count` lines from the cursor, like `dd`
+fn operator_range(editor: &mut Editor, keycode: Code, operator: Code) -> Option<OperatorRange> {
+    if keycode == operator || keycode == const { Code::from_str("_") } {
+        let count = take_count(editor);
+        let cur = editor.current();
+        let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+        return Some(OperatorRange::Lines(line, line.saturating_add(count - 1)));
+    }
+
+    let Some(Entry::Action(act)) = editor.keymap.normal().get(keycode) else {
+        return None;
+    };
+    let motion = act.motion()?;
+
+    let before = editor.current().view.cursor;
+    editor.log_action(Some(keycode), act.name());
+    act.run(editor);
+
+    let cur = editor.current();
+    let after = cur.view.cursor;
+    let contents = &cur.buffer.contents;
+
+    if motion == Motion::Linewise {
+        let (first, last) = (contents.char_to_line(before), contents.char_to_line(after));
+        return Some(OperatorRange::Lines(first.min(last), first.max(last)));
+    }
+
+    let start = before.min(after);
+    let mut end = before.max(after);
+    // `$` on an empty line stops on its newline, which is never part of an inclusive motion
+    if motion == Motion::Inclusive && contents.get_char(end) != Some('\n') {
+        end += 1;
+    }
+    // like vim, `dw` on the last word of a line stops at the line end,
+    // instead of also deleting the line break and the indent after it
+    let start_line = contents.char_to_line(start);
+    if motion == Motion::Exclusive && contents.char_to_line(end) > start_line {
+        let line_end = contents.line_to_char(start_line + 1) - 1;
+        if contents
+            .slice(line_end..end)
+            .chars()
+            .all(char::is_whitespace)
+        {
+            end = line_end;
+        }
+    }
+    let end = end.min(contents.len_chars());
+
+    Some(OperatorRange::Chars(start..end))
+}
+
+/// delete `range` into the registers, and move the cursor to where it was
+fn delete_chars(editor: &mut Editor, range: Range<usize>) {
+    let cur = editor.current_mut();
+    cur.view.cursor = range.start;
+    if range.is_empty() {
+        return;
+    }
+
+    let text = cur.buffer.contents.slice(range.clone()).to_string();
+    cur.buffer.replace_text_at(range, "");
+    editor.registers.delete(Register::new(text, false));
+}
+
+/// delete the lines `first..=last` into the registers
+fn delete_lines(editor: &mut Editor, first: usize, last: usize) {
+    let cur = editor.current_mut();
+    let contents = &cur.buffer.contents;
+    let last = last.min(contents.len_lines() - 1);
+
+    let mut start = contents.line_to_char(first);
+    let end = contents.line_to_char(last + 1);
+    let mut text = contents.slice(start..end).to_string();
+    // the last line has no newline, so the newline before it goes instead
+    if !text.ends_with('\n') {
+        text.push('\n');
+        start = start.saturating_sub(1);
+    }
+
+    cur.buffer.replace_text_at(start..end, "");
+    // like vim, the cursor goes to the first non-blank of the line after
+    let line = first.min(cur.buffer.contents.len_lines() - 1);
+    let line_start = cur.buffer.contents.line_to_char(line);
+    cur.view.cursor = line_start + cur.count_matching(line_start, |ch| ch == ' ' || ch == '\t');
+    editor.registers.delete(Register::new(text, true));
+}
+
+/// delete the contents of the lines `first..=last` into the registers, leaving one empty line
+fn clear_lines(editor: &mut Editor, first: usize, last: usize) {
+    let cur = editor.current_mut();
+    let contents = &cur.buffer.contents;
+    let last = last.min(contents.len_lines() - 1);
+
+    let start = contents.line_to_char(first);
+    let mut end = contents.line_to_char(last + 1);
+    let mut text = contents.slice(start..end).to_string();
+    if text.ends_with('\n') {
+        end -= 1;
+    } else {
+        text.push('\n');
+    }
+
+    cur.buffer.replace_text_at(start..end, "");
+    cur.view.cursor = start;
+    editor.registers.delete(Register::new(text, true));
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Backspace;
+
+impl Action for Backspace {
+    fn name(&self) -> &str {
+        "backspace"
+    }
+
+    fn description(&self) -> &str {
+        "delete the current selection, and move backwards"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        match editor.mode {
+            Mode::Insert { replace, .. } => {
+                let mut cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+                if cur.view.cursor == 0 {
+                    return;
+                }
+
+                // replace mode restores what the typed characters overwrote
+                if replace {
+                    let at = cur.view.cursor - 1;
+                    match editor.replaced.pop() {
+                        Some((typed_at, Some(old))) if typed_at == at => {
+                            let mut buf = [0u8; 4];
+                            cur.buffer
+                                .replace_text_at(at..at + 1, old.encode_utf8(&mut buf));
+                        }
+                        Some((typed_at, None)) if typed_at == at => {
+                            cur.buffer.replace_text_at(at..at + 1, "");
+                        }
+                        // the cursor moved away, only move back over the text
+                        _ => editor.replaced.clear(),
+                    }
+                    cur.jump_cursor(-1, 0);
+                    return;
+                }
+
+                let start = cur.buffer.prev_grapheme(cur.view.cursor);
+                cur.buffer.replace_text_at(start..cur.view.cursor, "");
+                cur.view.cursor = start;
+                cur.view.cursor_x_unclamp = 0;
+            }
+            Mode::Command => {
+                // the command line keeps its `:`, the palette has none
+                let prompt_len = if editor.command_palette { 0 } else { 1 };
+                let last = editor.command.grapheme_indices(true).next_back();
+                if let Some((at, _)) = last.filter(|&(at, _)| at >= prompt_len) {
+                    editor.command.truncate(at);
+                    editor.command_history_index = None;
+                    RefreshSuggestions.run(editor);
+                    incremental_search(editor);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Undo;
+
+impl Action for Undo {
+    fn name(&self) -> &str {
+        "undo"
+    }
+
+    fn description(&self) -> &str {
+        "undo the last change"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        // `.` repeats the last change, not the undo
+        editor.change_keys = None;
+        let cur = editor.current_mut();
+        if cur.buffer.huge {
+            editor.status.push_str("no undo history for huge files");
+            editor.status_is_error = true;
+            return;
+        }
+        let Some(cursor) = cur.buffer.undo() else {
+            editor.status.push_str("already at the oldest change");
+            return;
+        };
+        cur.view.cursor = cursor.min(cur.buffer.contents.len_chars());
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Redo;
+
+impl Action for Redo {
+    fn name(&self) -> &str {
+        "redo"
+    }
+
+    fn description(&self) -> &str {
+        "redo the last undone change"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.change_keys = None;
+        let cur = editor.current_mut();
+        let Some(cursor) = cur.buffer.redo() else {
+            editor.status.push_str("already at the newest change");
+            return;
+        };
+        cur.view.cursor = cursor.min(cur.buffer.contents.len_chars());
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Repeat;
+
+impl Action for Repeat {
+    fn name(&self) -> &str {
+        "repeat"
+    }
+
+    fn description(&self) -> &str {
+        "repeat the last change at the cursor"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let count = take_count(editor);
+        // the repeat itself is not a new change to repeat
+        editor.change_keys = None;
+        if editor.repeating || editor.last_change.is_empty() {
+            return;
+        }
+
+        let keys = mem::take(&mut editor.last_change);
+        editor.repeating = true;
+        for _ in 0..count {
+            for &(code, modifiers) in &keys {
+                editor.key(code, modifiers);
+            }
+        }
+        editor.repeating = false;
+        editor.last_change = keys;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct SelectRegister;
+
+impl Layer for SelectRegister {
+    fn name(&self) -> &str {
+        "select-register"
+    }
+
+    fn description(&self) -> &str {
+        "use register * for the next yank, delete or paste"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let KeyCode::Char(name) = keycode.keycode else {
+            return false;
+        };
+        if !Registers::is_valid_name(name) {
+            return false;
+        }
+
+        editor.registers.active = Some(name);
+        editor.mode = editor.mode.prev().mode();
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Yank;
+
+impl Action for Yank {
+    fn name(&self) -> &str {
+        "yank"
+    }
+
+    fn description(&self) -> &str {
+        "copy the current selection"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if let Some(selection) = visual_selection(editor) {
+            let linewise = visual_lines(editor).is_some();
+            let cur = editor.current_mut();
+            let mut text = cur.buffer.contents.slice(selection.clone()).to_string();
+            // the last line has no newline, but pasting lines needs one
+            if linewise && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            // the cursor goes back to the start of the selection, like in vim
+            cur.view.cursor = selection.start;
+            editor.registers.yank(Register::new(text, linewise));
+            editor.mode = Mode::Normal;
+            return;
+        }
+
+        let cur = editor.current();
+        let Some(ch) = cur.buffer.contents.get_char(cur.view.cursor) else {
+            return;
+        };
+
+        editor.registers.yank(Register::new(ch.to_string(), false));
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct YankLine;
+
+impl Action for YankLine {
+    fn name(&self) -> &str {
+        "yank-line"
+    }
+
+    fn description(&self) -> &str {
+        "copy the current line"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let cur = editor.current();
+        let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+        let mut text = cur.buffer.contents.line(line).to_string();
+        // the last line has no newline, but it is pasted as a whole line anyway
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+
+        editor.registers.yank(Register::new(text, true));
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DuplicateLine;
+
+impl Action for DuplicateLine {
+    fn name(&self) -> &str {
+        "duplicate-line"
+    }
+
+    fn description(&self) -> &str {
+        "copy the current line or the selection below itself"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let count = take_count(editor);
+
+        if let Some(selection) = visual_selection(editor) {
+            let cur = editor.current_mut();
+            let text = cur.buffer.contents.slice(selection.clone()).to_string();
+            if let Err(err) = cur.buffer.insert(selection.end, &text.repeat(count)) {
+                tracing::warn!("failed to duplicate the selection: {err}");
+                return;
+            }
+            cur.view.cursor = selection.end;
+            editor.mode = Mode::Normal;
+            return;
+        }
+
+        let cur = editor.current_mut();
+        let row = cur.buffer.contents.char_to_line(cur.view.cursor);
+        let line_start = cur.buffer.contents.line_to_char(row);
+        let col = cur.view.cursor - line_start;
+        let line = cur.buffer.contents.line(row).to_string();
+
+        // all copies are one insert, so they are undone together
+        let result = if line.ends_with('\n') {
+            let next_line = line_start + line.chars().count();
+            cur.buffer.insert(next_line, &line.repeat(count))
+        } else {
+            // the last line has no newline to copy
+            let end = cur.buffer.contents.len_chars();
+            cur.buffer.insert(end, &format!("\n{line}").repeat(count))
+        };
+        if let Err(err) = result {
+            tracing::warn!("failed to duplicate the line: {err}");
+            return;
+        }
+
+        // the same column on the first copy
+        cur.view.cursor = cur.buffer.contents.line_to_char(row + 1) + col;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct MoveLinesDown;
+
+impl Action for MoveLinesDown {
+    fn name(&self) -> &str {
+        "move-lines-down"
+    }
+
+    fn description(&self) -> &str {
+        "swap the current line or the selected lines with the line below"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        for _ in 0..take_count(editor) {
+            if !move_lines(editor, true) {
+                break;
+            }
+        }
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct MoveLinesUp;
+
+impl Action for MoveLinesUp {
+    fn name(&self) -> &str {
+        "move-lines-up"
+    }
+
+    fn description(&self) -> &str {
+        "swap the current line or the selected lines with the line above"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        for _ in 0..take_count(editor) {
+            if !move_lines(editor, false) {
+                break;
+            }
+        }
+    }
+}
+
+/// swap the cursor or selection lines with the next or previous line as one edit,
+/// the cursor and the selection move with the lines,
+/// returns false at the buffer edges
+fn move_lines(editor: &mut Editor, down: bool) -> bool {
+    let selection = visual_selection(editor);
+    let cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+    let contents = &cur.buffer.contents;
+
+    let (first, last) = match &selection {
+        Some(selection) => (
+            contents.char_to_line(selection.start),
+            contents.char_to_line(selection.end.saturating_sub(1).max(selection.start)),
+        ),
+        None => {
+            let row = contents.char_to_line(cur.view.cursor);
+            (row, row)
+        }
+    };
+
+    let start = contents.line_to_char(first);
+    let end = contents.line_to_char(last) + contents.line(last).len_chars();
+    let block = contents.slice(start..end).to_string();
+
+    // the neighbouring line moves to the other side of the block,
+    // both edits are in the text before the move
+    let (removed, at, text, delta) = if down {
+        // the empty line after the last newline isn't a line to swap with
+        let Some(below) = contents
+            .get_line(last + 1)
+            .filter(|line| line.len_chars() != 0)
+        else {
+            return false;
+        };
+        let below = below.to_string();
+        let below_len = below.chars().count();
+
+        // the last line has no newline, so it takes the one from the block
+        let (removed, text) = match (below.ends_with('\n'), block.ends_with('\n')) {
+            (true, _) => (end..end + below_len, below),
+            (false, true) => (end - 1..end + below_len, format!("{below}\n")),
+            (false, false) => return false,
+        };
+        let delta = text.chars().count() as isize;
+        (removed, start, text, delta)
+    } else {
+        if first == 0 {
+            return false;
+        }
+        let above = contents.line(first - 1).to_string();
+        let above_len = above.chars().count();
+
+        let text = if block.ends_with('\n') {
+            above
+        } else {
+            // the block was the last line without a newline
+            format!("\n{}", above.strip_suffix('\n').unwrap_or(&above))
+        };
+        let delta = -(above_len as isize);
+        (start - above_len..start, end, text, delta)
+    };
+
+    let result = cur.buffer.edit(|tx| {
+        tx.delete(removed);
+        tx.insert(at, &text);
+    });
+    if let Err(err) = result {
+        tracing::warn!("failed to move lines: {err}");
+        return false;
+    }
+    cur.view.cursor = cur.view.cursor.saturating_add_signed(delta);
+    if let Mode::Visual { anchor, .. } = &mut editor.mode {
+        *anchor = anchor.saturating_add_signed(delta);
+    }
+
+    true
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct PasteAfter;
+
+impl Action for PasteAfter {
+    fn name(&self) -> &str {
+        "paste-after"
+    }
+
+    fn description(&self) -> &str {
+        "paste after the cursor, or below the current line"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        paste(editor, false);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct PasteBefore;
+
+impl Action for PasteBefore {
+    fn name(&self) -> &str {
+        "paste-before"
+    }
+
+    fn description(&self) -> &str {
+        "paste before the cursor, or above the current line"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        paste(editor, true);
+    }
+}
+
+/// paste the active register count times, linewise registers go on their own lines
+fn paste(editor: &mut Editor, before: bool) {
+    let count = take_count(editor);
+    let Some((register, ring)) = editor.registers.take_paste() else {
+        return;
+    };
+
+    let buffer = editor.view.buffer_index;
+    let cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+    let contents = &cur.buffer.contents;
+    let cursor = cur.view.cursor;
+
+    // where to insert, what to insert and where the first pasted line starts
+    let (at, text, first_line) = if register.linewise {
+        let line = contents.char_to_line(cursor);
+        let text = register.text.repeat(count);
+        if before {
+            let at = contents.line_to_char(line);
+            (at, text, at)
+        } else if line + 1 < contents.len_lines() {
+            let at = contents.line_to_char(line + 1);
+            (at, text, at)
+        } else {
+            // the last line has no newline to paste after, so move it to the front
+            let at = contents.len_chars();
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            (at, format!("\n{text}"), at + 1)
+        }
+    } else {
+        // an empty line has nothing to paste after
+        let at = match contents.get_char(cursor) {
+            Some('\n') | None => cursor,
+            Some(_) if before => cursor,
+            Some(_) => cursor + 1,
+        };
+        (at, register.text.repeat(count), at)
+    };
+
+    if let Err(err) = cur.buffer.insert(at, &text) {
+        tracing::warn!("failed to paste: {err}");
+        return;
+    }
+    let end = at + text.chars().count();
+
+    let cursor = if register.linewise {
+        // the first non-blank of the first pasted line
+        first_line + cur.count_matching(first_line, |ch| ch == ' ' || ch == '\t')
+    } else {
+        end.saturating_sub(1).max(at)
+    };
+    cur.view.cursor = cursor;
+
+    editor.last_paste = Some(LastPaste {
+        buffer,
+        range: at..end,
+        revision: cur.buffer.revision,
+        ring,
+    });
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct CyclePaste;
+
+impl Action for CyclePaste {
+    fn name(&self) -> &str {
+        "cycle-paste"
+    }
+
+    fn description(&self) -> &str {
+        "replace the last paste with the previous delete"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let Some(last) = editor.last_paste.take() else {
+            return;
+        };
+
+        let buffer = editor.view.buffer_index;
+        let cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+        if last.buffer != buffer || last.revision != cur.buffer.revision {
+            return;
+        }
+
+        let Some((register, ring)) = editor.registers.next_in_ring(last.ring) else {
+            return;
+        };
+        let text = &register.text;
+
+        let at = last.range.start;
+        cur.buffer.replace_text_at(last.range, text);
+        let end = at + text.chars().count();
+        cur.view.cursor = end.saturating_sub(1).max(at);
+
+        editor.last_paste = Some(LastPaste {
+            buffer,
+            range: at..end,
+            revision: cur.buffer.revision,
+            ring: Some(ring),
+        });
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct TypeChar;
+
+impl Layer for TypeChar {
+    fn name(&self) -> &str {
+        "type-char"
+    }
+
+    fn description(&self) -> &str {
+        "type character *"
+    }
+
+    fn get(&self, _: Code) -> Option<Entry> {
+        None
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        <_>::default()
+    }
+
+    fn wildcard(&self) -> Option<&dyn Layer> {
+        Some(self)
+    }
+
+    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
+        let ch = match keycode {
+            Code {
+                keycode: KeyCode::Char(ch),
+                ..
+            } => ch,
+            Code {
+                keycode: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+            } => '\n',
+            Code {
+                keycode: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+            } if editor.mode.is_insert() => {
+                let mut cur = editor.current_mut();
+                let indent = cur.buffer.options.indent();
+                cur.buffer.insert_text_at(cur.view.cursor, &indent);
+                cur.jump_cursor(indent.chars().count() as isize, 0);
+                return true;
+            }
+            _ => return false,
+        };
+
+        match editor.mode {
+            Mode::Insert { replace, .. } => {
+                let mut cur = editor.current_mut();
+                let at = cur.view.cursor;
+                // replace mode overwrites characters up to the end of the line
+                let overwrite = replace
+                    && ch != '\n'
+                    && cur.buffer.contents.get_char(at).is_some_and(|c| c != '\n');
+                let old = cur.buffer.contents.get_char(at);
+                if overwrite {
+                    let mut buf = [0u8; 4];
+                    cur.buffer
+                        .replace_text_at(at..at + 1, ch.encode_utf8(&mut buf));
+                } else {
+                    cur.buffer.insert_char_at(at, ch);
+                }
+                cur.jump_cursor(1, 0);
+                if replace {
+                    editor.replaced.push((at, old.filter(|_| overwrite)));
+                }
+            }
+            Mode::Command => {
+                if ch == '\n' && editor.command_palette {
+                    // the palette runs the highlighted action, not what was typed
+                    editor.mode = Mode::Normal;
+                    editor.command_palette = false;
+
+                    let index = editor.command_suggestion_index.unwrap_or(0);
+                    let act = editor
+                        .command_suggestions
+                        .get(index)
+                        .map(|suggestion| suggestion.act.clone());
+                    editor.command.clear();
+                    editor.command_suggestions.clear();
+                    editor.command_suggestion_index = None;
+
+                    if let Some(act) = act {
+                        editor.log_action(None, act.name());
+                        act.run(editor);
+                    }
+                } else if ch == '\n' && editor.is_search_prompt() {
+                    editor.mode = Mode::Normal;
+
+                    // search again from where it started, not from the incremental match
+                    if let Some(origin) = editor.search_origin.take() {
+                        editor.view.cursor = origin.cursor;
+                        editor.search = origin.search;
+                    }
+
+                    // an empty search repeats the last one
+                    if editor.command.len() > 1 {
+                        editor.search.clear();
+                        editor.search.push_str(&editor.command[1..]);
+                    }
+                    editor.command.clear();
+                    jump_to_match(editor, true);
+                } else if ch == '\n' {
+                    editor.mode = Mode::Normal;
+
+                    // mistyped commands too, so they can be fixed
+                    if editor.command.len() > 1 {
+                        let command = editor.command.clone();
+                        editor.push_command_history(&command);
+                    }
+
+                    let command_name = editor.command.as_str().trim_start_matches(':');
+                    // remove arguments
+                    let command_name = command_name
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or(command_name);
+                    // `s/old/new/` has no space before its arguments
+                    let command_name = ["%s", "s"]
+                        .into_iter()
+                        .find(|name| {
+                            command_name
+                                .strip_prefix(name)
+                                .and_then(|args| args.chars().next())
+                                .is_some_and(|ch| !ch.is_alphanumeric())
+                        })
+                        .unwrap_or(command_name);
+                    let command_name = COMMAND_ALIASES
+                        .iter()
+                        .find(|(alias, _)| *alias == command_name)
+                        .map_or(command_name, |&(_, name)| name);
+
+                    let Some(act) = DEFAULT_ACTIONS.get(command_name) else {
+                        editor.command.clear();
+                        editor.command.push_str("invalid command");
+                        return true;
+                    };
+
+                    editor.log_action(None, act.act.name());
+                    act.act.run(editor);
+                    editor.command.clear();
+                    editor.command_suggestions.clear();
+                    editor.command_suggestion_index = None;
+                } else {
+                    editor.command.push(ch);
+                    editor.command_history_index = None;
+                    RefreshSuggestions.run(editor);
+                    incremental_search(editor);
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Quit;
+
+impl Action for Quit {
+    fn name(&self) -> &str {
+        "q"
+    }
+
+    fn description(&self) -> &str {
+        "quit without saving"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if editor.current().buffer.modified {
+            editor.status_is_error = true;
+            editor.status.clear();
+            editor
+                .status
+                .push_str("unsaved changes, type :q! to quit without saving");
+            return;
+        }
+
+        editor.should_close = true;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct QuitForce;
+
+impl Action for QuitForce {
+    fn name(&self) -> &str {
+        "q!"
+    }
+
+    fn description(&self) -> &str {
+        "force quit without saving"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.should_close = true;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Write;
+
+impl Action for Write {
+    fn name(&self) -> &str {
+        "w"
+    }
+
+    fn description(&self) -> &str {
+        "save"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if !editor.current().buffer.modified {
+            return;
+        }
+
+        if write_remote_in_background(editor).is_some() {
+            return;
+        }
+
+        let askpw_tx = editor.open_askpw_tx.clone();
+        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
+            editor.status_is_error = true;
+            editor.status.clear();
+            use std::fmt::Write;
+            _ = write!(&mut editor.status, "{err}");
+        }
+    }
+}
+
+/// remote writes can be slow, so they run on a worker thread and the result is
+/// sent back through [`Editor::save_buffer_tx`]
+///
+/// returns the name of the buffer, if it is a writable remote buffer
+fn write_remote_in_background(editor: &Editor) -> Option<String> {
+    let buf = editor.current().buffer;
+    let BufferInner::Remote {
+        remote,
+        readonly: false,
+    } = &buf.inner
+    else {
+        return None;
+    };
+
+    let remote = remote.clone();
+    let name = buf.name.to_string();
+    let contents = buf.contents.clone();
+    let ty = buf.ty;
+    let askpw_tx = editor.open_askpw_tx.clone();
+    let save_buffer_tx = editor.save_buffer_tx.clone();
+
+    let thread_name = name.clone();
+    thread::spawn(move || {
+        let name = thread_name;
+        let result = Buffer::write_remote(remote, &name, &contents, ty, askpw_tx);
+        if let Err(err) = save_buffer_tx.send((name, contents, result)) {
+            tracing::error!("failed to send back save result: {err}");
+        }
+    });
+
+    Some(name)
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct WriteForce;
+
+impl Action for WriteForce {
+    fn name(&self) -> &str {
+        "w!"
+    }
+
+    fn description(&self) -> &str {
+        "save, even if the file changed on disk"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.current_mut().buffer.ignore_disk_changes();
+        Write.run(editor);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Reload;
+
+impl Action for Reload {
+    fn name(&self) -> &str {
+        "reload"
+    }
+
+    fn description(&self) -> &str {
+        "read the file again, if there are no unsaved changes"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if editor.current().buffer.modified {
+            editor.status.clear();
+            editor
+                .status
+                .push_str("unsaved changes, `reload!` discards them");
+            editor.status_is_error = true;
+            return;
+        }
+        reload(editor);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct ReloadForce;
+
+impl Action for ReloadForce {
+    fn name(&self) -> &str {
+        "reload!"
+    }
+
+    fn description(&self) -> &str {
+        "read the file again, discarding unsaved changes"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        reload(editor);
+    }
+}
+
+fn reload(editor: &mut Editor) {
+    if let Err(err) = editor.reload_buffer(editor.view.buffer_index) {
+        editor.status_is_error = true;
+        editor.status.clear();
+        use std::fmt::Write;
+        _ = write!(&mut editor.status, "{err}");
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct WriteQuit;
+
+impl Action for WriteQuit {
+    fn name(&self) -> &str {
+        "x"
+    }
+
+    fn description(&self) -> &str {
+        "save and quit"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if !editor.current().buffer.modified {
+            editor.should_close = true;
+            return;
+        }
+
+        // the editor closes once the save succeeds
+        if let Some(name) = write_remote_in_background(editor) {
+            editor.close_after_save = Some(name);
+            return;
+        }
+
+        let askpw_tx = editor.open_askpw_tx.clone();
+        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
+            editor.status_is_error = true;
+            editor.status.clear();
+            use std::fmt::Write;
+            _ = write!(&mut editor.status, "{err}");
+            return;
+        }
+
+        editor.should_close = true;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct WriteQuitForce;
+
+impl Action for WriteQuitForce {
+    fn name(&self) -> &str {
+        "x!"
+    }
+
+    fn description(&self) -> &str {
+        "save and force quit"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if !editor.current().buffer.modified {
+            return;
+        }
+
+        // the editor closes once the save succeeds
+        if let Some(name) = write_remote_in_background(editor) {
+            editor.close_after_save = Some(name);
+            return;
+        }
+
+        let askpw_tx = editor.open_askpw_tx.clone();
+        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
+            editor.status_is_error = true;
+            editor.status.clear();
+            use std::fmt::Write;
+            _ = write!(&mut editor.status, "{err}");
+            return;
+        }
+
+        editor.should_close = true;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct ClearLog;
+
+impl Action for ClearLog {
+    fn name(&self) -> &str {
+        "clear-log"
+    }
+
+    fn description(&self) -> &str {
+        "clear the log file"
+    }
+
+    fn run(&self, _: &mut Editor) {
+        if let Some(log_file) = crate::LOG_FILE.get() {
+            log_file.set_len(0).unwrap();
+        }
+    }
+}
+
+//
+
+/// a command matching what was typed on the command line
+#[derive(Clone)]
+pub struct Suggestion {
+    pub act: Arc<dyn Action>,
+    /// indices of the characters that matched
+    pub matched: Vec<usize>,
+    /// the description matched instead of the name
+    pub in_description: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct RefreshSuggestions;
+
+impl Action for RefreshSuggestions {
+    fn name(&self) -> &str {
+        "refresh-suggestions"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.command_suggestions.clear();
+        editor.command_suggestion_index = None;
+
+        // searches aren't commands
+        if editor.is_search_prompt() {
+            return;
+        }
+
+        let cmd = editor
+            .command
+            .strip_prefix(":")
+            .unwrap_or(editor.command.as_str());
+
+        // an empty command lists everything, sorted by name
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<(i64, Suggestion)> = DEFAULT_ACTIONS
+            .iter()
+            .filter_map(|act| {
+                if cmd.is_empty() {
+                    let act = act.act.clone();
+                    let (matched, in_description) = (Vec::new(), false);
+                    return Some((
+                        0,
+                        Suggestion {
+                            act,
+                            matched,
+                            in_description,
+                        },
+                    ));
+                }
+
+                // terse names like `w` are easier to find by their description,
+                // the name still wins ties
+                let name = matcher.fuzzy_indices(act.act.name(), cmd);
+                let description = matcher.fuzzy_indices(act.act.description(), cmd);
+                let ((score, matched), in_description) = match (name, description) {
+                    (Some(name), Some(description)) if description.0 > name.0 => {
+                        (description, true)
+                    }
+                    (Some(name), _) => (name, false),
+                    (None, Some(description)) => (description, true),
+                    (None, None) => return None,
+                };
+
+                let act = act.act.clone();
+                Some((
+                    score,
+                    Suggestion {
+                        act,
+                        matched,
+                        in_description,
+                    },
+                ))
+            })
+            .collect();
+
+        // best matches first, DEFAULT_ACTIONS is already sorted by name
+        matches.sort_by_key(|(score, _)| Reverse(*score));
+        editor
+            .command_suggestions
+            .extend(matches.into_iter().map(|(_, suggestion)| suggestion));
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct NextSuggestion;
+
+impl Action for NextSuggestion {
+    fn name(&self) -> &str {
+        "next-suggestion"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        if editor.command_suggestions.is_empty() {
+            return;
+        }
+
+        if let Some(index) = editor.command_suggestion_index.as_mut() {
+            *index += 1;
+            *index = (*index).min(editor.command_suggestions.len() - 1);
+        };
+
+        let index = *editor.command_suggestion_index.get_or_insert(0);
+        complete_suggestion(editor, index);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct PrevSuggestion;
+
+impl Action for PrevSuggestion {
+    fn name(&self) -> &str {
+        "prev-suggestion"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        tracing::debug!("running action {}", self.name());
+
+        if editor.command_suggestions.is_empty() {
+            return;
+        }
+        if let Some(index) = editor.command_suggestion_index.as_mut() {
+            *index = (*index).saturating_sub(1);
+        }
+
+        let index = *editor.command_suggestion_index.get_or_insert(0);
+        complete_suggestion(editor, index);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct HistoryPrev;
+
+impl Action for HistoryPrev {
+    fn name(&self) -> &str {
+        "history-prev"
+    }
+
+    fn description(&self) -> &str {
+        "recall the previous command starting with what was typed"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        recall_command(editor, true);
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct HistoryNext;
+
+impl Action for HistoryNext {
+    fn name(&self) -> &str {
+        "history-next"
+    }
+
+    fn description(&self) -> &str {
+        "recall the next command starting with what was typed"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        recall_command(editor, false);
+    }
+}
+
+fn recall_command(editor: &mut Editor, back: bool) {
+    if editor.command_palette || editor.is_search_prompt() {
+        return;
+    }
+
+    let index = editor.command_history_index;
+    if index.is_none() {
+        editor.command_history_prefix.clone_from(&editor.command);
+    }
+
+    let history = &editor.command_history;
+    let prefix = editor.command_history_prefix.as_str();
+    let matches = |i: &usize| history[*i].starts_with(prefix);
+    let found = if back {
+        (0..index.unwrap_or(history.len())).rev().find(matches)
+    } else {
+        index.and_then(|index| (index + 1..history.len()).find(matches))
+    };
+
+    match found {
+        Some(i) => {
+            editor.command_history_index = Some(i);
+            editor.command.clone_from(&editor.command_history[i]);
+        }
+        // past the newest command, back to what was typed
+        None if !back && index.is_some() => {
+            editor.command_history_index = None;
+            editor.command.clone_from(&editor.command_history_prefix);
+        }
+        None => return,
+    }
+    RefreshSuggestions.run(editor);
+}
+
+//
+
+/// put the suggestion on the command line, the palette keeps what was typed instead
+fn complete_suggestion(editor: &mut Editor, index: usize) {
+    if editor.command_palette {
+        return;
+    }
+
+    editor.command.clear();
+    editor.command.push(':');
+    editor
+        .command
+        .push_str(editor.command_suggestions[index].act.name());
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct New;
+
+impl Action for New {
+    fn name(&self) -> &str {
+        "new"
+    }
+
+    fn description(&self) -> &str {
+        "create a new empty file"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.open_from(Buffer::new());
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Open;
+
+impl Action for Open {
+    fn name(&self) -> &str {
+        "open"
+    }
+
+    fn description(&self) -> &str {
+        "open a file, takes a path argument"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let Some(path) = editor.command.split_whitespace().nth(1) else {
+            tracing::error!("`open` is missing path argument");
+            return;
+        };
+
+        editor.open(path.to_string());
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DiffSplit;
+
+impl Action for DiffSplit {
+    fn name(&self) -> &str {
+        "diffsplit"
+    }
+
+    fn description(&self) -> &str {
+        "open a read-only copy of the saved file"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        let buf = editor.current().buffer;
+        if !matches!(
+            buf.inner,
+            BufferInner::File { .. } | BufferInner::Remote { .. }
+        ) {
+            editor.status.clear();
+            editor.status.push_str("no saved file to compare with");
+            editor.status_is_error = true;
+            return;
+        }
+
+        let path = buf.name.to_string();
+        let askpw_tx = editor.open_askpw_tx.clone();
+        let huge_file = editor.options.huge_file_bytes();
+        let right = editor.view.buffer_index;
+        editor.open_with(format!("{path}{SNAPSHOT_SUFFIX}"), move || {
+            Buffer::open_snapshot(&path, huge_file, askpw_tx)
+        });
+
+        // keep editing the original buffer, with the saved copy on the left
+        let left = editor.view.buffer_index;
+        editor.switch_to(right);
+        editor.diff = Some(DiffView::new(left, right));
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct DiffOff;
+
+impl Action for DiffOff {
+    fn name(&self) -> &str {
+        "diffoff"
+    }
+
+    fn description(&self) -> &str {
+        "close the diff view"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.diff = None;
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct NextHunk;
+
+impl Action for NextHunk {
+    fn name(&self) -> &str {
+        "next-hunk"
+    }
+
+    fn description(&self) -> &str {
+        "jump to the next diff hunk"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        jump_hunk(editor, true);
     }
+}
 
-    fn wildcard(&self) -> Option<&dyn Layer> {
-        Some(self)
+//
+
+#[derive(Debug, Default)]
+pub struct PrevHunk;
+
+impl Action for PrevHunk {
+    fn name(&self) -> &str {
+        "prev-hunk"
     }
 
-    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
-        let KeyCode::Char(ch) = keycode.keycode else {
-            return false;
-        };
+    fn description(&self) -> &str {
+        "jump to the previous diff hunk"
+    }
 
-        let cur = editor.current_mut();
+    fn run(&self, editor: &mut Editor) {
+        jump_hunk(editor, false);
+    }
+}
 
-        if cur.view.cursor <= 1 {
-            editor.mode = Mode::Normal;
-            return false;
-        }
-        cur.view.cursor -= cur
-            .rfind(cur.view.cursor - 2, |cur_ch| cur_ch == ch)
-            .map_or(0, |n| n + 1);
-        editor.mode = Mode::Normal;
-        true
+fn jump_hunk(editor: &mut Editor, forwards: bool) {
+    let Some(diff) = editor
+        .diff
+        .as_mut()
+        .filter(|diff| diff.is_valid(&editor.buffers) && diff.right == editor.view.buffer_index)
+    else {
+        editor.status.clear();
+        editor.status.push_str("not in a diff view");
+        editor.status_is_error = true;
+        return;
+    };
+    diff.update(&editor.buffers);
+
+    let mut cur = BufferViewMut::new(&mut editor.view, &mut editor.buffers);
+    let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+    let hunk = if forwards {
+        diff.next_hunk(line)
+    } else {
+        diff.prev_hunk(line)
+    };
+
+    if let Some(hunk) = hunk {
+        cur.view.cursor = cur.buffer.contents.line_to_char(hunk);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct Delete;
+pub struct ToggleBookmark;
 
-impl Action for Delete {
+impl Action for ToggleBookmark {
     fn name(&self) -> &str {
-        "delete"
+        "toggle-bookmark"
     }
 
     fn description(&self) -> &str {
-        "delete the current selection"
+        "bookmark the current line, or remove its bookmark"
     }
 
     fn run(&self, editor: &mut Editor) {
         let cur = editor.current_mut();
-        if cur.view.cursor == 0 {
-            return;
+        let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+        if !cur.buffer.bookmarks.remove(&line) {
+            cur.buffer.bookmarks.insert(line);
         }
+    }
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct NextBookmark;
+
+impl Action for NextBookmark {
+    fn name(&self) -> &str {
+        "next-bookmark"
+    }
+
+    fn description(&self) -> &str {
+        "jump to the next bookmarked line"
+    }
 
-        cur.buffer
-            .replace_text_at(cur.view.cursor..cur.view.cursor + 1, "");
+    fn run(&self, editor: &mut Editor) {
+        jump_bookmark(editor, true);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct Backspace;
+pub struct PrevBookmark;
 
-impl Action for Backspace {
+impl Action for PrevBookmark {
     fn name(&self) -> &str {
-        "backspace"
+        "prev-bookmark"
     }
 
     fn description(&self) -> &str {
-        "delete the current selection, and move backwards"
+        "jump to the previous bookmarked line"
     }
 
     fn run(&self, editor: &mut Editor) {
-        match editor.mode {
-            Mode::Insert { .. } => {
-                let mut cur = editor.current_mut();
-                if cur.view.cursor == 0 {
-                    return;
-                }
+        jump_bookmark(editor, false);
+    }
+}
 
-                cur.buffer
-                    .replace_text_at(cur.view.cursor - 1..cur.view.cursor, "");
-                cur.jump_cursor(-1, 0);
-            }
-            Mode::Command => {
-                if editor.command.len() >= 2 {
-                    _ = editor.command.pop();
-                    RefreshSuggestions.run(editor);
+/// jump to the bookmark after or before the cursor line, wrapping around the buffer ends
+fn jump_bookmark(editor: &mut Editor, forwards: bool) {
+    let cur = editor.current_mut();
+    let line = cur.buffer.contents.char_to_line(cur.view.cursor);
+    let bookmarks = &cur.buffer.bookmarks;
+    let bookmark = if forwards {
+        bookmarks
+            .range(line + 1..)
+            .next()
+            .or_else(|| bookmarks.first())
+    } else {
+        bookmarks
+            .range(..line)
+            .next_back()
+            .or_else(|| bookmarks.last())
+    };
+
+    let Some(&bookmark) = bookmark else {
+        editor.status.clear();
+        editor.status.push_str("no bookmarks");
+        editor.status_is_error = true;
+        return;
+    };
+
+    // never past the end, even if the bookmarks didn't follow an edit
+    let bookmark = bookmark.min(cur.buffer.contents.len_lines() - 1);
+    cur.view.cursor = cur.buffer.contents.line_to_char(bookmark);
+    cur.view.cursor_x_unclamp = 0;
+}
+
+//
+
+#[derive(Debug, Default)]
+pub struct Set;
+
+impl Action for Set {
+    fn name(&self) -> &str {
+        "set"
+    }
+
+    fn description(&self) -> &str {
+        "set options, like `set noexpandtab shiftwidth=8`"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        for arg in editor.command.split_whitespace().skip(1) {
+            // the file format belongs to the file, not the options
+            let result = if let Some(value) = arg
+                .strip_prefix("fileformat=")
+                .or_else(|| arg.strip_prefix("ff="))
+            {
+                match FileFormat::from_name(value) {
+                    Some(format) => editor.buffers[editor.view.buffer_index].set_fileformat(format),
+                    None => Err(eyre::eyre!(
+                        "`fileformat` expects `unix`, `dos` or `mac`, got `{value}`"
+                    )),
                 }
+            } else {
+                // buffer options change both the current buffer and the default for new buffers
+                editor.options.set(arg).and_then(|()| {
+                    editor.buffers[editor.view.buffer_index]
+                        .options
+                        .set(arg)
+                        .map(|_| ())
+                })
+            };
+            if let Err(err) = result {
+                editor.status.clear();
+                use std::fmt::Write;
+                _ = write!(&mut editor.status, "{err}");
+                editor.status_is_error = true;
             }
-            _ => {}
         }
     }
 }
@@ -747,447 +3473,557 @@ impl Action for Backspace {
 //
 
 #[derive(Debug, Default)]
-pub struct TypeChar;
+pub struct Dos2Unix;
 
-impl Layer for TypeChar {
+impl Action for Dos2Unix {
     fn name(&self) -> &str {
-        "type-char"
+        "dos2unix"
     }
 
     fn description(&self) -> &str {
-        "type character *"
+        "save with `\\n` line endings, same as `set fileformat=unix`"
     }
 
-    fn get(&self, _: Code) -> Option<Entry> {
-        None
+    fn run(&self, editor: &mut Editor) {
+        set_fileformat(editor, FileFormat::Unix);
     }
+}
 
-    fn entries(&self) -> Arc<[(Code, Entry)]> {
-        <_>::default()
+//
+
+#[derive(Debug, Default)]
+pub struct Unix2Dos;
+
+impl Action for Unix2Dos {
+    fn name(&self) -> &str {
+        "unix2dos"
     }
 
-    fn wildcard(&self) -> Option<&dyn Layer> {
-        Some(self)
+    fn description(&self) -> &str {
+        "save with `\\r\\n` line endings, same as `set fileformat=dos`"
     }
 
-    fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
-        let ch = match keycode {
-            Code {
-                keycode: KeyCode::Char(ch),
-                ..
-            } => ch,
-            Code {
-                keycode: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-            } => '\n',
-            _ => return false,
-        };
+    fn run(&self, editor: &mut Editor) {
+        set_fileformat(editor, FileFormat::Dos);
+    }
+}
 
-        match editor.mode {
-            Mode::Insert { .. } => {
-                let mut cur = editor.current_mut();
-                cur.buffer.insert_char_at(cur.view.cursor, ch);
-                cur.jump_cursor(1, 0);
-            }
-            Mode::Command => {
-                if ch == '\n' {
-                    editor.mode = Mode::Normal;
+fn set_fileformat(editor: &mut Editor, format: FileFormat) {
+    let result = editor.current_mut().buffer.set_fileformat(format);
+    editor.status.clear();
+    match result {
+        Ok(()) => {
+            editor.status.push_str("fileformat=");
+            editor.status.push_str(format.as_str());
+            editor.status_is_error = false;
+        }
+        Err(err) => {
+            use std::fmt::Write;
+            _ = write!(&mut editor.status, "{err}");
+            editor.status_is_error = true;
+        }
+    }
+}
 
-                    let command_name = editor.command.as_str().trim_start_matches(':');
-                    // remove arguments
-                    let command_name = command_name
-                        .split_whitespace()
-                        .next()
-                        .unwrap_or(command_name);
+//
 
-                    let Some(act) = DEFAULT_ACTIONS.get(command_name) else {
-                        editor.command.clear();
-                        editor.command.push_str("invalid command");
-                        return true;
-                    };
+#[derive(Debug, Default)]
+pub struct Retab;
 
-                    act.act.run(editor);
-                    editor.command.clear();
-                    editor.command_suggestions.clear();
-                    editor.command_suggestion_index = None;
-                } else {
-                    editor.command.push(ch);
-                    RefreshSuggestions.run(editor);
-                }
-            }
-            _ => {}
-        }
+impl Action for Retab {
+    fn name(&self) -> &str {
+        "retab"
+    }
 
-        true
+    fn description(&self) -> &str {
+        "convert indentation tabs to spaces, `retab all` converts every tab and `retab 3,7` lines 3 to 7"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        retab(editor, false);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct Quit;
+pub struct RetabBang;
 
-impl Action for Quit {
+impl Action for RetabBang {
     fn name(&self) -> &str {
-        "q"
+        "retab!"
     }
 
     fn description(&self) -> &str {
-        "quit without saving"
+        "convert indentation spaces to tabs, also `entab`, `retab! 3,7` converts lines 3 to 7"
     }
 
     fn run(&self, editor: &mut Editor) {
-        if editor.current().buffer.modified {
-            editor.status_is_error = true;
+        retab(editor, true);
+    }
+}
+
+/// convert the whole buffer, or the `first,last` lines given after the command
+fn retab(editor: &mut Editor, to_tabs: bool) {
+    use std::fmt::Write;
+    let mut all = false;
+    let mut lines = None;
+    for arg in editor.command.split_whitespace().skip(1) {
+        if arg == "all" {
+            all = true;
+        } else if let Some(range) = parse_line_range(arg) {
+            lines = Some(range);
+        } else {
             editor.status.clear();
-            editor
-                .status
-                .push_str("unsaved changes, type :q! to quit without saving");
+            _ = write!(&mut editor.status, "invalid argument: {arg}");
+            editor.status_is_error = true;
             return;
         }
+    }
 
-        editor.should_close = true;
+    let cur = editor.current_mut();
+    let tabstop = cur.buffer.options.tabstop;
+    let row = cur.buffer.contents.char_to_line(cur.view.cursor);
+    let col = cur.view.cursor - cur.buffer.contents.line_to_char(row);
+
+    let last_line = cur.buffer.contents.len_lines() - 1;
+    let (first, last) = lines.unwrap_or((0, last_line));
+    if first > last_line {
+        editor.status.clear();
+        _ = write!(&mut editor.status, "line {} is past the end", first + 1);
+        editor.status_is_error = true;
+        return;
+    }
+    let start = cur.buffer.contents.line_to_char(first);
+    let end = cur.buffer.contents.line_to_char(last.min(last_line) + 1);
+
+    let old = cur.buffer.contents.slice(start..end).to_string();
+    let new = old
+        .split_inclusive('\n')
+        .map(|line| retab_line(line, tabstop, to_tabs, all))
+        .collect::<String>();
+
+    if new == old {
+        editor.status.clear();
+        editor.status.push_str("nothing to convert");
+        editor.status_is_error = false;
+        return;
+    }
+
+    // one replace, so the whole conversion is undone at once
+    cur.buffer.replace_text_at(start..end, &new);
+
+    let line_start = cur.buffer.contents.line_to_char(row);
+    let line_len = cur.buffer.contents.line(row).len_chars();
+    cur.view.cursor = line_start + col.min(line_len.saturating_sub(1));
+    cur.view.cursor_x_unclamp = 0;
+}
+
+/// the 0 based first and last line of a 1 based `first,last` or a single `line`
+fn parse_line_range(arg: &str) -> Option<(usize, usize)> {
+    let (first, last) = arg.split_once(',').unwrap_or((arg, arg));
+    let first = first.parse::<usize>().ok()?.checked_sub(1)?;
+    let last = last.parse::<usize>().ok()?.checked_sub(1)?;
+    (first <= last).then_some((first, last))
+}
+
+/// converts the indentation of one line, and with `all` every other tab too
+fn retab_line(line: &str, tabstop: usize, to_tabs: bool, all: bool) -> String {
+    let indent_len = line
+        .find(|ch| ch != ' ' && ch != '\t')
+        .unwrap_or(line.len());
+    let (indent, rest) = line.split_at(indent_len);
+
+    let width = indent.chars().fold(0, |col, ch| match ch {
+        '\t' => (col / tabstop + 1) * tabstop,
+        _ => col + 1,
+    });
+
+    let mut out = if to_tabs {
+        "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop)
+    } else {
+        " ".repeat(width)
+    };
+
+    if !all || to_tabs {
+        out.push_str(rest);
+        return out;
+    }
+
+    let mut col = width;
+    for ch in rest.chars() {
+        if ch == '\t' {
+            let next = (col / tabstop + 1) * tabstop;
+            out.push_str(&" ".repeat(next - col));
+            col = next;
+        } else {
+            out.push(ch);
+            col += 1;
+        }
     }
+    out
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct QuitForce;
+pub struct FileInfo;
 
-impl Action for QuitForce {
+impl Action for FileInfo {
     fn name(&self) -> &str {
-        "q!"
+        "file"
     }
 
     fn description(&self) -> &str {
-        "force quit without saving"
+        "show the file name, state, size and cursor position"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.should_close = true;
+        let cur = editor.current();
+        let contents = &cur.buffer.contents;
+        let lines = contents.len_lines();
+        let line = contents.char_to_line(cur.view.cursor) + 1;
+
+        use std::fmt::Write;
+        let mut info = format!("\"{}\"", cur.buffer.name);
+        if cur.buffer.modified {
+            info.push_str(" [modified]");
+        }
+        if cur.buffer.is_readonly() {
+            info.push_str(" [read-only]");
+        }
+        _ = write!(
+            &mut info,
+            " {lines} line{} --{}%-- {}",
+            if lines == 1 { "" } else { "s" },
+            line * 100 / lines,
+            cur.buffer.ty.as_str(),
+        );
+        if let Some(format) = cur.buffer.fileformat() {
+            _ = write!(&mut info, " {}", format.as_str());
+        }
+
+        editor.status = info;
+        editor.status_is_error = false;
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct Write;
+pub struct ShowRegisters;
 
-impl Action for Write {
+impl Action for ShowRegisters {
     fn name(&self) -> &str {
-        "w"
+        "registers"
     }
 
     fn description(&self) -> &str {
-        "save"
+        "show the registers, and pick the one the next paste uses"
     }
 
     fn run(&self, editor: &mut Editor) {
-        if !editor.current().buffer.modified {
-            return;
-        }
+        editor.popup = Popup::Registers {
+            entries: editor.registers.populated(),
+            selected: 0,
+        };
+    }
+}
 
-        let askpw_tx = editor.open_askpw_tx.clone();
-        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
-            editor.status_is_error = true;
-            editor.status.clear();
-            use std::fmt::Write;
-            _ = write!(&mut editor.status, "{err}");
-        }
+//
+
+#[derive(Debug, Default)]
+pub struct ShowRegistersShort;
+
+impl Action for ShowRegistersShort {
+    fn name(&self) -> &str {
+        "reg"
+    }
+
+    fn description(&self) -> &str {
+        "show the registers"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        ShowRegisters.run(editor);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct WriteQuit;
+pub struct Verbose;
 
-impl Action for WriteQuit {
+impl Action for Verbose {
     fn name(&self) -> &str {
-        "x"
+        "verbose"
     }
 
     fn description(&self) -> &str {
-        "save and quit"
+        "toggle recording which actions keys run"
     }
 
     fn run(&self, editor: &mut Editor) {
-        if !editor.current().buffer.modified {
-            editor.should_close = true;
-            return;
-        }
+        editor.options.verbose ^= true;
+    }
+}
 
-        let askpw_tx = editor.open_askpw_tx.clone();
-        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
-            editor.status_is_error = true;
-            editor.status.clear();
-            use std::fmt::Write;
-            _ = write!(&mut editor.status, "{err}");
-            return;
-        }
+//
 
-        editor.should_close = true;
+#[derive(Debug, Default)]
+pub struct ActionLog;
+
+impl Action for ActionLog {
+    fn name(&self) -> &str {
+        "actions"
+    }
+
+    fn description(&self) -> &str {
+        "show recently run actions, see `verbose`"
+    }
+
+    fn run(&self, editor: &mut Editor) {
+        editor.popup = Popup::ActionLog {
+            entries: editor.action_log.iter().cloned().collect(),
+        };
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct WriteQuitForce;
+pub struct BufferClose;
 
-impl Action for WriteQuitForce {
+impl Action for BufferClose {
     fn name(&self) -> &str {
-        "x!"
+        "buffer-close"
     }
 
     fn description(&self) -> &str {
-        "save and force quit"
+        "close the current buffer"
     }
 
     fn run(&self, editor: &mut Editor) {
-        if !editor.current().buffer.modified {
+        if editor.current().buffer.modified {
+            editor.status_is_error = true;
+            editor.status.clear();
+            editor.status.push_str("unsaved changes");
             return;
         }
 
-        let askpw_tx = editor.open_askpw_tx.clone();
-        if let Err(err) = editor.current_mut().buffer.write(askpw_tx) {
-            editor.status_is_error = true;
-            editor.status.clear();
-            use std::fmt::Write;
-            _ = write!(&mut editor.status, "{err}");
+        // the buffer indices move
+        editor.diff = None;
+        editor.last_paste = None;
+
+        if editor.buffers.len() == 1 {
+            editor.buffers.clear();
+            editor.buffers.push(Buffer::new());
+            editor.view.buffer_index = 0;
+            for view in editor.splits.views_mut() {
+                *view = BufferView::new(0);
+            }
             return;
         }
 
-        editor.should_close = true;
+        editor.buffers.pop();
+        let last = editor.buffers.len() - 1;
+        editor.view.buffer_index = editor.view.buffer_index.min(last);
+        // the other splits showing the closed buffer move on too
+        for view in editor.splits.views_mut() {
+            if view.buffer_index > last {
+                *view = BufferView::new(last);
+            }
+        }
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct ClearLog;
+pub struct BufferNext;
 
-impl Action for ClearLog {
+impl Action for BufferNext {
     fn name(&self) -> &str {
-        "clear-log"
+        "buffer-next"
     }
 
     fn description(&self) -> &str {
-        "clear the log file"
+        "go to the next buffer"
     }
 
-    fn run(&self, _: &mut Editor) {
-        if let Some(log_file) = crate::LOG_FILE.get() {
-            log_file.set_len(0).unwrap();
-        }
+    fn run(&self, editor: &mut Editor) {
+        editor.view.buffer_index += 1;
+        editor.view.buffer_index %= editor.buffers.len();
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct RefreshSuggestions;
+pub struct BufferPrev;
 
-impl Action for RefreshSuggestions {
+impl Action for BufferPrev {
     fn name(&self) -> &str {
-        "refresh-suggestions"
+        "buffer-prev"
     }
 
-    fn run(&self, editor: &mut Editor) {
-        editor.command_suggestions.clear();
-        editor.command_suggestion_index = None;
+    fn description(&self) -> &str {
+        "go to the previous buffer"
+    }
 
-        let cmd = editor
-            .command
-            .strip_prefix(":")
-            .unwrap_or(editor.command.as_str());
-        editor.command_suggestions.extend(
-            DEFAULT_ACTIONS
-                .iter()
-                .filter(|act| act.act.name().contains(cmd))
-                .cloned(),
-        );
+    fn run(&self, editor: &mut Editor) {
+        let len = editor.buffers.len();
+        editor.view.buffer_index = (editor.view.buffer_index + len - 1) % len;
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct NextSuggestion;
+pub struct SplitHorizontal;
 
-impl Action for NextSuggestion {
+impl Action for SplitHorizontal {
     fn name(&self) -> &str {
-        "next-suggestion"
+        "split-horizontal"
     }
 
-    fn run(&self, editor: &mut Editor) {
-        if editor.command_suggestions.is_empty() {
-            return;
-        }
-
-        if let Some(index) = editor.command_suggestion_index.as_mut() {
-            *index += 1;
-            *index = (*index).min(editor.command_suggestions.len() - 1);
-        };
-
-        let index = *editor.command_suggestion_index.get_or_insert(0);
+    fn description(&self) -> &str {
+        "split the view in two, one above the other"
+    }
 
-        editor.command.clear();
-        editor.command.push(':');
-        editor
-            .command
-            .push_str(editor.command_suggestions[index].act.name());
+    fn run(&self, editor: &mut Editor) {
+        editor.splits.split(&editor.view, false);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct PrevSuggestion;
+pub struct SplitVertical;
 
-impl Action for PrevSuggestion {
+impl Action for SplitVertical {
     fn name(&self) -> &str {
-        "prev-suggestion"
+        "split-vertical"
     }
 
-    fn run(&self, editor: &mut Editor) {
-        tracing::debug!("running action {}", self.name());
-
-        if editor.command_suggestions.is_empty() {
-            return;
-        }
-        if let Some(index) = editor.command_suggestion_index.as_mut() {
-            *index = (*index).saturating_sub(1);
-        }
-
-        let index = *editor.command_suggestion_index.get_or_insert(0);
+    fn description(&self) -> &str {
+        "split the view in two, side by side"
+    }
 
-        editor.command.clear();
-        editor.command.push(':');
-        editor
-            .command
-            .push_str(editor.command_suggestions[index].act.name());
+    fn run(&self, editor: &mut Editor) {
+        editor.splits.split(&editor.view, true);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct New;
+pub struct SplitClose;
 
-impl Action for New {
+impl Action for SplitClose {
     fn name(&self) -> &str {
-        "new"
+        "split-close"
     }
 
     fn description(&self) -> &str {
-        "create a new empty file"
+        "close the focused split, the buffer stays open"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.open_from(Buffer::new());
+        match editor.splits.close() {
+            Some(view) => editor.view = view,
+            None => {
+                editor.status.clear();
+                editor.status.push_str("the only split");
+                editor.status_is_error = true;
+            }
+        }
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct Open;
+pub struct FocusLeft;
 
-impl Action for Open {
+impl Action for FocusLeft {
     fn name(&self) -> &str {
-        "open"
+        "focus-left"
     }
 
     fn description(&self) -> &str {
-        "open a file, takes a path argument"
+        "focus the split on the left"
     }
 
     fn run(&self, editor: &mut Editor) {
-        let Some(path) = editor.command.split_whitespace().nth(1) else {
-            tracing::error!("`open` is missing path argument");
-            return;
-        };
-
-        editor.open(path.to_string());
+        focus_split(editor, Direction::Left);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct BufferClose;
+pub struct FocusDown;
 
-impl Action for BufferClose {
+impl Action for FocusDown {
     fn name(&self) -> &str {
-        "buffer-close"
+        "focus-down"
     }
 
     fn description(&self) -> &str {
-        "close the current buffer"
+        "focus the split below"
     }
 
     fn run(&self, editor: &mut Editor) {
-        if editor.current().buffer.modified {
-            editor.status_is_error = true;
-            editor.status.clear();
-            editor.status.push_str("unsaved changes");
-            return;
-        }
-
-        if editor.buffers.len() == 1 {
-            editor.buffers.clear();
-            editor.buffers.push(Buffer::new());
-            editor.view.buffer_index = 0;
-            return;
-        }
-
-        editor.buffers.pop();
-        editor.view.buffer_index = editor.view.buffer_index.min(editor.buffers.len() - 1);
+        focus_split(editor, Direction::Down);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct BufferNext;
+pub struct FocusUp;
 
-impl Action for BufferNext {
+impl Action for FocusUp {
     fn name(&self) -> &str {
-        "buffer-next"
+        "focus-up"
     }
 
     fn description(&self) -> &str {
-        "go to the next buffer"
+        "focus the split above"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.view.buffer_index += 1;
-        editor.view.buffer_index %= editor.buffers.len();
+        focus_split(editor, Direction::Up);
     }
 }
 
 //
 
 #[derive(Debug, Default)]
-pub struct BufferPrev;
+pub struct FocusRight;
 
-impl Action for BufferPrev {
+impl Action for FocusRight {
     fn name(&self) -> &str {
-        "buffer-prev"
+        "focus-right"
     }
 
     fn description(&self) -> &str {
-        "go to the previous buffer"
+        "focus the split on the right"
     }
 
     fn run(&self, editor: &mut Editor) {
-        editor.view.buffer_index += 1;
-        editor.view.buffer_index %= editor.buffers.len();
+        focus_split(editor, Direction::Right);
     }
 }
 
+fn focus_split(editor: &mut Editor, dir: Direction) {
+    // the same area the splits are drawn in, without the command line
+    let (width, height) = editor.size;
+    let area = Rect::new(0, 0, width, height.saturating_sub(1));
+    editor.splits.focus(area, dir, &mut editor.view);
+}
+
 //
 
 #[derive(Debug, Default)]
@@ -1212,32 +4048,38 @@ impl Action for FileExplorer {
                 (path, None)
             }
             BufferInner::Remote { remote, .. } => {
-                let mut conn = CONN_POOL
-                    .connect_to(remote.clone(), editor.open_askpw_tx.clone())
-                    .unwrap();
+                let remote = remote.clone();
+                let askpw_tx = editor.open_askpw_tx.clone();
+                let open_popup_tx = editor.open_popup_tx.clone();
 
                 let path = PathBuf::from(
-                    buf.name
-                        .rsplit_once(':')
+                    Part::split_path(&buf.name)
                         .map(|(_, path)| path)
                         .unwrap_or(buf.name.as_ref())
                         .to_string(),
                 );
 
-                let mut path = match conn.canonicalize(&path) {
-                    Ok(path) => path,
-                    Err(err) => {
-                        editor.status.clear();
-                        use std::fmt::Write;
-                        _ = write!(&mut editor.status, "failed to get parent path {err}");
-                        editor.status_is_error = true;
-                        return;
+                // listing remote files can be slow, so it runs on a worker thread
+                thread::spawn(move || {
+                    let popup = (|| -> eyre::Result<Popup> {
+                        let mut conn = CONN_POOL.connect_to(remote.clone(), askpw_tx.clone())?;
+                        let mut path = conn
+                            .canonicalize(&path)
+                            .map_err(|err| eyre::eyre!("failed to get parent path {err}"))?;
+                        drop(conn);
+                        path.pop();
+                        Popup::file_explorer(Some(remote), askpw_tx, path)
+                    })();
+
+                    if let Err(err) = open_popup_tx.send(popup) {
+                        tracing::error!("failed to send back file explorer: {err}");
                     }
-                };
-                path.pop();
-                (path, Some(remote.clone()))
+                });
+                return;
+            }
+            BufferInner::Scratch { .. } | BufferInner::Loading => {
+                (env::current_dir().unwrap(), None)
             }
-            BufferInner::Scratch { .. } => (env::current_dir().unwrap(), None),
         };
 
         match Popup::file_explorer(remote, editor.open_askpw_tx.clone(), at) {
@@ -1280,3 +4122,438 @@ impl Action for WhichKey {
         editor.force_whichkey ^= true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{Event, KeyEvent};
+    use ropey::Rope;
+
+    use super::*;
+
+    /// an editor with a buffer of `text` and the cursor at char `cursor`
+    fn editor(text: &str, cursor: usize) -> Editor {
+        let mut buffer = Buffer::new();
+        buffer.contents = Rope::from_str(text);
+        let mut editor = Editor::for_tests(buffer);
+        editor.view.cursor = cursor;
+        editor
+    }
+
+    /// press each char of `keys` in order, `<...>` is a key name like `<esc>` or `<S-P>`
+    fn press(editor: &mut Editor, keys: &str) {
+        let mut rest = keys;
+        while let Some(ch) = rest.chars().next() {
+            let named = rest
+                .strip_prefix('<')
+                .and_then(|named| named.split_once('>'))
+                .and_then(|(name, after)| Some((Code::try_from_str(name)?, after)));
+            let code = if let Some((code, after)) = named {
+                rest = after;
+                code
+            } else {
+                rest = &rest[ch.len_utf8()..];
+                Code::from_event(KeyCode::Char(ch), KeyModifiers::NONE)
+            };
+            let event = KeyEvent::new(code.keycode, code.modifiers);
+            editor.event(Event::Key(event));
+        }
+    }
+
+    fn text(editor: &Editor) -> String {
+        editor.current().buffer.text()
+    }
+
+    fn cursor(editor: &Editor) -> usize {
+        editor.current().view.cursor
+    }
+
+    #[test]
+    fn delete_to_line_end() {
+        let mut editor = editor("abc\ndef\n", 1);
+        press(&mut editor, "d$");
+        assert_eq!(text(&editor), "a\ndef\n");
+    }
+
+    #[test]
+    fn delete_to_line_end_on_an_empty_line_keeps_the_newline() {
+        let mut editor = editor("a\n\nb\n", 2);
+        press(&mut editor, "d$");
+        assert_eq!(text(&editor), "a\n\nb\n");
+        assert_eq!(cursor(&editor), 2);
+    }
+
+    #[test]
+    fn delete_to_line_end_at_the_end_of_the_buffer() {
+        let mut editor = editor("abc", 1);
+        press(&mut editor, "d$");
+        assert_eq!(text(&editor), "a");
+        assert_eq!(cursor(&editor), 0);
+    }
+
+    #[test]
+    fn change_to_line_end() {
+        let mut editor = editor("abc\ndef\n", 1);
+        press(&mut editor, "c$x<esc>");
+        assert_eq!(text(&editor), "ax\ndef\n");
+    }
+
+    #[test]
+    fn change_to_line_end_on_an_empty_line_keeps_the_newline() {
+        let mut editor = editor("a\n\nb\n", 2);
+        press(&mut editor, "c$x<esc>");
+        assert_eq!(text(&editor), "a\nx\nb\n");
+    }
+
+    #[test]
+    fn change_to_line_end_at_the_end_of_the_buffer() {
+        let mut editor = editor("abc", 1);
+        press(&mut editor, "c$x<esc>");
+        assert_eq!(text(&editor), "ax");
+    }
+
+    #[test]
+    fn the_operator_count_multiplies_the_motion_count() {
+        let mut editor = editor("", 0);
+        editor.count = Some(3);
+        editor.operator_count = Some(2);
+        assert_eq!(take_count(&mut editor), 6);
+        assert_eq!(editor.count, None);
+        assert_eq!(editor.operator_count, None);
+        assert_eq!(take_count(&mut editor), 1);
+    }
+
+    #[test]
+    fn delete_a_counted_motion() {
+        let mut editor = editor("a b c d e\n", 0);
+        press(&mut editor, "d3w");
+        assert_eq!(text(&editor), "d e\n");
+    }
+
+    #[test]
+    fn delete_counted_lines() {
+        let mut two = editor("1\n2\n3\n4\n", 0);
+        press(&mut two, "2dd");
+        assert_eq!(text(&two), "3\n4\n");
+
+        let mut three = editor("1\n2\n3\n4\n", 0);
+        press(&mut three, "3dd");
+        assert_eq!(text(&three), "4\n");
+    }
+
+    #[test]
+    fn delete_lines_down() {
+        let mut editor = editor("1\n2\n3\n4\n", 0);
+        press(&mut editor, "d2j");
+        assert_eq!(text(&editor), "4\n");
+    }
+
+    #[test]
+    fn delete_with_both_counts() {
+        let mut editor = editor("a b c d e f g h\n", 0);
+        press(&mut editor, "2d3w");
+        assert_eq!(text(&editor), "g h\n");
+    }
+
+    #[test]
+    fn paste_lines_below_and_above() {
+        let mut after = editor("a\nb\n", 0);
+        press(&mut after, "yyjp");
+        assert_eq!(text(&after), "a\nb\na\n");
+        assert_eq!(cursor(&after), 4);
+
+        let mut before = editor("a\nb\n", 0);
+        press(&mut before, "yyj<S-P>");
+        assert_eq!(text(&before), "a\na\nb\n");
+        assert_eq!(cursor(&before), 2);
+    }
+
+    #[test]
+    fn paste_lines_below_the_last_line_without_a_newline() {
+        let mut editor = editor("a\nb", 0);
+        press(&mut editor, "yyjp");
+        assert_eq!(text(&editor), "a\nb\na");
+        assert_eq!(cursor(&editor), 4);
+    }
+
+    #[test]
+    fn paste_chars_after_and_before_the_cursor() {
+        let mut after = editor("abc", 0);
+        press(&mut after, "yl$p");
+        assert_eq!(text(&after), "abca");
+
+        let mut before = editor("abc", 0);
+        press(&mut before, "yl$<S-P>");
+        assert_eq!(text(&before), "abac");
+    }
+
+    #[test]
+    fn paste_count_copies() {
+        let mut lines = editor("a\n", 0);
+        press(&mut lines, "yy3p");
+        assert_eq!(text(&lines), "a\na\na\na\n");
+
+        let mut chars = editor("abc", 0);
+        press(&mut chars, "yl$3p");
+        assert_eq!(text(&chars), "abcaaa");
+
+        // all copies are undone at once
+        press(&mut chars, "u");
+        assert_eq!(text(&chars), "abc");
+    }
+
+    #[test]
+    fn undo_in_a_huge_file_says_there_is_no_history() {
+        let mut editor = editor("abc", 0);
+        editor.current_mut().buffer.huge = true;
+        press(&mut editor, "x");
+        press(&mut editor, "u");
+        assert_eq!(text(&editor), "bc");
+        assert_eq!(editor.status, "no undo history for huge files");
+        assert!(editor.status_is_error);
+    }
+
+    /// where the cursor stops after each press of `key`, starting at `from`
+    fn stops(text: &str, from: usize, key: &str, presses: usize) -> Vec<usize> {
+        let mut editor = editor(text, from);
+        (0..presses)
+            .map(|_| {
+                press(&mut editor, key);
+                cursor(&editor)
+            })
+            .collect()
+    }
+
+    /// two empty lines, a line of only spaces and a line with two words
+    const BLANK_LINES: &str = "foo\n\n\nbar\n  \nbaz qux\n";
+
+    #[test]
+    fn next_word_stops_on_empty_lines_but_not_on_blank_lines() {
+        assert_eq!(stops(BLANK_LINES, 0, "w", 5), [4, 5, 6, 13, 17]);
+    }
+
+    #[test]
+    fn prev_word_stops_on_empty_lines_but_not_on_blank_lines() {
+        assert_eq!(stops(BLANK_LINES, 17, "b", 5), [13, 6, 5, 4, 0]);
+    }
+
+    #[test]
+    fn word_end_skips_empty_lines() {
+        assert_eq!(stops(BLANK_LINES, 0, "e", 4), [2, 8, 15, 19]);
+    }
+
+    #[test]
+    fn punctuation_is_its_own_word() {
+        assert_eq!(stops("foo.bar baz", 0, "w", 3), [3, 4, 8]);
+        assert_eq!(stops("foo.bar baz", 0, "e", 3), [2, 3, 6]);
+        assert_eq!(stops("foo.bar baz", 8, "b", 3), [4, 3, 0]);
+    }
+
+    #[test]
+    fn counted_word_motions() {
+        assert_eq!(stops(BLANK_LINES, 0, "3w", 1), [6]);
+        assert_eq!(stops(BLANK_LINES, 17, "2b", 1), [6]);
+    }
+
+    /// an editor with `text` and a 4 column tabstop, running `command`
+    fn retab_editor(text: &str, command: &str) -> Editor {
+        let mut editor = editor(text, 0);
+        editor.current_mut().buffer.options.tabstop = 4;
+        editor.command = command.to_string();
+        editor
+    }
+
+    #[test]
+    fn retab_round_trips_the_indentation() {
+        let tabs = "\tfoo\n\t\tbar\tbaz\nqux\n";
+        let mut editor = retab_editor(tabs, "retab");
+        Retab.run(&mut editor);
+        assert_eq!(text(&editor), "    foo\n        bar\tbaz\nqux\n");
+
+        editor.command = "retab!".to_string();
+        RetabBang.run(&mut editor);
+        assert_eq!(text(&editor), tabs);
+    }
+
+    #[test]
+    fn retab_converts_mixed_indentation_and_partial_tabs() {
+        let mut editor = retab_editor("  \tx\n      y\n", "retab");
+        Retab.run(&mut editor);
+        assert_eq!(text(&editor), "    x\n      y\n");
+
+        RetabBang.run(&mut editor);
+        assert_eq!(text(&editor), "\tx\n\t  y\n");
+    }
+
+    #[test]
+    fn retab_converts_only_the_given_lines() {
+        let mut editor = retab_editor("\ta\n\tb\n\tc\n", "retab 2,3");
+        Retab.run(&mut editor);
+        assert_eq!(text(&editor), "\ta\n    b\n    c\n");
+
+        editor.command = "retab! 3".to_string();
+        RetabBang.run(&mut editor);
+        assert_eq!(text(&editor), "\ta\n    b\n\tc\n");
+
+        editor.command = "retab 3,2".to_string();
+        Retab.run(&mut editor);
+        assert_eq!(editor.status, "invalid argument: 3,2");
+        assert!(editor.status_is_error);
+    }
+
+    #[test]
+    fn entab_is_an_alias_of_retab_bang() {
+        let mut editor = retab_editor("    a\n", "");
+        press(&mut editor, ":entab<enter>");
+        assert_eq!(text(&editor), "\ta\n");
+        assert!(DEFAULT_ACTIONS.get("entab").is_none());
+    }
+
+    #[test]
+    fn retab_all_converts_tabs_inside_lines() {
+        let mut editor = retab_editor("a\tb\n\tab\tc\n", "retab all");
+        Retab.run(&mut editor);
+        assert_eq!(text(&editor), "a   b\n    ab  c\n");
+    }
+
+    #[test]
+    fn retab_is_undone_at_once() {
+        let mut editor = retab_editor("\ta\n\tb\n", "retab");
+        Retab.run(&mut editor);
+        editor.current_mut().buffer.undo();
+        assert_eq!(text(&editor), "\ta\n\tb\n");
+    }
+
+    #[test]
+    fn retab_without_tabs_changes_nothing() {
+        let mut editor = retab_editor("    a\n", "retab");
+        Retab.run(&mut editor);
+        assert_eq!(text(&editor), "    a\n");
+        assert_eq!(editor.status, "nothing to convert");
+    }
+
+    #[test]
+    fn append_after_the_cursor() {
+        let mut editor = editor("ab\ncd\n", 1);
+        press(&mut editor, "ax<esc>");
+        assert_eq!(text(&editor), "abx\ncd\n");
+        assert_eq!(cursor(&editor), 2);
+    }
+
+    #[test]
+    fn append_at_the_end_of_the_buffer() {
+        let mut editor = editor("ab", 1);
+        press(&mut editor, "ax<esc>");
+        assert_eq!(text(&editor), "abx");
+        assert_eq!(cursor(&editor), 2);
+    }
+
+    #[test]
+    fn append_on_an_empty_line_stays_on_it() {
+        let mut editor = editor("a\n\nb\n", 2);
+        press(&mut editor, "ax<esc>");
+        assert_eq!(text(&editor), "a\nx\nb\n");
+        assert_eq!(cursor(&editor), 2);
+    }
+
+    #[test]
+    fn append_at_the_end_of_the_last_line() {
+        let mut newline = editor("ab\ncd\n", 3);
+        press(&mut newline, "Ax<esc>");
+        assert_eq!(text(&newline), "ab\ncdx\n");
+        assert_eq!(cursor(&newline), 5);
+
+        let mut no_newline = editor("ab\ncd", 3);
+        press(&mut no_newline, "Ax<esc>");
+        assert_eq!(text(&no_newline), "ab\ncdx");
+        assert_eq!(cursor(&no_newline), 5);
+    }
+
+    #[test]
+    fn append_at_the_end_of_a_crlf_line() {
+        let mut editor = editor("ab\r\ncd\r\n", 0);
+        press(&mut editor, "Ax<esc>");
+        assert_eq!(text(&editor), "abx\r\ncd\r\n");
+    }
+
+    #[test]
+    fn up_after_end_goes_to_the_end_of_the_line_above() {
+        let mut editor = editor("abcdef\nab\n", 7);
+        press(&mut editor, "$");
+        assert_eq!(cursor(&editor), 9);
+        press(&mut editor, "k");
+        assert_eq!(cursor(&editor), 6);
+        press(&mut editor, "j");
+        assert_eq!(cursor(&editor), 9);
+    }
+
+    #[test]
+    fn end_sticks_over_shorter_lines() {
+        let mut editor = editor("abcdef\nab\nabcdef\n", 0);
+        press(&mut editor, "<end>jj");
+        assert_eq!(cursor(&editor), 16);
+    }
+
+    #[test]
+    fn home_resets_the_sticky_column() {
+        let mut editor = editor("abcdef\nab\nabcdef\n", 0);
+        press(&mut editor, "$jj0k");
+        assert_eq!(cursor(&editor), 7);
+    }
+
+    #[test]
+    fn other_moves_reset_the_sticky_column() {
+        let mut editor = editor("abcdef\nab\nabcdef\n", 4);
+        press(&mut editor, "jhj");
+        assert_eq!(cursor(&editor), 11);
+    }
+
+    #[test]
+    fn buffer_prev_and_next_wrap_around() {
+        let mut editor = editor("", 0);
+        editor.buffers.push(Buffer::new());
+        editor.buffers.push(Buffer::new());
+
+        BufferPrev.run(&mut editor);
+        assert_eq!(editor.view.buffer_index, 2);
+        BufferPrev.run(&mut editor);
+        assert_eq!(editor.view.buffer_index, 1);
+        BufferNext.run(&mut editor);
+        BufferNext.run(&mut editor);
+        assert_eq!(editor.view.buffer_index, 0);
+    }
+
+    #[test]
+    fn move_a_line_down() {
+        let mut editor = editor("a\nb\nc\n", 0);
+        assert!(move_lines(&mut editor, true));
+        assert_eq!(text(&editor), "b\na\nc\n");
+        assert_eq!(cursor(&editor), 2);
+    }
+
+    #[test]
+    fn move_a_line_down_past_the_last_line_without_a_newline() {
+        let mut editor = editor("a\nb", 0);
+        assert!(move_lines(&mut editor, true));
+        assert_eq!(text(&editor), "b\na");
+        assert_eq!(cursor(&editor), 2);
+
+        // moving the line back is one undo step
+        editor.current_mut().buffer.undo();
+        assert_eq!(text(&editor), "a\nb");
+    }
+
+    #[test]
+    fn move_the_last_line_without_a_newline_up() {
+        let mut editor = editor("a\nb", 2);
+        assert!(move_lines(&mut editor, false));
+        assert_eq!(text(&editor), "b\na");
+        assert_eq!(cursor(&editor), 0);
+    }
+
+    #[test]
+    fn the_first_line_does_not_move_up() {
+        let mut editor = editor("a\nb\n", 0);
+        assert!(!move_lines(&mut editor, false));
+        assert_eq!(text(&editor), "a\nb\n");
+    }
+}
@@ -0,0 +1,374 @@
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::Style,
+    text::Line,
+    widgets::{Block, Widget},
+    Frame,
+};
+use similar::{DiffTag, TextDiff};
+
+use crate::buffer::Buffer;
+
+use super::{
+    theme,
+    view::{self, BufferView, Visible},
+};
+
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    Equal,
+    Changed,
+    Inserted,
+    Deleted,
+}
+
+/// one aligned row of a diff, `None` lines are filler rows
+#[derive(Debug, Clone, Copy)]
+pub struct DiffRow {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub kind: RowKind,
+}
+
+/// two buffers side by side, with matching lines aligned and differences highlighted
+pub struct DiffView {
+    /// the buffer on the left, usually a saved copy
+    pub left: usize,
+    /// the buffer on the right, the one being edited
+    pub right: usize,
+    rows: Vec<DiffRow>,
+    /// the first row of each hunk
+    hunks: Vec<usize>,
+    /// buffer revisions the rows were computed from
+    revisions: Option<(u64, u64)>,
+    /// the first visible row, shared by both sides
+    scroll: usize,
+    /// the widest line numbers seen, the gutter only grows so the text doesn't shift
+    number_width: u16,
+}
+
+impl DiffView {
+    pub const fn new(left: usize, right: usize) -> Self {
+        Self {
+            left,
+            right,
+            rows: Vec::new(),
+            hunks: Vec::new(),
+            revisions: None,
+            scroll: 0,
+            number_width: 0,
+        }
+    }
+
+    /// compute the diff again, even if the buffer revisions didn't change
+    pub fn invalidate(&mut self) {
+        self.revisions = None;
+    }
+
+    /// returns true if both buffers still exist
+    pub fn is_valid(&self, buffers: &[Buffer]) -> bool {
+        self.left < buffers.len() && self.right < buffers.len()
+    }
+
+    /// recompute the rows, if either buffer was edited
+    pub fn update(&mut self, buffers: &[Buffer]) {
+        let (left, right) = (&buffers[self.left], &buffers[self.right]);
+        let revisions = Some((left.revision, right.revision));
+        if self.revisions == revisions {
+            return;
+        }
+        self.revisions = revisions;
+
+        let old = left.contents.to_string();
+        let new = right.contents.to_string();
+        let diff = TextDiff::from_lines(&old, &new);
+
+        self.rows.clear();
+        self.hunks.clear();
+        for op in diff.ops() {
+            let (old_range, new_range) = (op.old_range(), op.new_range());
+
+            if op.tag() == DiffTag::Equal {
+                self.rows
+                    .extend(old_range.zip(new_range).map(|(left, right)| DiffRow {
+                        left: Some(left),
+                        right: Some(right),
+                        kind: RowKind::Equal,
+                    }));
+                continue;
+            }
+
+            // replaced lines are paired up, the rest get filler rows on the other side
+            self.hunks.push(self.rows.len());
+            for i in 0..old_range.len().max(new_range.len()) {
+                let left = (i < old_range.len()).then_some(old_range.start + i);
+                let right = (i < new_range.len()).then_some(new_range.start + i);
+                let kind = match (left, right) {
+                    (Some(_), Some(_)) => RowKind::Changed,
+                    (Some(_), None) => RowKind::Deleted,
+                    _ => RowKind::Inserted,
+                };
+                self.rows.push(DiffRow { left, right, kind });
+            }
+        }
+    }
+
+    /// the right side line of the next hunk after `line`
+    pub fn next_hunk(&self, line: usize) -> Option<usize> {
+        self.hunk_lines().find(|hunk| *hunk > line)
+    }
+
+    /// the right side line of the previous hunk before `line`
+    pub fn prev_hunk(&self, line: usize) -> Option<usize> {
+        self.hunk_lines().filter(|hunk| *hunk < line).last()
+    }
+
+    fn hunk_lines(&self) -> impl Iterator<Item = usize> + '_ {
+        self.hunks.iter().filter_map(|hunk| {
+            // deleted lines don't exist on the right side, so use the closest line
+            self.rows[*hunk..]
+                .iter()
+                .find_map(|row| row.right)
+                .or_else(|| self.rows[..*hunk].iter().rev().find_map(|row| row.right))
+        })
+    }
+
+    /// render both buffers side by side, returns the real cursor position
+    pub fn render(
+        &mut self,
+        buffers: &[Buffer],
+        view: &BufferView,
+        mode: &str,
+        is_insert_mode: bool,
+        area: Rect,
+        frame: &mut Frame,
+    ) -> (usize, usize) {
+        let theme = theme::current();
+        self.update(buffers);
+
+        let (left, right) = (&buffers[self.left], &buffers[self.right]);
+
+        let [panes_area, bufferline_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+        let [left_area, _, right_area] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+        ])
+        .areas(panes_area);
+
+        let row = right.contents.char_to_line(view.cursor);
+        let before = right
+            .contents
+            .slice(right.contents.line_to_char(row)..view.cursor);
+        let col = view::columns_after(before.chars(), 0, right.options.tabstop);
+        let cursor_row = self
+            .rows
+            .iter()
+            .position(|diff_row| diff_row.right == Some(row))
+            .unwrap_or(0);
+
+        // keep the cursor within view, both sides scroll together
+        let min = (cursor_row + 3).saturating_sub(panes_area.height as usize);
+        let max = cursor_row;
+        self.scroll = self.scroll.clamp(min.min(max), max);
+
+        let lines = left.contents.len_lines().max(right.contents.len_lines());
+        self.number_width = self.number_width.max(view::number_width(lines));
+        let numberwidth = right.options.numberwidth.min(u16::MAX as usize) as u16;
+        let gutter = self.number_width.max(numberwidth) + 1;
+        let rows = &self.rows[self.scroll.min(self.rows.len())..];
+
+        frame.render_widget(
+            DiffPane {
+                buffer: left,
+                rows,
+                is_left: true,
+                gutter,
+                cursor: None,
+            },
+            left_area,
+        );
+        frame.render_widget(
+            DiffPane {
+                buffer: right,
+                rows,
+                is_left: false,
+                gutter,
+                cursor: Some((cursor_row - self.scroll, col, is_insert_mode)),
+            },
+            right_area,
+        );
+
+        // render the buffer line
+        let right_name = if right.modified {
+            Line::from_iter([right.name.as_ref(), " [+] "])
+        } else {
+            Line::from_iter([right.name.as_ref(), " "])
+        };
+        let info = Block::new()
+            .title(Line::from_iter([" ", mode, "   ", left.name.as_ref()]).left_aligned())
+            .title(right_name.right_aligned())
+            .style(Style::new().bg(theme.buffer_line));
+        frame.render_widget(info, bufferline_area);
+
+        (
+            panes_area.y as usize + cursor_row - self.scroll,
+            right_area.x as usize + gutter as usize + col,
+        )
+    }
+}
+
+//
+
+struct DiffPane<'a> {
+    buffer: &'a Buffer,
+    rows: &'a [DiffRow],
+    is_left: bool,
+    gutter: u16,
+    /// the cursor row, display column and if it is in insert mode
+    cursor: Option<(usize, usize, bool)>,
+}
+
+impl Widget for DiffPane<'_> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let theme = theme::current();
+        for (i, row) in self.rows.iter().take(area.height as usize).enumerate() {
+            let y = area.y + i as u16;
+            let line = if self.is_left { row.left } else { row.right };
+
+            let bg = match (row.kind, line) {
+                (RowKind::Changed, _) => theme.diff_changed,
+                (RowKind::Inserted, Some(_)) => theme.diff_added,
+                (RowKind::Deleted, Some(_)) => theme.diff_removed,
+                _ => theme.background,
+            };
+            let row_area = Rect {
+                x: area.x,
+                y,
+                width: area.width,
+                height: 1,
+            };
+            buf.set_style(row_area, Style::new().bg(bg));
+
+            let Some(line) = line else {
+                // the other side has lines here
+                for x in area.left()..area.right() {
+                    buf[(x, y)].set_char('╱').set_fg(theme.inactive);
+                }
+                continue;
+            };
+
+            buf.set_stringn(
+                area.x,
+                y,
+                format!("{:>width$} ", line + 1, width = self.gutter as usize - 1),
+                self.gutter as usize,
+                Style::new().fg(theme.inactive),
+            );
+
+            // drawn like in the buffer view, so control chars don't reach the terminal
+            let tabstop = self.buffer.options.tabstop;
+            let text_x = area.x + self.gutter;
+            let mut col = 0;
+            for ch in self.buffer.contents.line(line).chars() {
+                if ch == '\n' || ch == '\r' {
+                    continue;
+                }
+                let width = view::char_width_at(ch, col, tabstop);
+                let x = text_x as usize + col;
+                col += width;
+                if x + width > area.right() as usize {
+                    break;
+                }
+
+                let x = x as u16;
+                let inactive = Style::new().fg(theme.inactive);
+                match view::visible(ch) {
+                    // combining marks and other zero width chars join the previous cell
+                    Visible::Char(ch) if width == 0 => {
+                        if x > text_x {
+                            let cell = &mut buf[(x - 1, y)];
+                            let symbol = format!("{}{ch}", cell.symbol());
+                            cell.set_symbol(&symbol);
+                        }
+                    }
+                    // tabs are drawn as spaces up to the next tab stop
+                    Visible::Char('\t') => {
+                        for x in x..x + width as u16 {
+                            buf[(x, y)].set_char(' ');
+                        }
+                    }
+                    Visible::Char(ch) => {
+                        buf[(x, y)].set_char(ch);
+                        // the terminal draws wide chars over the next cell too
+                        for x in x + 1..x + width as u16 {
+                            buf[(x, y)].reset();
+                            buf[(x, y)].set_bg(bg);
+                        }
+                    }
+                    Visible::Caret(ch) => {
+                        buf[(x, y)].set_char('^').set_style(inactive);
+                        buf[(x + 1, y)].set_char(ch).set_style(inactive);
+                    }
+                    Visible::Replacement => {
+                        buf[(x, y)].set_char('\u{FFFD}').set_style(inactive);
+                    }
+                }
+            }
+
+            if let Some((cursor_row, cursor_col, is_insert_mode)) = self.cursor {
+                let x = area.x as usize + self.gutter as usize + cursor_col;
+                if cursor_row == i && !is_insert_mode && x < area.right() as usize {
+                    buf[(x as u16, y)]
+                        .set_bg(theme.cursor)
+                        .set_fg(theme.background);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    /// the symbols of the right pane with one line of `text`, after a 2 column gutter
+    fn render(text: &str, width: u16) -> String {
+        let mut buffer = Buffer::new();
+        buffer.contents = Rope::from_str(text);
+        buffer.options.tabstop = 4;
+        let rows = [DiffRow {
+            left: Some(0),
+            right: Some(0),
+            kind: RowKind::Equal,
+        }];
+        let area = Rect::new(0, 0, width, 1);
+        let mut buf = ratatui::prelude::Buffer::empty(area);
+        DiffPane {
+            buffer: &buffer,
+            rows: &rows,
+            is_left: false,
+            gutter: 2,
+            cursor: None,
+        }
+        .render(area, &mut buf);
+        buf.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn tabs_and_control_chars_are_drawn_visibly() {
+        assert_eq!(render("a\tb\x01\x7F\n", 12), "1 a   b^A^? ");
+    }
+
+    #[test]
+    fn wide_chars_take_two_cells() {
+        assert_eq!(render("漢字x\n", 8), "1 漢 字 x ");
+        // a wide char that doesn't fit is left out
+        assert_eq!(render("a漢\n", 4), "1 a ");
+    }
+}
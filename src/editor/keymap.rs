@@ -1,15 +1,22 @@
-use core::panic;
 use std::{
+    any::Any,
     borrow::Borrow,
     cmp::Ordering,
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
     hash::Hash,
-    sync::{Arc, LazyLock},
-    thread,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, LazyLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
 };
 
 use arc_swap::ArcSwap;
-use crossterm::event::{KeyCode, KeyModifiers};
+use crossterm::event::{KeyCode, KeyModifiers, MediaKeyCode};
+use eyre::{bail, eyre, Result};
 
 use crate::mode::Mode;
 
@@ -20,21 +27,108 @@ use super::{
 
 //
 
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Keymap {
     inner: Arc<ArcSwap<KeymapInner>>,
+    stop_tx: Sender<()>,
+    reloader: Option<JoinHandle<()>>,
 }
 
 impl Keymap {
     pub fn load() -> Self {
-        let inner = Arc::new(ArcSwap::new(<_>::default()));
+        let keymap = KeymapInner::default();
+        validate(&*keymap.normal);
+        validate(&*keymap.insert);
+        validate(&*keymap.command);
+        validate(&*keymap.visual);
+
+        let inner = Arc::new(ArcSwap::from_pointee(keymap));
+        let (stop_tx, stop_rx) = channel();
 
         let inner2 = inner.clone();
-        thread::spawn(move || {
-            // TODO: auto reload
-            _ = inner2;
+        let mut last_modified = [None; 2];
+        let reloader = thread::spawn(move || loop {
+            // a panic while reloading must not take the editor down,
+            // the last good keymap stays in use and the next reload tries again
+            if let Err(err) = panic::catch_unwind(AssertUnwindSafe(|| {
+                Self::reload(&inner2, &mut last_modified)
+            })) {
+                tracing::error!("keymap reload panicked: {}", panic_message(&*err));
+            }
+
+            match stop_rx.recv_timeout(RELOAD_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+            }
         });
 
-        Self { inner }
+        Self {
+            inner,
+            stop_tx,
+            reloader: Some(reloader),
+        }
+    }
+
+    /// the built-in keymap, without the user config and never reloaded
+    #[cfg(test)]
+    pub fn builtin() -> Self {
+        let (stop_tx, _) = channel();
+        Self {
+            inner: Arc::new(ArcSwap::from_pointee(KeymapInner::default())),
+            stop_tx,
+            reloader: None,
+        }
+    }
+
+    /// reload the user keymap config, if it was modified since the last reload,
+    /// the config is either `keymap.ron` or `keymap.toml`, if both exist neither is loaded
+    fn reload(inner: &ArcSwap<KeymapInner>, last_modified: &mut [Option<SystemTime>; 2]) {
+        let ron_path = crate::config_dir().join("keymap.ron");
+        let toml_path = crate::config_dir().join("keymap.toml");
+        let modified = [&ron_path, &toml_path]
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok());
+        if modified == *last_modified {
+            return;
+        }
+        *last_modified = modified;
+
+        let (path, is_toml) = match modified {
+            [None, None] => {
+                // the config was removed
+                inner.store(<_>::default());
+                return;
+            }
+            [Some(_), Some(_)] => {
+                // the last good keymap stays in use, until one of them is removed
+                tracing::error!(
+                    "both {ron_path:?} and {toml_path:?} exist, remove one of them to load the keymap"
+                );
+                return;
+            }
+            [Some(_), None] => (ron_path, false),
+            [None, Some(_)] => (toml_path, true),
+        };
+
+        let keymap = fs::read_to_string(&path)
+            .map_err(Into::into)
+            .and_then(|config| {
+                if is_toml {
+                    KeymapInner::from_toml(&config)
+                } else {
+                    KeymapInner::from_config(&config)
+                }
+            });
+
+        match keymap {
+            Ok(keymap) => {
+                tracing::info!("loaded keymap from {path:?}");
+                inner.store(Arc::new(keymap));
+            }
+            Err(err) => {
+                tracing::error!("failed to load keymap from {path:?}: {err}");
+            }
+        }
     }
 
     pub fn normal(&self) -> Arc<dyn Layer> {
@@ -48,6 +142,29 @@ impl Keymap {
     pub fn command(&self) -> Arc<dyn Layer> {
         self.inner.load().command.clone()
     }
+
+    pub fn visual(&self) -> Arc<dyn Layer> {
+        self.inner.load().visual.clone()
+    }
+}
+
+impl Drop for Keymap {
+    fn drop(&mut self) {
+        _ = self.stop_tx.send(());
+        if let Some(reloader) = self.reloader.take() {
+            _ = reloader.join();
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
 }
 
 //
@@ -122,6 +239,22 @@ pub trait Action: Sync + Send {
     }
 
     fn run(&self, editor: &mut Editor);
+
+    /// how an operator like `d` treats the text this action moves over,
+    /// `None` if the action isn't a motion
+    fn motion(&self) -> Option<Motion> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// up to the char the cursor lands on, like `w`
+    Exclusive,
+    /// up to and including the char the cursor lands on, like `e`
+    Inclusive,
+    /// every line between the cursor and where it lands, like `j`
+    Linewise,
 }
 
 pub trait ActionExt: Action {
@@ -153,6 +286,12 @@ pub trait Layer: Sync + Send {
         None
     }
 
+    /// returns true if the layer is an operator like `d`, that waits for a motion,
+    /// a count can be typed between them
+    fn is_operator(&self) -> bool {
+        false
+    }
+
     /// returns true if the layer is now done with actions and the original layer can be restored
     fn run(&self, keycode: Code, editor: &mut Editor) -> bool {
         let Some(next) = self.get(keycode) else {
@@ -165,12 +304,17 @@ pub trait Layer: Sync + Send {
 
         match next {
             Entry::Layer(layer) => {
+                // the count before the operator is kept apart, the motion can have its own
+                if layer.is_operator() {
+                    editor.operator_count = editor.count.take();
+                }
                 editor.mode = Mode::Action {
                     layer,
                     prev: editor.mode.prev(),
                 };
             }
             Entry::Action(action) => {
+                editor.log_action(Some(keycode), action.name());
                 action.run(editor);
                 editor.mode = editor.mode.prev().mode();
             }
@@ -205,7 +349,7 @@ impl LayerBase {
 
         if let Some(all) = Arc::get_mut(&mut all) {
             all.sort_by_key(|(code, _)| {
-                let mut buf = [const { 0 }; 16];
+                let mut buf = [0; Code::MAX_NAME_LEN];
                 code.as_str(&mut buf);
                 buf
             });
@@ -321,6 +465,24 @@ impl Layer for Command {
 
 //
 
+pub struct Visual(LayerBase);
+
+impl Layer for Visual {
+    fn name(&self) -> &str {
+        "visual"
+    }
+
+    fn get(&self, keycode: Code) -> Option<Entry> {
+        self.0.get(keycode)
+    }
+
+    fn entries(&self) -> Arc<[(Code, Entry)]> {
+        self.0.entries()
+    }
+}
+
+//
+
 pub static DEFAULT_ACTIONS: LazyLock<BTreeSet<ActionEntry>> = LazyLock::new(|| {
     BTreeSet::from_iter(
         act::all_actions()
@@ -387,18 +549,53 @@ impl<'a, I: Iterator<Item = (&'a str, &'a str)> + 'a> IterKeymap<'a> for I {
 
 macro_rules! map {
     ($($key:literal: $act:expr,)*) => {{
-        HashMap::from_iter([
-            $((const { Code::from_str($key) }, Entry::from($act)),)*
+        layer_map([
+            $(($key, const { Code::from_str($key) }, Entry::from($act)),)*
         ])
     }};
 
     ($base:ident, $($key:literal: $act:expr,)*) => {{
-        $base.extend([
-            $((const { Code::from_str($key) }, Entry::from($act)),)*
-        ]);
+        $base.extend(layer_map([
+            $(($key, const { Code::from_str($key) }, Entry::from($act)),)*
+        ]));
     }};
 }
 
+/// collect keys into a layer, warning about keys that are mapped more than once
+fn layer_map<const N: usize>(entries: [(&str, Code, Entry); N]) -> HashMap<Code, Entry> {
+    let mut map = HashMap::with_capacity(N);
+    for (key, code, entry) in entries {
+        if let Some(old) = map.get(&code) {
+            tracing::warn!(
+                "key `{key}` is mapped twice, `{}` is shadowed by `{}`",
+                old.name(),
+                entry.name()
+            );
+        }
+        map.insert(code, entry);
+    }
+    map
+}
+
+/// warn about mapped actions that are not in [`act::all_actions`]
+fn validate(layer: &dyn Layer) {
+    for (code, entry) in layer.entries().iter() {
+        match entry {
+            Entry::Layer(inner) => validate(&**inner),
+            Entry::Action(act) => {
+                if !DEFAULT_ACTIONS.contains(act.name()) {
+                    tracing::warn!(
+                        "key `{}` in `{}` is mapped to an unregistered action `{}`",
+                        code.as_str(&mut [0; Code::MAX_NAME_LEN]),
+                        layer.name(),
+                        act.name()
+                    );
+                }
+            }
+        }
+    }
+}
+
 static DEFAULT_GLOBAL: LazyLock<HashMap<Code, Entry>> = LazyLock::new(|| {
     map! {
         "esc": act::Escape::arc(),
@@ -420,32 +617,83 @@ static DEFAULT_NORMAL: LazyLock<Arc<dyn Layer>> = LazyLock::new(|| {
         "j":         act::MoveDown::arc(),
         "pageup":    act::MovePageUp::arc(),
         "pagedown":  act::MovePageDown::arc(),
-        "home":      act::MoveLineBeg::arc(),
+        "home":      act::MoveLineHome::arc(),
         "end":       act::MoveLineEnd::arc(),
+        "0":         act::MoveLineBeg::arc(),
+        "$":         act::MoveLineEnd::arc(),
+        "%":         act::MatchBracket::arc(),
         "w":         act::NextWordBeg::arc(),
         "e":         act::NextWordEnd::arc(),
         "b":         act::PrevWordBeg::arc(),
         "i":         act::SwitchToInsert::arc(),
         "I":         act::SwitchToInsertLineBeg::arc(),
         "a":         act::SwitchToAppend::arc(),
+        "S-R":       act::SwitchToReplace::arc(),
         "A":         act::SwitchToAppendLineEnd::arc(),
-        "a":         act::SwitchToAppend::arc(),
+        "v":         act::SwitchToVisual::arc(),
+        "S-V":       act::SwitchToVisualLine::arc(),
         ":":         act::SwitchToCommand::arc(),
+        "/":         act::Search::arc(),
+        "n":         act::SearchNext::arc(),
+        "S-N":       act::SearchPrev::arc(),
         "o":         act::InsertLineBelow::arc(),
         "S-O":       act::InsertLineAbove::arc(),
         "f":         act::JumpForwardsTo::arc(),
         "t":         act::JumpForwardsUntil::arc(),
         "S-F":       act::JumpBackwardsTo::arc(),
         "S-T":       act::JumpBackwardsUntil::arc(),
-        "d":         act::Delete::arc(),
+        "d":         act::DeleteMotion::arc(),
+        "c":         act::ChangeMotion::arc(),
+        "S-C":       act::ChangeLineEnd::arc(),
+        "x":         act::Delete::arc(),
+        "delete":    act::Delete::arc(),
+        "insert":    act::SwitchToInsert::arc(),
+        "y":         act::YankMotion::arc(),
+        "S-Y":       act::YankLine::arc(),
+        ">":         act::IndentMotion::arc(),
+        "<":         act::DedentMotion::arc(),
+        "\"":        act::SelectRegister::arc(),
+        "p":         act::PasteAfter::arc(),
+        "S-P":       act::PasteBefore::arc(),
+        "C-p":       act::CyclePaste::arc(),
+        "C-g":       act::FileInfo::arc(),
+        "u":         act::Undo::arc(),
+        "C-r":       act::Redo::arc(),
+        ".":         act::Repeat::arc(),
+        "A-j":       act::MoveLinesDown::arc(),
+        "A-k":       act::MoveLinesUp::arc(),
         "g":         map! {
             "g":         act::MoveBufferBeg::arc(),
             "e":         act::MoveBufferEnd::arc(),
+            "i":         act::SwitchToInsertLast::arc(),
+            "v":         act::ReselectVisual::arc(),
+        },
+        "]":         map! {
+            "c":         act::NextHunk::arc(),
+            "'":         act::NextBookmark::arc(),
+        },
+        "[":         map! {
+            "c":         act::PrevHunk::arc(),
+            "'":         act::PrevBookmark::arc(),
         },
         "space":     map! {
             "n":         act::New::arc(),
             "space":     act::FileExplorer::arc(),
             "b":         act::BufferPicker::arc(),
+            "d":         act::DuplicateLine::arc(),
+            "m":         act::ToggleBookmark::arc(),
+            "s":         act::SplitHorizontal::arc(),
+            "v":         act::SplitVertical::arc(),
+            ":":         act::CommandPalette::arc(),
+        },
+        "C-w":       map! {
+            "h":         act::FocusLeft::arc(),
+            "j":         act::FocusDown::arc(),
+            "k":         act::FocusUp::arc(),
+            "l":         act::FocusRight::arc(),
+            "s":         act::SplitHorizontal::arc(),
+            "v":         act::SplitVertical::arc(),
+            "q":         act::SplitClose::arc(),
         },
     }
     Arc::new(Normal(LayerBase::new(normal))) as _
@@ -461,13 +709,29 @@ static DEFAULT_INSERT: LazyLock<Arc<dyn Layer>> = LazyLock::new(|| {
         "down":      act::MoveDown::arc(),
         "pageup":    act::MovePageUp::arc(),
         "pagedown":  act::MovePageDown::arc(),
-        "home":      act::MoveLineBeg::arc(),
+        "home":      act::MoveLineHome::arc(),
         "end":       act::MoveLineEnd::arc(),
         "backspace": act::Backspace::arc(),
+        "delete":    act::Delete::arc(),
+        "insert":    act::ToggleReplace::arc(),
     }
     Arc::new(Insert(LayerBase::new(insert))) as _
 });
 
+static DEFAULT_VISUAL: LazyLock<Arc<dyn Layer>> = LazyLock::new(|| {
+    // the normal keys, but operators act on the selection right away
+    let mut visual: HashMap<Code, Entry> = DEFAULT_NORMAL.entries().iter().cloned().collect();
+    map! {
+        visual,
+        "d":         act::Delete::arc(),
+        "c":         act::ChangeSelection::arc(),
+        "y":         act::Yank::arc(),
+        ">":         act::IndentSelection::arc(),
+        "<":         act::DedentSelection::arc(),
+    }
+    Arc::new(Visual(LayerBase::new(visual))) as _
+});
+
 static DEFAULT_COMMAND: LazyLock<Arc<dyn Layer>> = LazyLock::new(|| {
     let mut command = DEFAULT_GLOBAL.clone();
     map! {
@@ -475,6 +739,8 @@ static DEFAULT_COMMAND: LazyLock<Arc<dyn Layer>> = LazyLock::new(|| {
         "backspace": act::Backspace::arc(),
         "tab":       act::NextSuggestion::arc(),
         "S-tab":     act::PrevSuggestion::arc(),
+        "up":        act::HistoryPrev::arc(),
+        "down":      act::HistoryNext::arc(),
     };
     Arc::new(Command(LayerBase::new(command))) as _
 });
@@ -485,6 +751,105 @@ pub struct KeymapInner {
     normal: Arc<dyn Layer>,
     insert: Arc<dyn Layer>,
     command: Arc<dyn Layer>,
+    visual: Arc<dyn Layer>,
+}
+
+impl KeymapInner {
+    /// the default keymap, with the user keymap config on top of it,
+    /// user bindings always override the default bindings of the same key
+    ///
+    /// ```ron
+    /// {
+    ///     "normal": {
+    ///         "g l": "move-line-end",
+    ///         "d": false, // unbind
+    ///     },
+    /// }
+    /// ```
+    pub fn from_config(config: &str) -> Result<Self> {
+        let config: BTreeMap<String, BTreeMap<String, ron::Value>> = ron::from_str(config)?;
+
+        let config = config
+            .into_iter()
+            .map(|(mode, bindings)| {
+                let bindings = bindings
+                    .into_iter()
+                    .map(|(keys, action)| {
+                        let action = match action {
+                            ron::Value::String(action) => Ok(Some(action)),
+                            ron::Value::Bool(false) => Ok(None),
+                            other => {
+                                Err(eyre!("expected an action name or false, got `{other:?}`"))
+                            }
+                        };
+                        (keys, action)
+                    })
+                    .collect();
+                (mode, bindings)
+            })
+            .collect();
+
+        Ok(Self::from_bindings(config))
+    }
+
+    /// the same as [`Self::from_config`], and tables are the keys after a key
+    ///
+    /// ```toml
+    /// [normal]
+    /// d = false # unbind
+    ///
+    /// [normal.g]
+    /// l = "move-line-end"
+    /// ```
+    pub fn from_toml(config: &str) -> Result<Self> {
+        let config: BTreeMap<String, toml::Table> = toml::from_str(config)?;
+
+        let config = config
+            .into_iter()
+            .map(|(mode, table)| {
+                let mut bindings = Vec::new();
+                toml_bindings("", table, &mut bindings);
+                (mode, bindings)
+            })
+            .collect();
+
+        Ok(Self::from_bindings(config))
+    }
+
+    /// mode, key sequence and action name or `None` to unbind,
+    /// invalid bindings are logged and skipped, so one typo doesn't drop the whole keymap
+    fn from_bindings(config: BTreeMap<String, Vec<(String, Result<Option<String>>)>>) -> Self {
+        let mut keymap = Self::default();
+        for (mode, bindings) in config {
+            let layer = match mode.as_str() {
+                "normal" => &mut keymap.normal,
+                "insert" => &mut keymap.insert,
+                "command" => &mut keymap.command,
+                "visual" => &mut keymap.visual,
+                _ => {
+                    tracing::error!("skipped unknown keymap mode `{mode}`");
+                    continue;
+                }
+            };
+
+            let mut map: HashMap<Code, Entry> = layer.entries().iter().cloned().collect();
+            for (keys, action) in bindings {
+                if let Err(err) = bind_config(&mut map, &keys, action) {
+                    tracing::error!("skipped `{mode}` binding `{keys}`: {err}");
+                }
+            }
+
+            let base = LayerBase::new(map);
+            *layer = match mode.as_str() {
+                "normal" => Arc::new(Normal(base)) as _,
+                "insert" => Arc::new(Insert(base)) as _,
+                "visual" => Arc::new(Visual(base)) as _,
+                _ => Arc::new(Command(base)) as _,
+            };
+        }
+
+        keymap
+    }
 }
 
 impl Default for KeymapInner {
@@ -493,10 +858,195 @@ impl Default for KeymapInner {
             normal: DEFAULT_NORMAL.clone(),
             insert: DEFAULT_INSERT.clone(),
             command: DEFAULT_COMMAND.clone(),
+            visual: DEFAULT_VISUAL.clone(),
         }
     }
 }
 
+/// bind or unbind a key sequence from the config, like `g l`
+fn bind_config(
+    map: &mut HashMap<Code, Entry>,
+    keys: &str,
+    action: Result<Option<String>>,
+) -> Result<()> {
+    let keys = keys
+        .split_whitespace()
+        .map(|key| Code::try_from_str(key).ok_or_else(|| eyre!("invalid key `{key}`")))
+        .collect::<Result<Vec<Code>>>()?;
+    let entry = match action? {
+        Some(action) => Some(
+            Entry::from_action_name(&action).ok_or_else(|| eyre!("unknown action `{action}`"))?,
+        ),
+        None => None,
+    };
+
+    bind(map, &keys, entry)
+}
+
+/// flatten nested toml tables into key sequences, `[normal.g]` with `l` is `g l`
+fn toml_bindings(
+    prefix: &str,
+    table: toml::Table,
+    bindings: &mut Vec<(String, Result<Option<String>>)>,
+) {
+    for (key, value) in table {
+        let keys = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix} {key}")
+        };
+        let action = match value {
+            toml::Value::Table(table) => {
+                toml_bindings(&keys, table, bindings);
+                continue;
+            }
+            toml::Value::String(action) => Ok(Some(action)),
+            toml::Value::Boolean(false) => Ok(None),
+            other => Err(eyre!(
+                "expected an action name, false or a table, got `{other}`"
+            )),
+        };
+        bindings.push((keys, action));
+    }
+}
+
+/// bind or unbind (`entry` is `None`) a key sequence, creating the layers in between,
+/// existing layers are merged, so binding `g l` keeps `g g`
+fn bind(map: &mut HashMap<Code, Entry>, keys: &[Code], entry: Option<Entry>) -> Result<()> {
+    let Some((first, rest)) = keys.split_first() else {
+        bail!("empty key sequence");
+    };
+
+    if rest.is_empty() {
+        if let Some(entry) = entry {
+            map.insert(*first, entry);
+        } else {
+            map.remove(first);
+        }
+        return Ok(());
+    }
+
+    let mut inner: HashMap<Code, Entry> = match map.get(first) {
+        Some(Entry::Layer(layer)) if layer.wildcard().is_some() => {
+            bail!("cannot bind keys inside the `{}` layer", layer.name());
+        }
+        Some(Entry::Layer(layer)) => layer.entries().iter().cloned().collect(),
+        // nothing to unbind
+        _ if entry.is_none() => return Ok(()),
+        // a user binding overrides a default action
+        Some(Entry::Action(_)) | None => HashMap::new(),
+    };
+    bind(&mut inner, rest, entry)?;
+
+    if inner.is_empty() {
+        map.remove(first);
+    } else {
+        map.insert(*first, inner.into());
+    }
+
+    Ok(())
+}
+
+//
+
+/// keys with names, every other key is written as the character itself
+const KEY_NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Esc, "esc"),
+    (KeyCode::Char(' '), "space"),
+    (KeyCode::Backspace, "backspace"),
+    (KeyCode::Left, "left"),
+    (KeyCode::Right, "right"),
+    (KeyCode::Up, "up"),
+    (KeyCode::Down, "down"),
+    (KeyCode::PageUp, "pageup"),
+    (KeyCode::PageDown, "pagedown"),
+    (KeyCode::Home, "home"),
+    (KeyCode::End, "end"),
+    (KeyCode::Tab, "tab"),
+    (KeyCode::Enter, "enter"),
+    (KeyCode::Insert, "insert"),
+    (KeyCode::Delete, "delete"),
+    (KeyCode::Menu, "menu"),
+    (KeyCode::KeypadBegin, "begin"),
+    (KeyCode::CapsLock, "capslock"),
+    (KeyCode::ScrollLock, "scrolllock"),
+    (KeyCode::NumLock, "numlock"),
+    (KeyCode::PrintScreen, "printscreen"),
+    (KeyCode::Pause, "pause"),
+    // media keys are only reported with the kitty keyboard protocol
+    (KeyCode::Media(MediaKeyCode::Play), "media-play"),
+    (KeyCode::Media(MediaKeyCode::Pause), "media-pause"),
+    (KeyCode::Media(MediaKeyCode::PlayPause), "media-playpause"),
+    (KeyCode::Media(MediaKeyCode::Reverse), "media-reverse"),
+    (KeyCode::Media(MediaKeyCode::Stop), "media-stop"),
+    (
+        KeyCode::Media(MediaKeyCode::FastForward),
+        "media-fastforward",
+    ),
+    (KeyCode::Media(MediaKeyCode::Rewind), "media-rewind"),
+    (KeyCode::Media(MediaKeyCode::TrackNext), "media-next"),
+    (KeyCode::Media(MediaKeyCode::TrackPrevious), "media-prev"),
+    (KeyCode::Media(MediaKeyCode::Record), "media-record"),
+    (KeyCode::Media(MediaKeyCode::LowerVolume), "volume-down"),
+    (KeyCode::Media(MediaKeyCode::RaiseVolume), "volume-up"),
+    (KeyCode::Media(MediaKeyCode::MuteVolume), "volume-mute"),
+];
+
+/// the highest function key crossterm reports
+const MAX_FUNCTION_KEY: u8 = 24;
+
+/// the longest key name with all modifiers, like `C-A-S-backspace`
+const fn max_name_len() -> usize {
+    // characters take at most 4 bytes, and function keys 3
+    let mut max = 4;
+    let mut i = 0;
+    while i < KEY_NAMES.len() {
+        if KEY_NAMES[i].1.len() > max {
+            max = KEY_NAMES[i].1.len();
+        }
+        i += 1;
+    }
+    "C-A-S-".len() + max
+}
+
+/// decode `b` if it is exactly one UTF-8 character
+const fn single_char(b: &[u8]) -> Option<char> {
+    let (first, len) = match b {
+        [c @ 0xc0..=0xdf, ..] => ((*c & 0x1f) as u32, 2),
+        [c @ 0xe0..=0xef, ..] => ((*c & 0x0f) as u32, 3),
+        [c @ 0xf0..=0xf7, ..] => ((*c & 0x07) as u32, 4),
+        _ => return None,
+    };
+    if b.len() != len {
+        return None;
+    }
+
+    let mut c = first;
+    let mut i = 1;
+    while i < len {
+        if b[i] & 0xc0 != 0x80 {
+            return None;
+        }
+        c = (c << 6) | (b[i] & 0x3f) as u32;
+        i += 1;
+    }
+    char::from_u32(c)
+}
+
+const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
 //
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Hash, Clone, Copy)]
@@ -506,6 +1056,11 @@ pub struct Code {
 }
 
 impl Code {
+    /// a buffer of this size fits the name of any key, see [`Code::as_str`]
+    pub const MAX_NAME_LEN: usize = max_name_len();
+
+    /// `C-i`, `C-m` and `C-[` stay separate from `tab`, `enter` and `esc`, but only terminals
+    /// with the kitty keyboard protocol report them separately
     pub const fn from_event(mut keycode: KeyCode, mut modifiers: KeyModifiers) -> Self {
         if matches!(keycode, KeyCode::BackTab) {
             keycode = KeyCode::Tab;
@@ -515,7 +1070,7 @@ impl Code {
         Self { keycode, modifiers }
     }
 
-    pub fn as_str<'a>(&self, buf: &'a mut [u8; 16]) -> &'a str {
+    pub fn as_str<'a>(&self, buf: &'a mut [u8; Self::MAX_NAME_LEN]) -> &'a str {
         let mut len = 0usize;
 
         if self.modifiers.contains(KeyModifiers::CONTROL) {
@@ -534,25 +1089,18 @@ impl Code {
             len += 2;
         }
 
-        let other: &[u8] = match self.keycode {
-            KeyCode::Esc => b"esc",
-            KeyCode::Backspace => b"backspace",
-            KeyCode::Left => b"left",
-            KeyCode::Right => b"right",
-            KeyCode::Up => b"up",
-            KeyCode::Down => b"down",
-            KeyCode::PageUp => b"pageup",
-            KeyCode::PageDown => b"pagedown",
-            KeyCode::Home => b"home",
-            KeyCode::End => b"end",
-            KeyCode::Tab => b"tab",
-            KeyCode::Enter => b"enter",
-            KeyCode::Char(' ') => b"space",
-            KeyCode::Char(c) => {
+        let named = KEY_NAMES
+            .iter()
+            .find(|(keycode, _)| *keycode == self.keycode)
+            .map(|(_, name)| name.as_bytes());
+
+        let other: &[u8] = match (named, self.keycode) {
+            (Some(name), _) => name,
+            (None, KeyCode::Char(c)) => {
                 len += c.encode_utf8(&mut buf[len..]).len();
                 return std::str::from_utf8(&buf[..len]).unwrap_or("??");
             }
-            KeyCode::F(n) => {
+            (None, KeyCode::F(n @ 1..=MAX_FUNCTION_KEY)) => {
                 buf[len] = b'f';
                 if n >= 10 {
                     buf[len + 1] = (n / 10) % 10 + b'0';
@@ -562,7 +1110,7 @@ impl Code {
                 len += 2;
                 return std::str::from_utf8(&buf[..len]).unwrap_or("??");
             }
-            _ => b"??",
+            (None, _) => b"??",
         };
 
         buf[len..][..other.len()].copy_from_slice(other);
@@ -628,7 +1176,7 @@ impl Code {
         };
 
         let key = match key {
-            [b'f', num @ ..] if !num.is_empty() => {
+            [b'f', num @ ..] if matches!(num, [b'1'..=b'9', ..]) => {
                 let Ok(num) = std::str::from_utf8(num) else {
                     return None;
                 };
@@ -637,27 +1185,28 @@ impl Code {
                     return None;
                 };
 
-                if num > 24 || num == 0 {
+                if num > MAX_FUNCTION_KEY || num == 0 {
                     return None;
                 }
 
                 KeyCode::F(num)
             }
-            b"esc" => KeyCode::Esc,
-            b"space" => KeyCode::Char(' '),
-            b"backspace" => KeyCode::Backspace,
-            b"left" => KeyCode::Left,
-            b"right" => KeyCode::Right,
-            b"up" => KeyCode::Up,
-            b"down" => KeyCode::Down,
-            b"pageup" => KeyCode::PageUp,
-            b"pagedown" => KeyCode::PageDown,
-            b"home" => KeyCode::Home,
-            b"end" => KeyCode::End,
-            b"tab" => KeyCode::Tab,
-            b"enter" => KeyCode::Enter,
             [c] => KeyCode::Char(*c as char),
-            _ => return None,
+            _ => {
+                let mut i = 0;
+                loop {
+                    if i == KEY_NAMES.len() {
+                        let Some(c) = single_char(key) else {
+                            return None;
+                        };
+                        break KeyCode::Char(c);
+                    }
+                    if bytes_eq(key, KEY_NAMES[i].1.as_bytes()) {
+                        break KEY_NAMES[i].0;
+                    }
+                    i += 1;
+                }
+            }
         };
 
         Some(Self {
@@ -666,3 +1215,156 @@ impl Code {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the name of the action `keys` run in `layer`, `None` if they don't lead to an action
+    fn action(layer: &dyn Layer, keys: &str) -> Option<String> {
+        let mut keys = keys.split_whitespace().map(Code::from_str);
+        let mut entry = layer.get(keys.next()?)?;
+        for key in keys {
+            let Entry::Layer(layer) = entry else {
+                return None;
+            };
+            entry = layer.get(key)?;
+        }
+        match entry {
+            Entry::Action(action) => Some(action.name().to_string()),
+            Entry::Layer(_) => None,
+        }
+    }
+
+    /// every key with a name, every function key and some characters of each utf-8 length
+    fn all_keys() -> impl Iterator<Item = KeyCode> {
+        KEY_NAMES
+            .iter()
+            .map(|(keycode, _)| *keycode)
+            .chain((1..=MAX_FUNCTION_KEY).map(KeyCode::F))
+            .chain("aZ0-SAC\u{e9}\u{4e2d}\u{1f980}".chars().map(KeyCode::Char))
+    }
+
+    /// every combination of the modifiers a key name can have
+    fn all_modifiers() -> [KeyModifiers; 8] {
+        let (c, a, s) = (
+            KeyModifiers::CONTROL,
+            KeyModifiers::ALT,
+            KeyModifiers::SHIFT,
+        );
+        [KeyModifiers::NONE, c, a, s, c | a, c | s, a | s, c | a | s]
+    }
+
+    #[test]
+    fn every_key_name_fits_the_buffer() {
+        for keycode in all_keys() {
+            for modifiers in all_modifiers() {
+                let mut buf = [0; Code::MAX_NAME_LEN];
+                let name = Code { keycode, modifiers }.as_str(&mut buf);
+                assert!(!name.ends_with("??"), "{keycode:?} {modifiers:?}");
+            }
+        }
+        assert_eq!(Code::MAX_NAME_LEN, "C-A-S-media-fastforward".len());
+    }
+
+    #[test]
+    fn every_key_name_round_trips() {
+        for keycode in all_keys() {
+            for modifiers in all_modifiers() {
+                let code = Code { keycode, modifiers };
+                let mut buf = [0; Code::MAX_NAME_LEN];
+                let name = code.as_str(&mut buf);
+                assert_eq!(Code::try_from_str(name), Some(code), "`{name}`");
+            }
+        }
+    }
+
+    #[test]
+    fn function_keys_out_of_range_are_invalid() {
+        assert_eq!(Code::try_from_str("f0"), None);
+        assert_eq!(Code::try_from_str("f25"), None);
+        assert_eq!(Code::try_from_str("f100"), None);
+        assert_eq!(
+            Code::try_from_str("C-f12"),
+            Some(Code {
+                keycode: KeyCode::F(12),
+                modifiers: KeyModifiers::CONTROL,
+            })
+        );
+    }
+
+    #[test]
+    fn back_tab_is_shift_tab() {
+        let code = Code::from_event(KeyCode::BackTab, KeyModifiers::NONE);
+        assert_eq!(Some(code), Code::try_from_str("S-tab"));
+    }
+
+    #[test]
+    fn user_bindings_override_the_defaults() {
+        let keymap = KeymapInner::from_config(r#"{ "normal": { "x": "move-line-end" } }"#).unwrap();
+        assert_eq!(
+            action(&*keymap.normal, "x").as_deref(),
+            Some("move-line-end")
+        );
+        assert_eq!(action(&*keymap.normal, "h").as_deref(), Some("move-left"));
+    }
+
+    #[test]
+    fn false_unbinds_a_key() {
+        let keymap = KeymapInner::from_config(r#"{ "normal": { "x": false } }"#).unwrap();
+        assert!(keymap.normal.get(Code::from_str("x")).is_none());
+    }
+
+    #[test]
+    fn nop_disables_a_key() {
+        let keymap = KeymapInner::from_config(r#"{ "normal": { "x": "nop" } }"#).unwrap();
+        assert_eq!(action(&*keymap.normal, "x").as_deref(), Some("nop"));
+    }
+
+    #[test]
+    fn binding_inside_a_layer_keeps_the_other_keys() {
+        let keymap =
+            KeymapInner::from_config(r#"{ "normal": { "g l": "move-line-end", "g e": false } }"#)
+                .unwrap();
+        assert_eq!(
+            action(&*keymap.normal, "g l").as_deref(),
+            Some("move-line-end")
+        );
+        assert_eq!(
+            action(&*keymap.normal, "g g").as_deref(),
+            Some("move-buffer-beg")
+        );
+        assert_eq!(action(&*keymap.normal, "g e"), None);
+    }
+
+    #[test]
+    fn invalid_bindings_are_skipped() {
+        let keymap = KeymapInner::from_config(
+            r#"{
+                "normal": {
+                    "nokey": "move-line-end",
+                    "x": "no-such-action",
+                    "0": 1,
+                    "h": "move-line-end",
+                },
+                "nomode": { "x": "nop" },
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(action(&*keymap.normal, "x").as_deref(), Some("delete"));
+        assert_eq!(
+            action(&*keymap.normal, "0").as_deref(),
+            Some("move-line-beg")
+        );
+        assert_eq!(
+            action(&*keymap.normal, "h").as_deref(),
+            Some("move-line-end")
+        );
+    }
+
+    #[test]
+    fn invalid_config_is_an_error() {
+        assert!(KeymapInner::from_config(r#"{ "normal": "#).is_err());
+        assert!(KeymapInner::from_config(r#"{ "normal": ["x"] }"#).is_err());
+    }
+}
@@ -1,108 +1,267 @@
 use std::{
     borrow::Cow,
-    mem,
+    collections::VecDeque,
+    fs, io, iter, mem,
+    ops::Range,
+    path::PathBuf,
     sync::mpsc::{channel, Receiver, Sender},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use color_eyre::Result;
 
 use crossterm::{
     cursor::SetCursorStyle,
-    event::{self, Event, KeyEvent, KeyEventKind},
-    execute, terminal,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    style::Print,
+    terminal,
 };
 use ratatui::{
     layout::{Constraint, Layout, Margin, Position, Rect},
     style::{Color, Style, Stylize},
-    text::Line,
-    widgets::{Block, Clear},
+    text::{Line, Span},
+    widgets::{Block, Clear, Scrollbar, ScrollbarOrientation, ScrollbarState},
     DefaultTerminal, Frame,
 };
+use ropey::Rope;
 
 use crate::{
-    buffer::Buffer,
-    mode::{Mode, ModeSubset},
+    buffer::{Buffer, BufferInner, CONN_POOL},
+    mode::{LastVisual, Mode, ModeSubset},
 };
 
 use self::{
-    keymap::{ActionEntry, Code, Keymap},
+    actions::Suggestion,
+    diff::DiffView,
+    keymap::{Code, Keymap, Layer},
+    options::Options,
     popup::Popup,
+    registers::{LastPaste, Registers},
+    search::{SearchOrigin, Substitution},
+    split::Split,
+    theme::ColorSupport,
     view::BufferView,
 };
 
 //
 
 pub mod actions;
+pub mod diff;
 pub mod keymap;
+pub mod options;
 pub mod popup;
+pub mod registers;
+pub mod search;
+pub mod split;
 pub mod theme;
 pub mod view;
 
 //
 
+const REMOTE_BUSY_STATUS: &str = "running remote command… (Esc to cancel)";
+
+const ACTION_LOG_LEN: usize = 100;
+
+/// the most command suggestions shown at once
+const MAX_SUGGESTIONS: usize = 10;
+
+/// suggestions kept visible above and below the selected one
+const SUGGESTION_SCROLL_MARGIN: usize = 2;
+
+/// the most commands kept in the command history
+const COMMAND_HISTORY_LEN: usize = 500;
+
+//
+
 pub struct Editor {
     pub should_close: bool,
     pub size: (u16, u16),
     pub real_cursor: (usize, usize),
 
     pub buffers: Vec<Buffer>,
+    /// the focused split
     pub view: BufferView,
+    /// the split layout and the views of the other splits
+    pub splits: Split,
+    /// side by side diff, shown while the right side buffer is the current buffer
+    pub diff: Option<DiffView>,
     pub open_askpw_rx: Receiver<(String, Sender<String>)>,
     pub open_askpw_tx: Sender<(String, Sender<String>)>,
-    pub open_buffer_rx: Receiver<Result<Buffer>>,
-    pub open_buffer_tx: Sender<Result<Buffer>>,
+    /// buffer path and the result of a background load
+    pub open_buffer_rx: Receiver<(String, Result<Buffer>)>,
+    pub open_buffer_tx: Sender<(String, Result<Buffer>)>,
+    pub open_popup_rx: Receiver<Result<Popup>>,
+    pub open_popup_tx: Sender<Result<Popup>>,
+    /// buffer name, the saved contents and the result of a background save
+    pub save_buffer_rx: Receiver<(String, Rope, Result<()>)>,
+    pub save_buffer_tx: Sender<(String, Rope, Result<()>)>,
+    /// the buffer being saved by `:x`, the editor closes once it is saved
+    pub close_after_save: Option<String>,
 
     pub popup: Popup,
 
     pub command: String,
-    pub command_suggestions: Vec<ActionEntry>,
+    pub command_suggestions: Vec<Suggestion>,
     pub command_suggestion_index: Option<usize>,
+    /// the first visible suggestion
+    pub command_suggestion_scroll: usize,
+    /// the command line is a command palette without the `:`, that runs the selected suggestion
+    pub command_palette: bool,
+    /// commands run from the command line, oldest first, saved across restarts
+    pub command_history: Vec<String>,
+    /// the recalled command, while going through the history with up and down
+    pub command_history_index: Option<usize>,
+    /// what was typed before going through the history, only commands starting with it are recalled
+    pub command_history_prefix: String,
+    /// the last text searched with `/`, highlighted in the buffer
+    pub search: String,
+    /// where the `/` search being typed started, `None` when not searching
+    pub search_origin: Option<SearchOrigin>,
+    /// the selection before visual mode was left, `None` if nothing was selected yet
+    pub last_visual: Option<LastVisual>,
 
     pub status: String,
     pub status_is_error: bool,
+    /// ring the terminal bell after the next frame
+    pub ring_bell: bool,
+    /// when a key was last pressed, `None` once the buffers were auto-saved after it
+    pub last_input: Option<Instant>,
+
+    pub options: Options,
+    pub registers: Registers,
+    /// the last paste, until something else edits the buffer
+    pub last_paste: Option<LastPaste>,
+    /// where replace mode typed and the char it overwrote, `None` past the line end,
+    /// so backspace can restore them
+    pub replaced: Vec<(usize, Option<char>)>,
+    /// recently run actions and the keys that ran them, only recorded when `verbose` is set
+    pub action_log: VecDeque<String>,
+
+    /// the colors the terminal supports
+    pub colors: ColorSupport,
 
     pub mode: Mode,
+    /// the count typed before an action, like the `3` in `3p`
+    pub count: Option<usize>,
+    /// the count typed before an operator, like the `2` in `2d3w`,
+    /// it multiplies the count typed before the motion
+    pub operator_count: Option<usize>,
     pub force_whichkey: bool,
 
+    /// keys typed since normal mode, `None` if they can't be repeated with `.`,
+    /// like undo or a command
+    pub change_keys: Option<Vec<(KeyCode, KeyModifiers)>>,
+    /// the buffer revision when `change_keys` started
+    pub change_revision: u64,
+    /// the keys of the last change to the buffer, repeated with `.`
+    pub last_change: Vec<(KeyCode, KeyModifiers)>,
+    /// `.` is replaying `last_change`, so the keys aren't recorded again
+    pub repeating: bool,
+
     pub keymap: Keymap,
 }
 
 impl Editor {
     pub fn new(buffer: Buffer) -> Self {
+        Self::with_config(
+            buffer,
+            terminal::size().unwrap(),
+            Options::load(),
+            Keymap::load(),
+            load_command_history(),
+        )
+    }
+
+    /// an editor with the built-in options and keymap, for tests without a terminal
+    #[cfg(test)]
+    pub fn for_tests(buffer: Buffer) -> Self {
+        Self::with_config(
+            buffer,
+            (80, 24),
+            Options::default(),
+            Keymap::builtin(),
+            Vec::new(),
+        )
+    }
+
+    fn with_config(
+        buffer: Buffer,
+        size: (u16, u16),
+        options: Options,
+        keymap: Keymap,
+        command_history: Vec<String>,
+    ) -> Self {
         let (open_askpw_tx, open_askpw_rx) = channel();
         let (open_buffer_tx, open_buffer_rx) = channel();
+        let (open_popup_tx, open_popup_rx) = channel();
+        let (save_buffer_tx, save_buffer_rx) = channel();
 
         Self {
             should_close: false,
-            size: terminal::size().unwrap(),
+            size,
             real_cursor: (0, 0),
 
             buffers: vec![buffer],
             view: BufferView::new(0),
+            splits: Split::Focused,
+            diff: None,
             open_askpw_rx,
             open_askpw_tx,
             open_buffer_rx,
             open_buffer_tx,
+            open_popup_rx,
+            open_popup_tx,
+            save_buffer_rx,
+            save_buffer_tx,
+            close_after_save: None,
 
             popup: <_>::default(),
 
             command: String::new(),
             command_suggestions: Vec::new(),
             command_suggestion_index: None,
+            command_suggestion_scroll: 0,
+            command_palette: false,
+            command_history,
+            command_history_index: None,
+            command_history_prefix: String::new(),
+            search: String::new(),
+            search_origin: None,
+            last_visual: None,
 
             status: String::new(),
             status_is_error: false,
+            ring_bell: false,
+            last_input: None,
+
+            options,
+            registers: Registers::default(),
+            last_paste: None,
+            replaced: Vec::new(),
+            action_log: VecDeque::new(),
+
+            colors: ColorSupport::Auto.detect(),
 
             mode: Mode::Normal,
+            count: None,
+            operator_count: None,
             force_whichkey: false,
 
-            keymap: Keymap::load(),
+            change_keys: Some(Vec::new()),
+            change_revision: 0,
+            last_change: Vec::new(),
+            repeating: false,
+
+            keymap,
         }
     }
 
     pub fn run(&mut self, mut terminal: DefaultTerminal) {
+        // crossterm only starts reading the keys below, a late query reply must be gone by then
+        theme::drain_query_reply();
+
         'main: loop {
             let area = terminal
                 .draw(|frame| {
@@ -121,7 +280,7 @@ impl Editor {
                     })
                     .unwrap();
             } else if self.mode.is_insert() {
-                execute!(terminal.backend_mut(), SetCursorStyle::SteadyBar).unwrap();
+                execute!(terminal.backend_mut(), self.mode.cursor_style()).unwrap();
                 terminal.show_cursor().unwrap();
                 terminal
                     .set_cursor_position(Position {
@@ -133,8 +292,15 @@ impl Editor {
                 terminal.hide_cursor().unwrap();
             }
 
+            if mem::take(&mut self.ring_bell) {
+                execute!(terminal.backend_mut(), Print('\x07')).unwrap();
+            }
+
             while !event::poll(Duration::from_millis(1)).unwrap() {
                 if self.update() {
+                    if self.should_close {
+                        break 'main;
+                    }
                     continue 'main;
                 }
             }
@@ -147,20 +313,51 @@ impl Editor {
     }
 
     pub fn render(&mut self, frame: &mut Frame) {
+        let theme = theme::current();
         frame.render_widget(
-            Block::new().style(Style::new().bg(theme::BACKGROUND)),
+            Block::new().style(Style::new().bg(theme.background)),
             frame.area(),
         );
 
         let [buffer_area, cmd_area] =
             Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
 
-        // render the main buffer view
-        self.real_cursor = BufferViewMut::new(&mut self.view, &mut self.buffers).render(
-            &self.mode,
-            buffer_area,
-            frame,
-        );
+        for (area, split) in self.splits.layout_mut(buffer_area) {
+            let Split::View(view) = split else {
+                continue;
+            };
+            let buffer = &self.buffers[view.buffer_index];
+            view.render(buffer, &self.mode, &self.search, area, frame, false);
+        }
+        let focused_area = self
+            .splits
+            .layout_mut(buffer_area)
+            .into_iter()
+            .find(|(_, split)| matches!(split, Split::Focused))
+            .map_or(buffer_area, |(area, _)| area);
+
+        // render the focused buffer view, or the diff view
+        if let Some(diff) = self
+            .diff
+            .as_mut()
+            .filter(|diff| diff.is_valid(&self.buffers) && diff.right == self.view.buffer_index)
+        {
+            self.real_cursor = diff.render(
+                &self.buffers,
+                &self.view,
+                self.mode.as_str(),
+                self.mode.is_insert(),
+                focused_area,
+                frame,
+            );
+        } else {
+            self.real_cursor = BufferViewMut::new(&mut self.view, &mut self.buffers).render(
+                &self.mode,
+                &self.search,
+                focused_area,
+                frame,
+            );
+        }
 
         // render the command suggestion box
         self.render_cmd_suggestions(buffer_area, frame);
@@ -173,64 +370,110 @@ impl Editor {
 
         // render a keymapping layer helper
         self.render_whichkey(buffer_area, frame);
+
+        self.colors.downgrade(frame.buffer_mut());
     }
 
     fn render_cmd_suggestions(&mut self, area: Rect, frame: &mut Frame) {
-        // render suggestions as a popup over the buffer area
+        let theme = theme::current();
+        let len = self.command_suggestions.len();
+        let height = len.min(MAX_SUGGESTIONS);
+        let overflows = len > height;
+
+        // render suggestions as a popup over the buffer area,
+        // with a counter above them when they don't all fit
         let [_, area] = Layout::vertical([
             Constraint::Min(1),
-            Constraint::Length((self.command_suggestions.len() as u16).min(10)),
+            Constraint::Length(height as u16 + overflows as u16),
         ])
         .areas(area);
 
-        let suggestion_list_chunk_start = self
-            .command_suggestion_index
-            .unwrap_or(0)
-            .checked_div(area.height as usize)
-            .unwrap_or(0)
-            .checked_mul(area.height as usize)
-            .unwrap_or(0);
-        let suggestion_bg = Block::new().style(Style::new().bg(theme::BACKGROUND_LIGHT));
+        let suggestion_bg = Block::new().style(Style::new().bg(theme.background_light));
         frame.render_widget(Clear, area);
         frame.render_widget(suggestion_bg, area);
-        for (i, act) in self
+        if len == 0 {
+            return;
+        }
+
+        // scroll just enough to keep the selection away from the edges
+        let selected = self.command_suggestion_index.unwrap_or(0);
+        let margin = SUGGESTION_SCROLL_MARGIN.min(height.saturating_sub(1) / 2);
+        let min = (selected + margin + 1).saturating_sub(height);
+        let max = selected.saturating_sub(margin).max(min);
+        self.command_suggestion_scroll = self
+            .command_suggestion_scroll
+            .clamp(min, max)
+            .min(len - height);
+        let scroll = self.command_suggestion_scroll;
+
+        let mut list_area = area;
+        if overflows {
+            let counter_area;
+            [counter_area, list_area] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+
+            let counter = match self.command_suggestion_index {
+                Some(index) => format!("[{}/{len}] ", index + 1),
+                None => format!("[-/{len}] "),
+            };
+            let counter = Line::from(counter).right_aligned().fg(theme.accent);
+            frame.render_widget(counter, counter_area);
+
+            let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None);
+            let mut scrollbar_state = ScrollbarState::new(len - height).position(scroll);
+            frame.render_stateful_widget(scrollbar, list_area, &mut scrollbar_state);
+
+            // leave the scrollbar column alone
+            list_area.width = list_area.width.saturating_sub(1);
+        }
+
+        for ((i, act), area) in self
             .command_suggestions
             .iter()
             .enumerate()
-            .skip(suggestion_list_chunk_start)
-            .take(area.height as usize)
+            .skip(scroll)
+            .take(height)
+            .zip(list_area.rows())
         {
             let (fg, bg) = if Some(i) == self.command_suggestion_index {
-                (theme::BACKGROUND_LIGHT, theme::CURSOR)
+                (theme.background_light, theme.cursor)
             } else {
-                (theme::CURSOR, theme::BACKGROUND_LIGHT)
+                (theme.cursor, theme.background_light)
             };
 
-            let area = Rect {
-                x: area.x,
-                y: area.y + (i - suggestion_list_chunk_start) as u16,
-                width: area.width,
-                height: 1,
+            // underline the characters that matched the command, in the name or the description
+            let highlight = |text: &str, matched: bool, style: Style| {
+                Line::from_iter(text.chars().enumerate().map(|(i, ch)| {
+                    if matched && act.matched.contains(&i) {
+                        Span::styled(ch.to_string(), style.bold().underlined())
+                    } else {
+                        Span::styled(ch.to_string(), style)
+                    }
+                }))
             };
+            let name = highlight(
+                act.act.name(),
+                !act.in_description,
+                Style::new().fg(fg).bg(bg),
+            );
+            let description = highlight(
+                act.act.description(),
+                act.in_description,
+                Style::new().fg(theme.accent),
+            );
 
             let suggestion = Block::new()
-                .title(
-                    Line::from_iter([act.act.description()])
-                        .right_aligned()
-                        .fg(theme::ACCENT),
-                )
-                .title(
-                    Line::from_iter([act.act.name()])
-                        .left_aligned()
-                        .fg(fg)
-                        .bg(bg),
-                );
+                .title(description.right_aligned())
+                .title(name.left_aligned());
             frame.render_widget(suggestion, area);
         }
     }
 
     fn render_cmdline(&mut self, area: Rect, frame: &mut Frame) {
-        if !self.command.is_empty() {
+        let theme = theme::current();
+        if !self.command.is_empty() || self.command_palette {
             let cmd = Block::new()
                 // .style(Style::new().bg(Color::Black))
                 .title(self.command.as_str());
@@ -246,7 +489,7 @@ impl Editor {
                 .style(Style::new().fg(if self.status_is_error {
                     Color::Red
                 } else {
-                    Color::Reset
+                    theme.text
                 }));
             frame.render_widget(status, area);
         }
@@ -259,16 +502,19 @@ impl Editor {
             horizontal: (area.width as f32 * 0.1) as u16,
             vertical: (area.height as f32 * 0.1) as u16,
         });
-        self.popup.render(&self.buffers, popup_area, frame);
+        self.popup
+            .render(&self.buffers, &self.registers, popup_area, frame);
     }
 
     fn render_whichkey(&mut self, area: Rect, frame: &mut Frame) {
+        let theme = theme::current();
         let layer = if let Mode::Action { layer, .. } = &self.mode {
             layer.clone()
         } else if self.force_whichkey {
             match self.mode {
                 Mode::Normal => self.keymap.normal(),
                 Mode::Insert { .. } => self.keymap.insert(),
+                Mode::Visual { .. } => self.keymap.visual(),
                 Mode::Command => self.keymap.command(),
                 Mode::Action { ref layer, .. } => layer.clone(),
             }
@@ -282,7 +528,7 @@ impl Editor {
         let width = entries
             .iter()
             .map(|(code, entry)| {
-                code.as_str(&mut [const { 0 }; 16]).len() + 1 + entry.description().len()
+                code.as_str(&mut [0; Code::MAX_NAME_LEN]).len() + 1 + entry.description().len()
             })
             .max()
             .unwrap_or(0)
@@ -305,7 +551,7 @@ impl Editor {
         frame.render_widget(
             Block::new()
                 // .title(layer.description())
-                .style(Style::new().bg(theme::BACKGROUND_LIGHT)),
+                .style(Style::new().bg(theme.background_light)),
             area,
         );
 
@@ -313,7 +559,7 @@ impl Editor {
             .into_iter()
             .map(|wildcard_description| (Cow::Borrowed("*"), wildcard_description.description()))
             .chain(entries.iter().map(|(code, entry)| {
-                let key = code.as_str(&mut [const { 0 }; 16]).to_string().into();
+                let key = code.as_str(&mut [0; Code::MAX_NAME_LEN]).to_string().into();
                 let entry = entry.description();
                 (key, entry)
             }))
@@ -321,8 +567,8 @@ impl Editor {
         {
             let action_name = Line::from_iter([action_name])
                 .right_aligned()
-                .style(Style::new().fg(theme::ACCENT));
-            let key_name = Line::from_iter([key_name]).left_aligned().fg(Color::Reset);
+                .style(Style::new().fg(theme.accent));
+            let key_name = Line::from_iter([key_name]).left_aligned().fg(theme.text);
             let line = Block::new().title(action_name).title(key_name);
             frame.render_widget(line, area);
         }
@@ -341,7 +587,7 @@ impl Editor {
                 ..
             }) = event
             {
-                let mut buf = [const { 0 }; 16];
+                let mut buf = [0; Code::MAX_NAME_LEN];
                 let key_name = Code::from_event(code, modifiers).as_str(&mut buf);
                 tracing::debug!("pressed '{key_name}'");
             }
@@ -352,6 +598,18 @@ impl Editor {
             return;
         }
 
+        if CONN_POOL.is_busy() {
+            if let Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                kind: KeyEventKind::Press,
+                ..
+            }) = event
+            {
+                CONN_POOL.cancel();
+                return;
+            }
+        }
+
         match event {
             /* Event::Key(KeyEvent {
                 code: KeyCode::Char('c'),
@@ -365,31 +623,159 @@ impl Editor {
             Event::Resize(w, h) => {
                 self.size = (w, h);
             }
+            Event::FocusGained => self.check_disk(),
             Event::Key(KeyEvent {
                 code,
                 modifiers,
                 kind: KeyEventKind::Press,
                 ..
             }) => {
-                let (layer, prev) = match self.mode {
-                    Mode::Normal => (self.keymap.normal(), ModeSubset::Normal),
-                    Mode::Insert { append } => {
-                        (self.keymap.insert(), ModeSubset::Insert { append })
+                self.last_input = Some(Instant::now());
+                if !self.repeating {
+                    if let Some(keys) = &mut self.change_keys {
+                        keys.push((code, modifiers));
                     }
-                    Mode::Command => (self.keymap.command(), ModeSubset::Command),
-                    Mode::Action { ref layer, prev } => (layer.clone(), prev),
-                };
-
-                if layer.run(Code::from_event(code, modifiers), self) {
-                    return;
                 }
-
-                self.mode = prev.mode();
+                self.key(code, modifiers);
+                self.record_change();
             }
             _ => {}
         }
     }
 
+    /// run a pressed key in the current mode
+    pub fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        // escape cancels a key sequence and returns to the mode it started from
+        if let (Mode::Action { prev, .. }, KeyCode::Esc) = (&self.mode, code) {
+            self.mode = prev.mode();
+            self.count = None;
+            self.operator_count = None;
+            return;
+        }
+
+        // digits in normal mode are a count for the next action, `0` only continues one,
+        // after an operator they are a count for its motion
+        let counted = match &self.mode {
+            Mode::Normal | Mode::Visual { .. } => true,
+            Mode::Action { layer, .. } => layer.is_operator(),
+            _ => false,
+        };
+        if let (true, KeyCode::Char(digit @ '0'..='9')) = (counted, code) {
+            if modifiers.is_empty() && (digit != '0' || self.count.is_some()) {
+                let digit = digit as usize - '0' as usize;
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit));
+                return;
+            }
+        }
+
+        // the selection before the key that leaves visual mode is the one `gv` selects again
+        if let Mode::Visual { anchor, linewise } = self.mode {
+            self.last_visual = Some(LastVisual {
+                buffer_index: self.view.buffer_index,
+                anchor,
+                cursor: self.view.cursor,
+                linewise,
+            });
+        }
+
+        let (layer, prev) = match self.mode {
+            Mode::Normal => (self.keymap.normal(), ModeSubset::Normal),
+            Mode::Insert { append, replace } => {
+                (self.keymap.insert(), ModeSubset::Insert { append, replace })
+            }
+            Mode::Command => (self.keymap.command(), ModeSubset::Command),
+            Mode::Visual { anchor, linewise } => (
+                self.keymap.visual(),
+                ModeSubset::Visual { anchor, linewise },
+            ),
+            Mode::Action { ref layer, prev } => (layer.clone(), prev),
+        };
+
+        let code = Code::from_event(code, modifiers);
+        let handled = layer.run(code, self);
+        // the count is only for the next action, not the keys after it
+        if !self.mode.is_action() {
+            self.count = None;
+            self.operator_count = None;
+        }
+        if handled {
+            return;
+        }
+
+        if self.mode.is_action() || self.mode.is_normal() || self.mode.is_visual() {
+            self.unmapped(code, &*layer);
+        }
+
+        self.mode = prev.mode();
+    }
+
+    /// keep the keys typed since normal mode as the last change, once back in normal mode,
+    /// if they edited the buffer
+    fn record_change(&mut self) {
+        if self.repeating {
+            return;
+        }
+        // visual selections and commands aren't repeated
+        if matches!(self.mode, Mode::Visual { .. } | Mode::Command) {
+            self.change_keys = None;
+            return;
+        }
+        if !self.mode.is_normal() || self.count.is_some() {
+            return;
+        }
+
+        let revision = self.current().buffer.revision;
+        if let Some(keys) = self.change_keys.take() {
+            if revision != self.change_revision {
+                self.last_change = keys;
+            }
+        }
+        self.change_keys = Some(Vec::new());
+        self.change_revision = revision;
+    }
+
+    /// record an action about to run, if `verbose` is set,
+    /// `key` is `None` for actions run from the command line
+    pub fn log_action(&mut self, key: Option<Code>, action: &str) {
+        if !self.options.verbose {
+            return;
+        }
+
+        let entry = if let Some(key) = key {
+            format!("{} -> {action}", key.as_str(&mut [0; Code::MAX_NAME_LEN]))
+        } else {
+            format!(":{action}")
+        };
+
+        self.status.clear();
+        self.status.push_str(&entry);
+        self.status_is_error = false;
+
+        if self.action_log.len() == ACTION_LOG_LEN {
+            self.action_log.pop_front();
+        }
+        self.action_log.push_back(entry);
+    }
+
+    /// feedback for a key that isn't mapped to anything
+    fn unmapped(&mut self, code: Code, layer: &dyn Layer) {
+        if self.options.visualbell {
+            self.status.clear();
+            use std::fmt::Write;
+            _ = write!(
+                &mut self.status,
+                "unmapped: {} in {}",
+                code.as_str(&mut [0; Code::MAX_NAME_LEN]),
+                layer.name()
+            );
+            self.status_is_error = true;
+        }
+        if self.options.errorbells {
+            self.ring_bell = true;
+        }
+    }
+
     pub fn update(&mut self) -> bool {
         let mut updated = false;
 
@@ -404,10 +790,65 @@ impl Editor {
             };
         }
 
-        if let Ok(result) = self.open_buffer_rx.try_recv() {
+        if let Ok((path, result)) = self.open_buffer_rx.try_recv() {
+            updated = true;
+            // the placeholder is gone if the buffer was closed while loading
+            let placeholder = self
+                .find_opened(&path)
+                .filter(|i| matches!(self.buffers[*i].inner, BufferInner::Loading));
+
+            match (placeholder, result) {
+                (None, _) => {}
+                (Some(i), Ok(mut buf)) => {
+                    buf.options = self.options.for_filetype(buf.lang());
+                    if let (true, Some(format)) = (buf.mixed_line_endings, buf.fileformat()) {
+                        self.status.clear();
+                        use std::fmt::Write;
+                        _ = write!(
+                            &mut self.status,
+                            "mixed line endings, saving them as {}",
+                            format.as_str()
+                        );
+                        self.status_is_error = true;
+                    }
+                    self.buffers[i] = buf;
+                    if let Some(diff) = self.diff.as_mut() {
+                        diff.invalidate();
+                    }
+                    for view in iter::once(&mut self.view).chain(self.splits.views_mut()) {
+                        if view.buffer_index == i {
+                            *view = BufferView::new(i);
+                        }
+                    }
+                }
+                (Some(i), Err(err)) => {
+                    self.buffers.remove(i);
+                    // the buffer indices moved
+                    self.diff = None;
+                    self.last_paste = None;
+                    self.last_visual = None;
+                    if self.buffers.is_empty() {
+                        self.buffers.push(Buffer::new());
+                    }
+                    for view in iter::once(&mut self.view).chain(self.splits.views_mut()) {
+                        if view.buffer_index >= i {
+                            let index = view.buffer_index.saturating_sub(1);
+                            *view = BufferView::new(index.min(self.buffers.len() - 1));
+                        }
+                    }
+
+                    self.status.clear();
+                    use std::fmt::Write;
+                    _ = write!(&mut self.status, "failed to open: {err}");
+                    self.status_is_error = true;
+                }
+            }
+        }
+
+        if let Ok(result) = self.open_popup_rx.try_recv() {
             updated = true;
             match result {
-                Ok(buf) => self.open_from(buf),
+                Ok(popup) => self.popup = popup,
                 Err(err) => {
                     self.status.clear();
                     use std::fmt::Write;
@@ -417,6 +858,46 @@ impl Editor {
             }
         }
 
+        if let Ok((name, contents, result)) = self.save_buffer_rx.try_recv() {
+            updated = true;
+            match result {
+                Ok(()) => {
+                    if let Some(i) = self.find_opened(&name) {
+                        // the buffer could have been edited while it was being saved
+                        let buf = &mut self.buffers[i];
+                        if buf.contents == contents {
+                            buf.modified = false;
+                        }
+                    }
+                    if self.close_after_save.as_ref() == Some(&name) {
+                        self.should_close = true;
+                    }
+                }
+                Err(err) => {
+                    if self.close_after_save.as_ref() == Some(&name) {
+                        self.close_after_save = None;
+                    }
+                    self.status.clear();
+                    use std::fmt::Write;
+                    _ = write!(&mut self.status, "failed to save: {err}");
+                    self.status_is_error = true;
+                }
+            }
+        }
+
+        updated |= self.autosave();
+
+        // show that a remote command is blocking something
+        let busy = CONN_POOL.is_busy();
+        if busy && self.status.is_empty() {
+            updated = true;
+            self.status.push_str(REMOTE_BUSY_STATUS);
+            self.status_is_error = false;
+        } else if !busy && self.status == REMOTE_BUSY_STATUS {
+            updated = true;
+            self.status.clear();
+        }
+
         // if updated {
         //     tracing::warn!("update");
         // }
@@ -424,6 +905,45 @@ impl Editor {
         updated
     }
 
+    /// save the modified buffers, once there was no input for `autosave` seconds
+    fn autosave(&mut self) -> bool {
+        let delay = Duration::from_secs(self.options.autosave as u64);
+        if self.options.autosave == 0 || !self.last_input.is_some_and(|at| at.elapsed() >= delay) {
+            return false;
+        }
+        self.last_input = None;
+
+        let mut updated = false;
+        for buf in self.buffers.iter_mut() {
+            // remote saves can ask for a password, so they are left for `w`
+            let skip = matches!(
+                buf.inner,
+                BufferInner::Scratch { .. } | BufferInner::Remote { .. }
+            );
+            if !buf.modified || buf.is_readonly() || skip {
+                continue;
+            }
+
+            updated = true;
+            match buf.write(self.open_askpw_tx.clone()) {
+                Ok(()) => tracing::info!("auto-saved {}", buf.name),
+                Err(err) => {
+                    tracing::error!("failed to auto-save {}: {err}", buf.name);
+                    self.status.clear();
+                    use std::fmt::Write;
+                    _ = write!(&mut self.status, "failed to auto-save {}: {err}", buf.name);
+                    self.status_is_error = true;
+                }
+            }
+        }
+        updated
+    }
+
+    /// the command line is a `/` search prompt
+    pub fn is_search_prompt(&self) -> bool {
+        self.mode.is_command() && !self.command_palette && self.command.starts_with('/')
+    }
+
     pub fn current(&self) -> BufferViewRef<'_> {
         BufferViewRef::new(&self.view, &self.buffers)
     }
@@ -432,6 +952,28 @@ impl Editor {
         BufferViewMut::new(&mut self.view, &mut self.buffers)
     }
 
+    /// remember a command that was run, and save the history for the next time
+    pub fn push_command_history(&mut self, command: &str) {
+        self.command_history_index = None;
+        // running an old command again moves it to the end
+        self.command_history.retain(|old| old != command);
+        self.command_history.push(command.to_string());
+        let extra = self
+            .command_history
+            .len()
+            .saturating_sub(COMMAND_HISTORY_LEN);
+        self.command_history.drain(..extra);
+
+        let path = command_history_path();
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, self.command_history.join("\n")));
+        if let Err(err) = result {
+            tracing::warn!("failed to save the command history to {path:?}: {err}");
+        }
+    }
+
     pub fn find_opened(&self, path: &str) -> Option<usize> {
         // look for existing open buffers
         for (i, existing) in self.buffers.iter().enumerate() {
@@ -448,28 +990,171 @@ impl Editor {
     }
 
     pub fn open(&mut self, path: String) {
-        if let Some(i) = self.find_opened(&path) {
+        let askpw_tx = self.open_askpw_tx.clone();
+        let huge_file = self.options.huge_file_bytes();
+        self.open_with(path.clone(), move || {
+            Buffer::open(&path, huge_file, askpw_tx)
+        });
+    }
+
+    /// open a buffer named `name` using `open` on a worker thread,
+    /// or switch to it if it is already open
+    pub fn open_with(
+        &mut self,
+        name: String,
+        open: impl FnOnce() -> Result<Buffer> + Send + 'static,
+    ) {
+        if let Some(i) = self.find_opened(&name) {
             self.switch_to(i);
             return;
         }
 
-        let askpw_tx = self.open_askpw_tx.clone();
         let buffer_tx = self.open_buffer_tx.clone();
 
+        // show a placeholder, while the real file loads
+        self.open_from(Buffer::new_loading(&name));
+
         thread::spawn(move || {
-            if let Err(err) = buffer_tx.send(Buffer::open(&path, askpw_tx)) {
+            let result = open();
+            if let Err(err) = buffer_tx.send((name, result)) {
                 tracing::error!("failed to send back opened buffer: {err}");
             }
         });
     }
 
-    pub fn open_from(&mut self, buf: Buffer) {
+    /// notice another program changing the current file, an unmodified buffer is reloaded,
+    /// otherwise a popup asks which version to keep
+    pub fn check_disk(&mut self) {
+        let i = self.view.buffer_index;
+        let buf = &self.buffers[i];
+        if !buf.changed_on_disk() {
+            return;
+        }
+        if buf.modified {
+            if matches!(self.popup, Popup::None) {
+                self.popup = Popup::Reload { buffer_index: i };
+            }
+            return;
+        }
+
+        let name = buf.name.to_string();
+        self.status.clear();
+        use std::fmt::Write;
+        if let Err(err) = self.reload_buffer(i) {
+            _ = write!(&mut self.status, "failed to reload: {err}");
+            self.status_is_error = true;
+        } else {
+            _ = write!(&mut self.status, "{name} changed on disk, reloaded it");
+            self.status_is_error = false;
+        }
+    }
+
+    /// replace the matches of `sub` on `lines` of the current buffer, starting at char `from`,
+    /// with `confirm` it stops at the next match and returns the popup asking about it
+    pub fn substitute(
+        &mut self,
+        sub: Substitution,
+        lines: Range<usize>,
+        from: usize,
+        mut count: usize,
+    ) -> Popup {
+        self.status.clear();
+        use std::fmt::Write;
+
+        let buffer = &mut self.buffers[self.view.buffer_index];
+        if sub.confirm {
+            if let Some(at) =
+                search::next_match(&buffer.contents, &sub.pattern, from, lines.clone())
+            {
+                self.view.cursor = at.start;
+                return Popup::Substitute {
+                    sub,
+                    lines,
+                    at,
+                    count,
+                };
+            }
+        } else {
+            let matches = sub.matches(&buffer.contents, from, lines);
+            if let Some(last) = matches.last() {
+                let last_line = buffer.contents.char_to_line(last.start);
+                // one undo step and one re-parse for all of them
+                let result = buffer.edit(|tx| {
+                    for at in &matches {
+                        tx.replace(at.clone(), &sub.replacement);
+                    }
+                });
+                if let Err(err) = result {
+                    _ = write!(&mut self.status, "{err}");
+                    self.status_is_error = true;
+                    return Popup::None;
+                }
+                count += matches.len();
+                // the replacements have no newlines, so the line is still there
+                self.view.cursor = buffer.contents.line_to_char(last_line);
+            }
+        }
+
+        _ = write!(
+            &mut self.status,
+            "{count} substitution{}",
+            if count == 1 { "" } else { "s" }
+        );
+        self.status_is_error = false;
+        Popup::None
+    }
+
+    /// read buffer `i` from disk again, its views stay on the same lines
+    pub fn reload_buffer(&mut self, i: usize) -> Result<()> {
+        let huge_file = self.options.huge_file_bytes();
+        let contents = &self.buffers[i].contents;
+        let lines: Vec<usize> = iter::once(&mut self.view)
+            .chain(self.splits.views_mut())
+            .filter(|view| view.buffer_index == i)
+            .map(|view| contents.char_to_line(view.cursor.min(contents.len_chars())))
+            .collect();
+
+        self.buffers[i].reload(huge_file)?;
+        if let Some(diff) = self.diff.as_mut() {
+            diff.invalidate();
+        }
+
+        let contents = &self.buffers[i].contents;
+        let views = iter::once(&mut self.view)
+            .chain(self.splits.views_mut())
+            .filter(|view| view.buffer_index == i);
+        for (view, line) in views.zip(lines) {
+            let line = line.min(contents.len_lines() - 1);
+            *view = BufferView::new(i);
+            view.cursor = contents.line_to_char(line);
+        }
+        Ok(())
+    }
+
+    pub fn open_from(&mut self, mut buf: Buffer) {
+        buf.options = self.options.for_filetype(buf.lang());
         let idx = self.buffers.len();
         self.buffers.push(buf);
         self.view = BufferView::new(idx);
     }
 }
 
+fn command_history_path() -> PathBuf {
+    crate::tmpdir().join("command_history")
+}
+
+fn load_command_history() -> Vec<String> {
+    let path = command_history_path();
+    match fs::read_to_string(&path) {
+        Ok(history) => history.lines().map(str::to_string).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            tracing::warn!("failed to load the command history from {path:?}: {err}");
+            Vec::new()
+        }
+    }
+}
+
 //
 
 pub struct BufferViewRef<'a> {
@@ -500,14 +1185,19 @@ impl<'a> BufferViewRef<'a> {
         self.view.rfind(self.buffer, from, pred)
     }
 
-    /// find the next word boundary starting and including `from`
-    pub fn find_boundary(&self, from: usize) -> usize {
-        self.view.find_boundary(self.buffer, from)
+    /// the start of the word after `from`, like vim `w`
+    pub fn next_word_beg(&self, from: usize) -> usize {
+        self.view.next_word_beg(self.buffer, from)
+    }
+
+    /// the end of the word after `from`, like vim `e`
+    pub fn next_word_end(&self, from: usize) -> usize {
+        self.view.next_word_end(self.buffer, from)
     }
 
-    /// reverse find the next word boundary starting and including `from`
-    pub fn rfind_boundary(&self, from: usize) -> usize {
-        self.view.rfind_boundary(self.buffer, from)
+    /// the start of the word before `from`, like vim `b`
+    pub fn prev_word_beg(&self, from: usize) -> usize {
+        self.view.prev_word_beg(self.buffer, from)
     }
 }
 
@@ -527,10 +1217,12 @@ impl<'a> BufferViewMut<'a> {
     pub fn render(
         self,
         mode: &Mode,
+        search: &str,
         area: Rect,
         frame: &mut ratatui::prelude::Frame,
     ) -> (usize, usize) {
-        self.view.render(self.buffer, mode, area, frame)
+        self.view
+            .render(self.buffer, mode, search, area, frame, true)
     }
 
     /// count matching characters starting and including `from`
@@ -548,20 +1240,29 @@ impl<'a> BufferViewMut<'a> {
         self.view.rfind(self.buffer, from, pred)
     }
 
-    /// find the next word boundary starting and including `from`
-    pub fn find_boundary(&self, from: usize) -> usize {
-        self.view.find_boundary(self.buffer, from)
+    /// the start of the word after `from`, like vim `w`
+    pub fn next_word_beg(&self, from: usize) -> usize {
+        self.view.next_word_beg(self.buffer, from)
+    }
+
+    /// the end of the word after `from`, like vim `e`
+    pub fn next_word_end(&self, from: usize) -> usize {
+        self.view.next_word_end(self.buffer, from)
     }
 
-    /// reverse find the next word boundary starting and including `from`
-    pub fn rfind_boundary(&self, from: usize) -> usize {
-        self.view.rfind_boundary(self.buffer, from)
+    /// the start of the word before `from`, like vim `b`
+    pub fn prev_word_beg(&self, from: usize) -> usize {
+        self.view.prev_word_beg(self.buffer, from)
     }
 
     pub fn jump_cursor(&mut self, delta_x: isize, delta_y: isize) {
         self.view.jump_cursor(self.buffer, delta_x, delta_y);
     }
 
+    pub fn jump_graphemes(&mut self, delta: isize) {
+        self.view.jump_graphemes(self.buffer, delta);
+    }
+
     pub fn jump_line_beg(&mut self) {
         self.view.jump_line_beg(self.buffer);
     }
@@ -570,6 +1271,14 @@ impl<'a> BufferViewMut<'a> {
         self.view.jump_line_end(self.buffer)
     }
 
+    pub fn jump_append(&mut self) {
+        self.view.jump_append(self.buffer)
+    }
+
+    pub fn jump_line_append(&mut self) {
+        self.view.jump_line_append(self.buffer)
+    }
+
     pub fn jump_beg(&mut self) {
         self.view.jump_beg()
     }
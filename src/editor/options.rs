@@ -0,0 +1,280 @@
+use std::{collections::BTreeMap, fs};
+
+use eyre::{bail, eyre, Result};
+
+use clap::ValueEnum;
+
+use super::theme::{self, Background};
+use crate::buffer::Lang;
+
+//
+
+/// built-in per-filetype options, user filetype options are applied after these
+const DEFAULT_FILETYPE_OPTIONS: &[(&str, &str)] = &[
+    ("make", "noexpandtab tabstop=8 shiftwidth=8"),
+    ("go", "noexpandtab"),
+    ("markdown", "textwidth=80"),
+];
+
+//
+
+/// editor settings, changed with `:set`
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// show unmapped keys in the status line
+    pub visualbell: bool,
+    /// ring the terminal bell on unmapped keys
+    pub errorbells: bool,
+    /// record and show which actions keys ran
+    pub verbose: bool,
+    /// files over this many MiB are opened read-only without syntax highlighting
+    pub hugefile: usize,
+    /// home goes to the first non-blank char first, and then to the line beginning
+    pub smarthome: bool,
+    /// save modified buffers after this many seconds without input, 0 turns it off
+    pub autosave: usize,
+
+    /// defaults for new buffers
+    pub buffer: BufferOptions,
+    /// `:set` arguments for each filetype, from `filetypes.ron`
+    pub filetypes: BTreeMap<String, String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            visualbell: true,
+            errorbells: false,
+            verbose: false,
+            hugefile: 64,
+            smarthome: true,
+            autosave: 0,
+
+            buffer: BufferOptions::default(),
+            filetypes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Options {
+    /// the default options, with the user filetype options config
+    ///
+    /// ```ron
+    /// {
+    ///     "rust": "shiftwidth=4",
+    ///     "markdown": "textwidth=100",
+    /// }
+    /// ```
+    pub fn load() -> Self {
+        let mut options = Self::default();
+
+        let path = crate::config_dir().join("filetypes.ron");
+        match fs::read_to_string(&path) {
+            Ok(config) => match ron::from_str(&config) {
+                Ok(filetypes) => options.filetypes = filetypes,
+                Err(err) => tracing::error!("failed to load filetype options from {path:?}: {err}"),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => tracing::error!("failed to load filetype options from {path:?}: {err}"),
+        }
+
+        options
+    }
+
+    /// apply one `:set` argument, like `errorbells`, `novisualbell` or `name=value`
+    pub fn set(&mut self, arg: &str) -> Result<()> {
+        let (name, value) = split_arg(arg);
+
+        match name {
+            "visualbell" | "vb" => self.visualbell = flag(name, true, value)?,
+            "novisualbell" | "novb" => self.visualbell = flag(name, false, value)?,
+            "errorbells" | "errorbell" | "eb" => self.errorbells = flag(name, true, value)?,
+            "noerrorbells" | "noerrorbell" | "noeb" => self.errorbells = flag(name, false, value)?,
+            "verbose" => self.verbose = flag(name, true, value)?,
+            "noverbose" => self.verbose = flag(name, false, value)?,
+            "hugefile" => self.hugefile = number(name, value)?,
+            "smarthome" | "sh" => self.smarthome = flag(name, true, value)?,
+            "nosmarthome" | "nosh" => self.smarthome = flag(name, false, value)?,
+            "autosave" => self.autosave = number(name, value)?,
+            "background" | "bg" => theme::set(background(name, value)?.theme()),
+            _ => {
+                if !self.buffer.set(arg)? {
+                    bail!("unknown option `{name}`");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the huge file threshold in bytes
+    pub fn huge_file_bytes(&self) -> usize {
+        self.hugefile.saturating_mul(1024 * 1024)
+    }
+
+    /// options for a new buffer of filetype `lang`
+    pub fn for_filetype(&self, lang: Option<Lang>) -> BufferOptions {
+        let mut options = self.buffer.clone();
+        let Some(lang) = lang else {
+            return options;
+        };
+
+        let defaults = DEFAULT_FILETYPE_OPTIONS
+            .iter()
+            .filter(|(filetype, _)| *filetype == lang.name())
+            .map(|(_, args)| *args);
+        let user = self.filetypes.get(lang.name()).map(String::as_str);
+
+        for arg in defaults.chain(user).flat_map(str::split_whitespace) {
+            match options.set(arg) {
+                Ok(true) => {}
+                Ok(false) => tracing::warn!("`{arg}` is not a buffer option"),
+                Err(err) => tracing::warn!("invalid `{}` option: {err}", lang.name()),
+            }
+        }
+
+        options
+    }
+}
+
+//
+
+/// buffer local settings, new buffers get them from [`Options::buffer`] and their filetype
+#[derive(Debug, Clone)]
+pub struct BufferOptions {
+    /// the number of columns an indent level takes
+    pub shiftwidth: usize,
+    /// the number of columns a tab character takes
+    pub tabstop: usize,
+    /// indent with spaces instead of tabs
+    pub expandtab: bool,
+    /// the maximum line width, 0 for no limit
+    pub textwidth: usize,
+    /// long lines continue on the next row instead of going past the right edge
+    pub wrap: bool,
+    /// draw a `│` at each indent level of the leading whitespace
+    pub indentguides: bool,
+    /// highlight the row of the cursor
+    pub cursorline: bool,
+    /// highlight the column of the cursor
+    pub cursorcolumn: bool,
+    /// highlight this column as a line length marker, 0 for none
+    pub colorcolumn: usize,
+    /// the minimum width of the line numbers
+    pub numberwidth: usize,
+    /// the columns left of the line numbers
+    pub gutterleft: usize,
+    /// the columns between the line numbers and the text
+    pub gutterright: usize,
+    /// drawn in the line numbers of the rows past the end of the buffer, `~`, `─` or a space
+    pub eob: char,
+    /// typing after a pause of this many milliseconds is undone separately,
+    /// 0 undoes a whole insert at once
+    pub undotimeout: usize,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            shiftwidth: 4,
+            tabstop: 8,
+            expandtab: true,
+            textwidth: 0,
+            wrap: false,
+            indentguides: false,
+            cursorline: true,
+            cursorcolumn: true,
+            colorcolumn: 80,
+            numberwidth: 1,
+            gutterleft: 2,
+            gutterright: 2,
+            eob: '~',
+            undotimeout: 500,
+        }
+    }
+}
+
+impl BufferOptions {
+    /// apply one `:set` argument, returns false if it isn't a buffer option
+    pub fn set(&mut self, arg: &str) -> Result<bool> {
+        let (name, value) = split_arg(arg);
+
+        match name {
+            "shiftwidth" | "sw" => self.shiftwidth = number(name, value)?,
+            "tabstop" | "ts" => self.tabstop = number(name, value)?.max(1),
+            "expandtab" | "et" => self.expandtab = flag(name, true, value)?,
+            "noexpandtab" | "noet" => self.expandtab = flag(name, false, value)?,
+            "textwidth" | "tw" => self.textwidth = number(name, value)?,
+            "wrap" => self.wrap = flag(name, true, value)?,
+            "nowrap" => self.wrap = flag(name, false, value)?,
+            "indentguides" | "ig" => self.indentguides = flag(name, true, value)?,
+            "noindentguides" | "noig" => self.indentguides = flag(name, false, value)?,
+            "cursorline" | "cul" => self.cursorline = flag(name, true, value)?,
+            "nocursorline" | "nocul" => self.cursorline = flag(name, false, value)?,
+            "cursorcolumn" | "cuc" => self.cursorcolumn = flag(name, true, value)?,
+            "nocursorcolumn" | "nocuc" => self.cursorcolumn = flag(name, false, value)?,
+            "colorcolumn" | "cc" => self.colorcolumn = number(name, value)?,
+            "numberwidth" | "nuw" => self.numberwidth = number(name, value)?,
+            "gutterleft" => self.gutterleft = number(name, value)?,
+            "gutterright" => self.gutterright = number(name, value)?,
+            "eob" => self.eob = single_char(name, value)?,
+            "undotimeout" | "ut" => self.undotimeout = number(name, value)?,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// the text inserted for one indent level
+    pub fn indent(&self) -> String {
+        if self.expandtab {
+            " ".repeat(self.shiftwidth)
+        } else {
+            "\t".to_string()
+        }
+    }
+}
+
+//
+
+fn split_arg(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg, None),
+    }
+}
+
+fn flag(name: &str, enable: bool, value: Option<&str>) -> Result<bool> {
+    if value.is_some() {
+        bail!("`{name}` doesn't take a value");
+    }
+    Ok(enable)
+}
+
+fn number(name: &str, value: Option<&str>) -> Result<usize> {
+    let value = value.ok_or_else(|| eyre!("`{name}` needs a value, like `{name}=4`"))?;
+    value
+        .parse()
+        .map_err(|_| eyre!("`{name}` expects a number, got `{value}`"))
+}
+
+/// an empty value is a space, so `eob=` leaves the rows blank
+fn single_char(name: &str, value: Option<&str>) -> Result<char> {
+    let value = value.ok_or_else(|| eyre!("`{name}` needs a value, like `{name}=~`"))?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(' '),
+        (Some(ch), None) => Ok(ch),
+        _ => bail!("`{name}` expects a single character, got `{value}`"),
+    }
+}
+
+fn background(name: &str, value: Option<&str>) -> Result<Background> {
+    let value = value.ok_or_else(|| eyre!("`{name}` needs a value, like `{name}=light`"))?;
+    match Background::from_str(value, true) {
+        Ok(Background::Auto) | Err(_) => {
+            bail!("`{name}` expects `dark` or `light`, got `{value}`")
+        }
+        Ok(background) => Ok(background),
+    }
+}
@@ -1,8 +1,10 @@
 use std::{
     borrow::Cow,
     fs,
+    ops::Range,
     path::PathBuf,
     sync::{mpsc::Sender, Arc},
+    thread,
 };
 
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
@@ -20,7 +22,13 @@ use crate::{
     tramp::Part,
 };
 
-use super::{theme, view::BufferView, Editor};
+use super::{
+    registers::{Register, Registers},
+    search::Substitution,
+    theme,
+    view::BufferView,
+    Editor,
+};
 
 //
 
@@ -35,12 +43,32 @@ pub enum Popup {
     BufferPicker {
         selected: usize,
     },
+    ActionLog {
+        entries: Vec<String>,
+    },
+    Registers {
+        /// register names and their contents
+        entries: Vec<(char, Register)>,
+        selected: usize,
+    },
     Askpw {
         path: String,
         password: String,
         sender: Sender<String>,
         prev: Box<Popup>,
     },
+    /// the file of a modified buffer changed on disk, reload it or keep the buffer
+    Reload {
+        buffer_index: usize,
+    },
+    /// `:s` with the `c` flag, replace the match at `at` or not
+    Substitute {
+        sub: Substitution,
+        lines: Range<usize>,
+        at: Range<usize>,
+        /// replacements so far
+        count: usize,
+    },
     // Error {
     //     prev: Box<Popup>,
     // },
@@ -57,9 +85,8 @@ impl Popup {
         let mut files: Vec<(Cow<'static, str>, bool)>;
 
         if let Some(remote) = remote.clone() {
-            let mut conn = CONN_POOL.connect_to(remote, askpw_tx)?;
-            cwd = conn.canonicalize(&cwd)?;
-            let read_dir = conn.list_files(&cwd)?;
+            let read_dir;
+            (cwd, read_dir) = CONN_POOL.list_dir(remote, &cwd, askpw_tx)?;
 
             files = [(Cow::Borrowed(".."), true)]
                 .into_iter()
@@ -110,7 +137,14 @@ impl Popup {
         Self::BufferPicker { selected: current }
     }
 
-    pub fn render(&mut self, buffers: &[Buffer], area: Rect, frame: &mut Frame) {
+    pub fn render(
+        &mut self,
+        buffers: &[Buffer],
+        registers: &Registers,
+        area: Rect,
+        frame: &mut Frame,
+    ) {
+        let theme = theme::current();
         match self {
             Popup::FileExplorer {
                 files,
@@ -120,7 +154,7 @@ impl Popup {
             } => {
                 let block = Block::bordered()
                     .title("File explorer")
-                    .style(Style::new().bg(theme::BACKGROUND));
+                    .style(Style::new().bg(theme.background));
                 frame.render_widget(Clear, area);
                 frame.render_widget(block, area);
 
@@ -153,11 +187,11 @@ impl Popup {
                     .take(chunk_len)
                     .zip(area.rows())
                 {
-                    let mut bg = theme::BACKGROUND;
+                    let mut bg = theme.background;
                     let mut fg = if *is_dir {
                         Color::LightBlue
                     } else {
-                        theme::CURSOR
+                        theme.cursor
                     };
 
                     if *selected == i {
@@ -178,7 +212,7 @@ impl Popup {
             Popup::BufferPicker { selected } => {
                 let block = Block::bordered()
                     .title("Buffer picker")
-                    .style(Style::new().bg(theme::BACKGROUND));
+                    .style(Style::new().bg(theme.background));
                 frame.render_widget(Clear, area);
                 frame.render_widget(block, area);
 
@@ -201,8 +235,8 @@ impl Popup {
                     .take(chunk_len)
                     .zip(area.rows())
                 {
-                    let mut bg = theme::BACKGROUND;
-                    let mut fg = theme::CURSOR;
+                    let mut bg = theme.background;
+                    let mut fg = theme.cursor;
 
                     if *selected == i {
                         (fg, bg) = (bg, fg);
@@ -213,6 +247,82 @@ impl Popup {
                     frame.render_widget(entry, area);
                 }
             }
+            Popup::ActionLog { entries } => {
+                let block = Block::bordered()
+                    .title("Action log")
+                    .style(Style::new().bg(theme.background));
+                frame.render_widget(Clear, area);
+                frame.render_widget(block, area);
+
+                let area = area.inner(Margin {
+                    horizontal: 1,
+                    vertical: 1,
+                });
+
+                // show the latest entries
+                let skip = entries.len().saturating_sub(area.height as usize);
+                for (entry, area) in entries.iter().skip(skip).zip(area.rows()) {
+                    let entry =
+                        Line::from_iter([entry.as_str()]).style(Style::new().fg(theme.cursor));
+                    frame.render_widget(entry, area);
+                }
+            }
+            Popup::Registers { entries, selected } => {
+                let block = Block::bordered()
+                    .title("Registers")
+                    .style(Style::new().bg(theme.background));
+                frame.render_widget(Clear, area);
+                frame.render_widget(block, area);
+
+                let area = area.inner(Margin {
+                    horizontal: 1,
+                    vertical: 1,
+                });
+
+                if entries.is_empty() {
+                    let entry =
+                        Line::from_iter(["no registers"]).style(Style::new().fg(theme.inactive));
+                    frame.render_widget(entry, area);
+                    return;
+                }
+
+                let chunk_start = (*selected)
+                    .checked_div(area.height as usize)
+                    .unwrap_or(0)
+                    .checked_mul(area.height as usize)
+                    .unwrap_or(0);
+                let chunk_len = area.height as usize;
+
+                for ((i, (name, register)), area) in entries
+                    .iter()
+                    .enumerate()
+                    .skip(chunk_start)
+                    .take(chunk_len)
+                    .zip(area.rows())
+                {
+                    let mut bg = theme.background;
+                    let mut fg = theme.cursor;
+
+                    if *selected == i {
+                        (fg, bg) = (bg, fg);
+                    }
+
+                    let active = if registers.active == Some(*name) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    let kind = if register.linewise { 'l' } else { 'c' };
+                    let label = format!("{active}\"{name} {kind}  ");
+                    let preview = preview(
+                        &register.text,
+                        (area.width as usize).saturating_sub(label.chars().count()),
+                    );
+
+                    let entry = Line::from_iter([label, preview]).style(Style::new().fg(fg).bg(bg));
+                    frame.render_widget(entry, area);
+                }
+            }
             Popup::Askpw { path, password, .. } => {
                 let w = (path.len() + 15).min(u16::MAX as usize) as u16;
                 let h = 3;
@@ -234,7 +344,7 @@ impl Popup {
                 let block = Block::bordered()
                     .title(Line::from_iter(["Password for"]).left_aligned())
                     .title(Line::from_iter([" ", path]).right_aligned())
-                    .style(Style::new().bg(theme::BACKGROUND));
+                    .style(Style::new().bg(theme.background));
 
                 frame.render_widget(Clear, area);
                 frame.render_widget(block, area);
@@ -247,10 +357,104 @@ impl Popup {
                 let entry = Line::from_iter(["*".repeat(password.len())]);
                 frame.render_widget(entry, area);
             }
+            Popup::Reload { buffer_index } => {
+                let name = buffers
+                    .get(*buffer_index)
+                    .map_or("", |buffer| buffer.name.as_ref());
+                let question =
+                    format!("{name} changed on disk, reload it and lose the changes? y/n");
+                let w = (question.chars().count() + 2).min(u16::MAX as usize) as u16;
+                let h = 3;
+
+                let [_, area, _] = Layout::horizontal([
+                    Constraint::Fill(1),
+                    Constraint::Length(w),
+                    Constraint::Fill(1),
+                ])
+                .areas(area);
+
+                let [_, area, _] = Layout::vertical([
+                    Constraint::Fill(1),
+                    Constraint::Length(h),
+                    Constraint::Fill(1),
+                ])
+                .areas(area);
+
+                let block = Block::bordered()
+                    .title("Changed on disk")
+                    .style(Style::new().bg(theme.background));
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(block, area);
+
+                let area = area.inner(Margin {
+                    horizontal: 1,
+                    vertical: 1,
+                });
+
+                let entry = Line::from_iter([question]).style(Style::new().fg(theme.cursor));
+                frame.render_widget(entry, area);
+            }
+            Popup::Substitute { sub, .. } => {
+                let question = format!("replace with `{}`? y/n/a/q/l", sub.replacement);
+                let w = (question.chars().count() + 2).min(u16::MAX as usize) as u16;
+                let h = 3;
+
+                // at the bottom, the match is somewhere in the middle
+                let [_, area, _] = Layout::horizontal([
+                    Constraint::Fill(1),
+                    Constraint::Length(w),
+                    Constraint::Fill(1),
+                ])
+                .areas(area);
+
+                let [_, area] =
+                    Layout::vertical([Constraint::Fill(1), Constraint::Length(h)]).areas(area);
+
+                let block = Block::bordered()
+                    .title("Substitute")
+                    .style(Style::new().bg(theme.background));
+
+                frame.render_widget(Clear, area);
+                frame.render_widget(block, area);
+
+                let area = area.inner(Margin {
+                    horizontal: 1,
+                    vertical: 1,
+                });
+
+                let entry = Line::from_iter([question]).style(Style::new().fg(theme.cursor));
+                frame.render_widget(entry, area);
+            }
             Popup::None => {}
         }
     }
 
+    /// opens a file explorer at `cwd`, remote listings run on a worker thread
+    /// and the new popup is sent back through [`Editor::open_popup_tx`]
+    fn travel(self, editor: &Editor, remote: Option<Arc<[Part]>>, cwd: PathBuf) -> Self {
+        let askpw_tx = editor.open_askpw_tx.clone();
+
+        if remote.is_some() {
+            let open_popup_tx = editor.open_popup_tx.clone();
+            thread::spawn(move || {
+                let popup = Popup::file_explorer(remote, askpw_tx, cwd);
+                if let Err(err) = open_popup_tx.send(popup) {
+                    tracing::error!("failed to send back file explorer: {err}");
+                }
+            });
+            return self;
+        }
+
+        match Popup::file_explorer(None, askpw_tx, cwd) {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!("failed to travel directories: {err}");
+                self
+            }
+        }
+    }
+
     pub fn event(mut self, editor: &mut Editor, event: &Event) -> Self {
         match self {
             Popup::FileExplorer {
@@ -285,18 +489,10 @@ impl Popup {
                     kind: KeyEventKind::Press,
                     ..
                 }) => {
+                    let mut cwd = cwd.clone();
                     cwd.pop();
-                    match Popup::file_explorer(
-                        remote.clone(),
-                        editor.open_askpw_tx.clone(),
-                        cwd.clone(),
-                    ) {
-                        Ok(v) => v,
-                        Err(err) => {
-                            tracing::error!("failed to travel directories: {err}");
-                            Popup::None
-                        }
-                    }
+                    let remote = remote.clone();
+                    self.travel(editor, remote, cwd)
                 }
                 Event::Key(KeyEvent {
                     code: KeyCode::Right | KeyCode::Enter,
@@ -307,20 +503,13 @@ impl Popup {
                         return self;
                     };
 
-                    cwd.push(filename.as_ref());
                     if *is_dir {
-                        match Popup::file_explorer(
-                            remote.clone(),
-                            editor.open_askpw_tx.clone(),
-                            cwd.clone(),
-                        ) {
-                            Ok(v) => v,
-                            Err(err) => {
-                                tracing::error!("failed to travel directories: {err}");
-                                self
-                            }
-                        }
+                        let mut cwd = cwd.clone();
+                        cwd.push(filename.as_ref());
+                        let remote = remote.clone();
+                        self.travel(editor, remote, cwd)
                     } else {
+                        cwd.push(filename.as_ref());
                         match cwd.as_os_str().to_str() {
                             Some(path) => {
                                 let path = if let Some(remote) = remote.clone() {
@@ -373,17 +562,69 @@ impl Popup {
                 }
                 _ => self,
             },
+            Popup::Registers {
+                ref entries,
+                ref mut selected,
+            } => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Up,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if !entries.is_empty() => {
+                    *selected = (*selected + entries.len() - 1) % entries.len();
+                    self
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Down,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) if !entries.is_empty() => {
+                    *selected = (*selected + 1) % entries.len();
+                    self
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Left | KeyCode::Esc,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => Popup::None,
+                Event::Key(KeyEvent {
+                    code: KeyCode::Right | KeyCode::Enter,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    let Some((name, _)) = entries.get(*selected) else {
+                        return Popup::None;
+                    };
+
+                    editor.registers.active = Some(*name);
+                    editor.status.clear();
+                    use std::fmt::Write;
+                    _ = write!(&mut editor.status, "the next paste uses \"{name}");
+                    editor.status_is_error = false;
+                    Popup::None
+                }
+                _ => self,
+            },
+            Popup::ActionLog { .. } => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Left | KeyCode::Esc | KeyCode::Enter,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => Popup::None,
+                _ => self,
+            },
             Popup::Askpw {
                 mut password,
                 sender,
                 prev,
                 path,
             } => match event {
+                // dropping the sender cancels the connection
                 Event::Key(KeyEvent {
                     code: KeyCode::Esc,
                     kind: KeyEventKind::Press,
                     ..
-                }) => Popup::None,
+                }) => *prev,
                 Event::Key(KeyEvent {
                     code: KeyCode::Char(ch),
                     kind: KeyEventKind::Press,
@@ -427,7 +668,107 @@ impl Popup {
                     path,
                 },
             },
+            Popup::Reload { buffer_index } => match event {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y'),
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    if let Err(err) = editor.reload_buffer(buffer_index) {
+                        editor.status.clear();
+                        use std::fmt::Write;
+                        _ = write!(&mut editor.status, "failed to reload: {err}");
+                        editor.status_is_error = true;
+                    }
+                    Popup::None
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('n') | KeyCode::Esc,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    // keep the buffer, saving it overwrites the other changes
+                    if let Some(buffer) = editor.buffers.get_mut(buffer_index) {
+                        buffer.ignore_disk_changes();
+                    }
+                    Popup::None
+                }
+                _ => self,
+            },
+            Popup::Substitute {
+                mut sub,
+                lines,
+                at,
+                count,
+            } => {
+                let Event::Key(KeyEvent {
+                    code,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) = event
+                else {
+                    return Popup::Substitute {
+                        sub,
+                        lines,
+                        at,
+                        count,
+                    };
+                };
+
+                let replaced = at.start + sub.replacement.chars().count();
+                let buffer = &mut editor.buffers[editor.view.buffer_index];
+                match code {
+                    // replace this one
+                    KeyCode::Char('y') => {
+                        buffer.replace_text_at(at, &sub.replacement);
+                        let from = sub.resume_at(&buffer.contents, replaced);
+                        editor.substitute(sub, lines, from, count + 1)
+                    }
+                    // skip this one
+                    KeyCode::Char('n') => {
+                        let from = sub.resume_at(&buffer.contents, at.end);
+                        editor.substitute(sub, lines, from, count)
+                    }
+                    // replace this one and the rest without asking
+                    KeyCode::Char('a') => {
+                        buffer.replace_text_at(at, &sub.replacement);
+                        let from = sub.resume_at(&buffer.contents, replaced);
+                        sub.confirm = false;
+                        editor.substitute(sub, lines, from, count + 1)
+                    }
+                    // replace this one and stop
+                    KeyCode::Char('l') => {
+                        buffer.replace_text_at(at, &sub.replacement);
+                        editor.substitute(sub, 0..0, replaced, count + 1)
+                    }
+                    // stop
+                    KeyCode::Char('q') | KeyCode::Esc => editor.substitute(sub, 0..0, 0, count),
+                    _ => Popup::Substitute {
+                        sub,
+                        lines,
+                        at,
+                        count,
+                    },
+                }
+            }
             Popup::None => self,
         }
     }
 }
+
+//
+
+/// the first line of `text`, cut to `width` characters with an ellipsis
+fn preview(text: &str, width: usize) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("");
+    let more_lines = lines.next().is_some();
+
+    let len = first.chars().count();
+    if len <= width && !more_lines {
+        return first.to_string();
+    }
+
+    let keep = len.min(width.saturating_sub(1));
+    first.chars().take(keep).chain(['…']).collect()
+}
@@ -0,0 +1,217 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, VecDeque},
+    io::Write,
+    ops::Range,
+    process::{Command, Stdio},
+};
+
+//
+
+/// how many deletes the numbered registers `1` to `9` remember
+const DELETE_RING_LEN: usize = 9;
+
+/// commands that print the system clipboard, the first one that works is used
+const CLIPBOARD_READERS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-out"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+];
+
+/// commands that replace the system clipboard with their stdin
+const CLIPBOARD_WRITERS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard", "-in"],
+    &["xsel", "--clipboard", "--input"],
+    &["pbcopy"],
+];
+
+//
+
+/// yanked and deleted text, and the system clipboard, vim style
+#[derive(Debug, Default)]
+pub struct Registers {
+    /// `"`, the last yank or delete
+    unnamed: Option<Register>,
+    /// `0`, the last yank
+    yanked: Option<Register>,
+    /// `1` to `9`, the last deletes, newest first
+    deleted: VecDeque<Register>,
+    /// `a` to `z`
+    named: BTreeMap<char, Register>,
+
+    /// the register the next yank, delete or paste uses, instead of the unnamed register
+    pub active: Option<char>,
+}
+
+impl Registers {
+    /// returns true if `name` can be picked with `"`
+    pub const fn is_valid_name(name: char) -> bool {
+        matches!(name, '"' | '0'..='9' | 'a'..='z' | '+')
+    }
+
+    /// store yanked text in the active register, or in `"` and `0`
+    pub fn yank(&mut self, register: Register) {
+        if self.store_in_active(&register) {
+            return;
+        }
+        self.unnamed = Some(register.clone());
+        self.yanked = Some(register);
+    }
+
+    /// store deleted text in the active register, or in `"` and push it to `1`,
+    /// older deletes move to `2` to `9`
+    pub fn delete(&mut self, register: Register) {
+        if self.store_in_active(&register) {
+            return;
+        }
+        self.unnamed = Some(register.clone());
+        self.deleted.push_front(register);
+        self.deleted.truncate(DELETE_RING_LEN);
+    }
+
+    /// store text in the named or clipboard register picked with `"`, and in `"`
+    fn store_in_active(&mut self, register: &Register) -> bool {
+        match self.active.take() {
+            Some(name @ 'a'..='z') => _ = self.named.insert(name, register.clone()),
+            Some('+') => write_clipboard(&register.text),
+            _ => return false,
+        }
+        self.unnamed = Some(register.clone());
+        true
+    }
+
+    /// the contents of a register, `+` reads the system clipboard
+    pub fn get(&self, name: char) -> Option<Cow<'_, Register>> {
+        match name {
+            '"' => self.unnamed.as_ref().map(Cow::Borrowed),
+            '0' => self.yanked.as_ref().map(Cow::Borrowed),
+            '1'..='9' => self
+                .deleted
+                .get(name as usize - '1' as usize)
+                .map(Cow::Borrowed),
+            'a'..='z' => self.named.get(&name).map(Cow::Borrowed),
+            '+' => read_clipboard().map(|text| Cow::Owned(Register::from_clipboard(text))),
+            _ => None,
+        }
+    }
+
+    /// the register to paste, the active register or the unnamed register,
+    /// and its position in the delete ring
+    pub fn take_paste(&mut self) -> Option<(Cow<'_, Register>, Option<usize>)> {
+        let name = self.active.take().unwrap_or('"');
+        let ring = match name {
+            '"' if self.unnamed.is_some() && self.unnamed.as_ref() == self.deleted.front() => {
+                Some(0)
+            }
+            '1'..='9' => Some(name as usize - '1' as usize),
+            _ => None,
+        };
+        Some((self.get(name)?, ring))
+    }
+
+    /// the delete ring entry after `ring`, wrapping back to the newest one
+    pub fn next_in_ring(&self, ring: Option<usize>) -> Option<(&Register, usize)> {
+        if self.deleted.is_empty() {
+            return None;
+        }
+        let ring = ring.map_or(0, |ring| (ring + 1) % self.deleted.len());
+        Some((&self.deleted[ring], ring))
+    }
+
+    /// every register that has something in it, in the order `:registers` lists them
+    pub fn populated(&self) -> Vec<(char, Register)> {
+        let numbered = ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9'];
+        let named = self.named.keys().copied();
+
+        ['"']
+            .into_iter()
+            .chain(numbered)
+            .chain(named)
+            .chain(['+'])
+            .filter_map(|name| Some((name, self.get(name)?.into_owned())))
+            .collect()
+    }
+}
+
+//
+
+/// the contents of one register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// whole lines, pasted on their own lines instead of at the cursor
+    pub linewise: bool,
+}
+
+impl Register {
+    pub const fn new(text: String, linewise: bool) -> Self {
+        Self { text, linewise }
+    }
+
+    /// the clipboard has no such tag, so full lines are treated as linewise
+    fn from_clipboard(text: String) -> Self {
+        let linewise = text.ends_with('\n');
+        Self { text, linewise }
+    }
+}
+
+//
+
+/// the text the last paste inserted, so it can be swapped for an older delete
+#[derive(Debug, Clone)]
+pub struct LastPaste {
+    pub buffer: usize,
+    pub range: Range<usize>,
+    /// the buffer revision right after the paste, any later edit ends the cycle
+    pub revision: u64,
+    /// the position of the pasted text in the delete ring
+    pub ring: Option<usize>,
+}
+
+//
+
+fn read_clipboard() -> Option<String> {
+    CLIPBOARD_READERS.iter().find_map(|reader| {
+        let (cmd, args) = reader.split_first()?;
+        let output = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    })
+}
+
+fn write_clipboard(text: &str) {
+    let written = CLIPBOARD_WRITERS.iter().any(|writer| {
+        let Some((cmd, args)) = writer.split_first() else {
+            return false;
+        };
+        let Ok(mut child) = Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            return false;
+        };
+
+        let wrote = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        // the stdin has to be closed before waiting, or the copy never finishes
+        wrote && child.wait().is_ok_and(|status| status.success())
+    });
+
+    if !written {
+        tracing::warn!("failed to write the system clipboard, no clipboard command worked");
+    }
+}
@@ -0,0 +1,225 @@
+use std::{mem, ops::Range};
+
+use eyre::{bail, Result};
+use ropey::{Rope, RopeSlice};
+
+//
+
+/// the view before a `/` search, restored if the search is cancelled
+pub struct SearchOrigin {
+    pub cursor: usize,
+    pub view_line: usize,
+    /// the previous search, highlighted again
+    pub search: String,
+}
+
+/// a parsed `:s/pattern/replacement/flags` command
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub pattern: String,
+    pub replacement: String,
+    /// the `c` flag, ask before each replacement
+    pub confirm: bool,
+    /// the `g` flag, replace every match on a line instead of only the first one
+    pub global: bool,
+}
+
+impl Substitution {
+    /// parse the arguments after `s`, like `/old/new/c`,
+    /// the first char is the delimiter and a `\` escapes it
+    pub fn parse(args: &str) -> Result<Self> {
+        let mut chars = args.chars();
+        let Some(delimiter) = chars.next() else {
+            bail!("usage: s/pattern/replacement/flags");
+        };
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() || delimiter == '\\' {
+            bail!("`{delimiter}` can't be a delimiter");
+        }
+
+        let mut parts = Vec::new();
+        let mut part = String::new();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some(next) if next == delimiter || next == '\\' => part.push(next),
+                    Some(next) => part.extend(['\\', next]),
+                    None => part.push('\\'),
+                },
+                _ if ch == delimiter => parts.push(mem::take(&mut part)),
+                _ => part.push(ch),
+            }
+        }
+        parts.push(part);
+        if parts.len() > 3 {
+            bail!("too many `{delimiter}`s, escape them with `\\{delimiter}`");
+        }
+
+        let mut parts = parts.into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let (mut confirm, mut global) = (false, false);
+        for flag in parts.next().unwrap_or_default().chars() {
+            match flag {
+                'c' => confirm = true,
+                'g' => global = true,
+                _ => bail!("unknown flag `{flag}`"),
+            }
+        }
+
+        Ok(Self {
+            pattern,
+            replacement,
+            confirm,
+            global,
+        })
+    }
+
+    /// where the next match is looked for after a match ending at `after`,
+    /// the next line unless the `g` flag is set
+    pub fn resume_at(&self, contents: &Rope, after: usize) -> usize {
+        if self.global {
+            after
+        } else {
+            contents.line_to_char(contents.char_to_line(after) + 1)
+        }
+    }
+
+    /// the char ranges it replaces at or after `from` on `lines`,
+    /// every match with the `g` flag, otherwise the first one on each line
+    pub fn matches(&self, contents: &Rope, from: usize, lines: Range<usize>) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut from = from;
+        while let Some(at) = next_match(contents, &self.pattern, from, lines.clone()) {
+            from = self.resume_at(contents, at.end);
+            matches.push(at);
+        }
+        matches
+    }
+}
+
+/// char ranges of `pattern` in `line`, relative to the line start
+///
+/// the pattern is typed on the command line, so it never has a newline
+/// and matches never cross lines
+pub fn matches_in_line(line: RopeSlice, pattern: &str) -> Vec<Range<usize>> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let text = line.to_string();
+    let pattern_len = pattern.chars().count();
+
+    let mut matches = Vec::new();
+    let (mut last_byte, mut last_char) = (0, 0);
+    for (byte, _) in text.match_indices(pattern) {
+        last_char += text[last_byte..byte].chars().count();
+        last_byte = byte;
+        matches.push(last_char..last_char + pattern_len);
+    }
+    matches
+}
+
+/// the char range of the first match at or after `from` on `lines`
+pub fn next_match(
+    contents: &Rope,
+    pattern: &str,
+    from: usize,
+    lines: Range<usize>,
+) -> Option<Range<usize>> {
+    let first = contents.char_to_line(from.min(contents.len_chars()));
+    (first.max(lines.start)..lines.end.min(contents.len_lines())).find_map(|line| {
+        let line_start = contents.line_to_char(line);
+        matches_in_line(contents.line(line), pattern)
+            .into_iter()
+            .map(|m| line_start + m.start..line_start + m.end)
+            .find(|m| m.start >= from)
+    })
+}
+
+/// the char index of the next match after `cursor`, or the previous one before it,
+/// wrapping around the buffer ends
+///
+/// also returns true if the search wrapped around
+pub fn find(
+    contents: &Rope,
+    pattern: &str,
+    cursor: usize,
+    forwards: bool,
+) -> Option<(usize, bool)> {
+    let lines = contents.len_lines();
+    let cursor_line = contents.char_to_line(cursor.min(contents.len_chars()));
+
+    // the cursor line is searched twice, the part after the cursor first
+    // and the part before it last
+    for i in 0..=lines {
+        let line = if forwards {
+            (cursor_line + i) % lines
+        } else {
+            (cursor_line + lines - i % lines) % lines
+        };
+        let line_start = contents.line_to_char(line);
+
+        let mut found = matches_in_line(contents.line(line), pattern)
+            .into_iter()
+            .map(|m| line_start + m.start)
+            .filter(|&at| match i {
+                0 if forwards => at > cursor,
+                0 => at < cursor,
+                _ if i == lines && forwards => at <= cursor,
+                _ if i == lines => at >= cursor,
+                _ => true,
+            });
+
+        let found = if forwards {
+            found.next()
+        } else {
+            found.next_back()
+        };
+
+        if let Some(at) = found {
+            let wrapped = if forwards { at <= cursor } else { at >= cursor };
+            return Some((at, wrapped));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_substitution() {
+        let sub = Substitution::parse("/old/new/gc").unwrap();
+        assert_eq!(sub.pattern, "old");
+        assert_eq!(sub.replacement, "new");
+        assert!(sub.confirm && sub.global);
+
+        let sub = Substitution::parse("#a/b#c\\#d").unwrap();
+        assert_eq!(sub.pattern, "a/b");
+        assert_eq!(sub.replacement, "c#d");
+        assert!(!sub.confirm && !sub.global);
+
+        assert!(Substitution::parse("").is_err());
+        assert!(Substitution::parse("aoldanewa").is_err());
+        assert!(Substitution::parse("/a/b/x").is_err());
+        assert!(Substitution::parse("/a/b/c/d").is_err());
+    }
+
+    #[test]
+    fn substitution_without_g_takes_the_first_match_on_each_line() {
+        let contents = Rope::from_str("aa\nxa a\n");
+        let sub = Substitution::parse("/a/b/").unwrap();
+        assert_eq!(sub.matches(&contents, 0, 0..3), [0..1, 4..5]);
+    }
+
+    #[test]
+    fn substitution_with_g_takes_every_match() {
+        let contents = Rope::from_str("aa\nxa a\n");
+        let sub = Substitution::parse("/a/b/g").unwrap();
+        assert_eq!(sub.matches(&contents, 0, 0..3), [0..1, 1..2, 4..5, 6..7]);
+        // only the lines in the range
+        assert_eq!(sub.matches(&contents, 3, 1..2), [4..5, 6..7]);
+    }
+}
@@ -0,0 +1,162 @@
+use std::mem;
+
+use ratatui::layout::{Constraint, Layout, Rect};
+
+use super::view::BufferView;
+
+//
+
+/// the split windows, a tree with a view in each leaf
+///
+/// the focused view isn't in the tree, it is [`Editor::view`](super::Editor::view)
+/// and [`Split::Focused`] marks where it goes
+#[derive(Default)]
+pub enum Split {
+    #[default]
+    Focused,
+    View(BufferView),
+    /// one above the other
+    Horizontal(Box<Split>, Box<Split>),
+    /// side by side
+    Vertical(Box<Split>, Box<Split>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+impl Split {
+    /// split the focused view in two, the new view shows the same buffer at the same place,
+    /// the focus stays in the top or left half
+    pub fn split(&mut self, focused: &BufferView, vertical: bool) {
+        let Some(leaf) = self.focused_leaf() else {
+            return;
+        };
+        let first = Box::new(Split::Focused);
+        let second = Box::new(Split::View(focused.clone()));
+        *leaf = if vertical {
+            Split::Vertical(first, second)
+        } else {
+            Split::Horizontal(first, second)
+        };
+    }
+
+    /// remove the focused view, returns the view focused instead, `None` if it is the only view
+    pub fn close(&mut self) -> Option<BufferView> {
+        let (Split::Horizontal(a, b) | Split::Vertical(a, b)) = self else {
+            return None;
+        };
+
+        let sibling = if matches!(**a, Split::Focused) {
+            b
+        } else if matches!(**b, Split::Focused) {
+            a
+        } else {
+            return a.close().or_else(|| b.close());
+        };
+        let mut sibling = mem::take(&mut **sibling);
+        let view = sibling.focus_first();
+        *self = sibling;
+        view
+    }
+
+    /// move the focus to the closest view in `dir` from the focused view,
+    /// the views are laid out in `area`, returns false if there is no view there
+    pub fn focus(&mut self, area: Rect, dir: Direction, focused: &mut BufferView) -> bool {
+        let mut leaves = self.layout_mut(area);
+        let Some(from) = leaves
+            .iter()
+            .position(|(_, leaf)| matches!(leaf, Split::Focused))
+        else {
+            return false;
+        };
+        let f = leaves[from].0;
+
+        // the gap to a view on that side, if it overlaps the focused view on the other axis
+        let gap = |r: Rect| -> Option<u16> {
+            let beside = r.y < f.bottom() && f.y < r.bottom();
+            let above = r.x < f.right() && f.x < r.right();
+            match dir {
+                Direction::Left if beside && r.right() <= f.x => Some(f.x - r.right()),
+                Direction::Right if beside && r.x >= f.right() => Some(r.x - f.right()),
+                Direction::Up if above && r.bottom() <= f.y => Some(f.y - r.bottom()),
+                Direction::Down if above && r.y >= f.bottom() => Some(r.y - f.bottom()),
+                _ => None,
+            }
+        };
+        let Some(to) = leaves
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (r, _))| Some((gap(*r)?, i)))
+            .min()
+            .map(|(_, i)| i)
+        else {
+            return false;
+        };
+
+        let Split::View(view) = mem::take(&mut *leaves[to].1) else {
+            return false;
+        };
+        *leaves[from].1 = Split::View(mem::replace(focused, view));
+        true
+    }
+
+    /// each leaf and where it is drawn in `area`
+    pub fn layout_mut(&mut self, area: Rect) -> Vec<(Rect, &mut Split)> {
+        let mut leaves = Vec::new();
+        self.layout_into(area, &mut leaves);
+        leaves
+    }
+
+    /// every view in the tree, not including the focused view
+    pub fn views_mut(&mut self) -> Vec<&mut BufferView> {
+        self.layout_mut(Rect::default())
+            .into_iter()
+            .filter_map(|(_, leaf)| match leaf {
+                Split::View(view) => Some(view),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn layout_into<'a>(&'a mut self, area: Rect, leaves: &mut Vec<(Rect, &'a mut Split)>) {
+        let halves = [Constraint::Fill(1), Constraint::Fill(1)];
+        let ([first, second], a, b) = match self {
+            Split::Horizontal(a, b) => (Layout::vertical(halves).areas::<2>(area), a, b),
+            // a blank column between the views
+            Split::Vertical(a, b) => (Layout::horizontal(halves).spacing(1).areas::<2>(area), a, b),
+            leaf => {
+                leaves.push((area, leaf));
+                return;
+            }
+        };
+        a.layout_into(first, leaves);
+        b.layout_into(second, leaves);
+    }
+
+    fn focused_leaf(&mut self) -> Option<&mut Split> {
+        match self {
+            Split::Focused => Some(self),
+            Split::View(_) => None,
+            Split::Horizontal(a, b) | Split::Vertical(a, b) => {
+                a.focused_leaf().or_else(|| b.focused_leaf())
+            }
+        }
+    }
+
+    /// focus the top left view, `None` if something is already focused
+    fn focus_first(&mut self) -> Option<BufferView> {
+        match self {
+            Split::Focused => None,
+            Split::View(_) => match mem::take(self) {
+                Split::View(view) => Some(view),
+                _ => None,
+            },
+            Split::Horizontal(a, _) | Split::Vertical(a, _) => a.focus_first(),
+        }
+    }
+}
@@ -1,11 +1,529 @@
-use ratatui::style::Color;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+    time::{Duration, Instant},
+};
+
+use arc_swap::{ArcSwap, Guard};
+use clap::ValueEnum;
+use ratatui::{
+    buffer::Buffer,
+    style::{Color, Modifier, Style},
+};
+
+//
+
+/// how long to wait for the terminal to answer the background color query
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// the terminal didn't finish answering the background query in time,
+/// the rest of the answer is still on its way, see [`drain_query_reply`]
+static QUERY_REPLY_PENDING: AtomicBool = AtomicBool::new(false);
+
+static THEME: LazyLock<ArcSwap<Theme>> = LazyLock::new(|| ArcSwap::from_pointee(Theme::dark()));
+
+/// the theme in use
+pub fn current() -> Guard<Arc<Theme>> {
+    THEME.load()
+}
+
+/// switch to another theme, starting from the next frame
+pub fn set(theme: Theme) {
+    THEME.store(theme.into());
+}
+
+//
+
+/// colors used by the editor
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// the default text color
+    pub text: Color,
+    pub background: Color,
+    pub background_light: Color,
+    pub cursor_line: Color,
+    pub cursor: Color,
+    pub buffer_line: Color,
+    pub inactive: Color,
+    pub accent: Color,
+    pub diff_added: Color,
+    pub diff_removed: Color,
+    pub diff_changed: Color,
+    /// the background of search matches
+    pub search_match: Color,
+    /// the background of the bracket matching the one under the cursor
+    pub matching_bracket: Color,
+    /// highlight capture names like `keyword` or `string.escape` and their styles
+    pub syntax: HashMap<String, Style>,
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            text: Color::Rgb(0xCA, 0xD3, 0xF5),
+            background: Color::Rgb(0x24, 0x27, 0x3A),
+            background_light: Color::Rgb(0x36, 0x3A, 0x4F),
+            cursor_line: Color::Rgb(0x2A, 0x2B, 0x3C),
+            cursor: Color::Rgb(0xB4, 0xBE, 0xFE),
+            buffer_line: Color::Rgb(0x18, 0x18, 0x25),
+            inactive: Color::Rgb(0x45, 0x47, 0x5A),
+            accent: Color::from_u32(0xEED49F),
+            diff_added: Color::Rgb(0x2B, 0x3B, 0x34),
+            diff_removed: Color::Rgb(0x3F, 0x2A, 0x36),
+            diff_changed: Color::Rgb(0x2C, 0x32, 0x4A),
+            search_match: Color::Rgb(0x55, 0x4B, 0x3A),
+            matching_bracket: Color::Rgb(0x49, 0x4D, 0x64),
+            syntax: syntax(&[
+                ("keyword", 0xC6A0F6, Modifier::BOLD),
+                ("string", 0xA6DA95, Modifier::empty()),
+                ("char", 0xA6DA95, Modifier::empty()),
+                ("escape", 0xF5BDE6, Modifier::empty()),
+                ("comment", 0x6E738D, Modifier::ITALIC),
+                ("number", 0xF5A97F, Modifier::empty()),
+                ("integer", 0xF5A97F, Modifier::empty()),
+                ("float", 0xF5A97F, Modifier::empty()),
+                ("boolean", 0xF5A97F, Modifier::empty()),
+                ("constant", 0xF5A97F, Modifier::empty()),
+                ("type", 0xEED49F, Modifier::empty()),
+                ("function", 0x8AADF4, Modifier::empty()),
+                ("macro", 0x8BD5CA, Modifier::empty()),
+                ("attribute", 0xEED49F, Modifier::empty()),
+                ("lifetime", 0xED8796, Modifier::empty()),
+                ("label", 0x7DC4E4, Modifier::empty()),
+                ("field", 0xB7BDF8, Modifier::empty()),
+                ("property", 0xB7BDF8, Modifier::empty()),
+                ("operator", 0x91D7E3, Modifier::empty()),
+                ("punctuation", 0x939AB7, Modifier::empty()),
+                ("link", 0x7DC4E4, Modifier::UNDERLINED),
+            ]),
+        }
+    }
+
+    /// tuned for contrast on a light background, the syntax colors are darker than the dark theme's
+    pub fn light() -> Self {
+        Self {
+            text: Color::Rgb(0x4C, 0x4F, 0x69),
+            background: Color::Rgb(0xEF, 0xF1, 0xF5),
+            background_light: Color::Rgb(0xDC, 0xE0, 0xE8),
+            cursor_line: Color::Rgb(0xE6, 0xE9, 0xEF),
+            cursor: Color::Rgb(0x1E, 0x66, 0xF5),
+            buffer_line: Color::Rgb(0xCC, 0xD0, 0xDA),
+            inactive: Color::Rgb(0x9C, 0xA0, 0xB0),
+            accent: Color::Rgb(0xA8, 0x62, 0x00),
+            diff_added: Color::Rgb(0xD3, 0xEB, 0xD0),
+            diff_removed: Color::Rgb(0xF4, 0xD3, 0xDA),
+            diff_changed: Color::Rgb(0xD6, 0xDE, 0xF6),
+            search_match: Color::Rgb(0xF5, 0xE0, 0xB0),
+            matching_bracket: Color::Rgb(0xBC, 0xC0, 0xCC),
+            syntax: syntax(&[
+                ("keyword", 0x8839EF, Modifier::BOLD),
+                ("string", 0x2F7D1F, Modifier::empty()),
+                ("char", 0x2F7D1F, Modifier::empty()),
+                ("escape", 0xC2479E, Modifier::empty()),
+                ("comment", 0x7C7F93, Modifier::ITALIC),
+                ("number", 0xC84A00, Modifier::empty()),
+                ("integer", 0xC84A00, Modifier::empty()),
+                ("float", 0xC84A00, Modifier::empty()),
+                ("boolean", 0xC84A00, Modifier::empty()),
+                ("constant", 0xC84A00, Modifier::empty()),
+                ("type", 0x9A6A00, Modifier::empty()),
+                ("function", 0x1E56D0, Modifier::empty()),
+                ("macro", 0x137A7F, Modifier::empty()),
+                ("attribute", 0x9A6A00, Modifier::empty()),
+                ("lifetime", 0xD20F39, Modifier::empty()),
+                ("label", 0x037FB5, Modifier::empty()),
+                ("field", 0x5865D6, Modifier::empty()),
+                ("property", 0x5865D6, Modifier::empty()),
+                ("operator", 0x037FB5, Modifier::empty()),
+                ("punctuation", 0x7C7F93, Modifier::empty()),
+                ("link", 0x037FB5, Modifier::UNDERLINED),
+            ]),
+        }
+    }
+
+    /// the style of a highlight capture, `keyword.function` falls back to `keyword`
+    pub fn capture(&self, mut capture: &str) -> Option<Style> {
+        loop {
+            if let Some(style) = self.syntax.get(capture) {
+                return Some(*style);
+            }
+            capture = capture.rsplit_once('.')?.0;
+        }
+    }
+
+    /// override syntax styles with the user config, a color and modifiers, like
+    ///
+    /// ```ron
+    /// {
+    ///     "keyword": "bold #C6A0F6",
+    ///     "comment": "italic gray",
+    /// }
+    /// ```
+    fn load_syntax(&mut self, file: &str) {
+        let path = crate::config_dir().join(file);
+        let config: HashMap<String, String> = match fs::read_to_string(&path) {
+            Ok(config) => match ron::from_str(&config) {
+                Ok(config) => config,
+                Err(err) => {
+                    tracing::error!("failed to load syntax colors from {path:?}: {err}");
+                    return;
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                tracing::error!("failed to load syntax colors from {path:?}: {err}");
+                return;
+            }
+        };
+
+        for (capture, style) in config {
+            match parse_style(&style) {
+                Some(style) => _ = self.syntax.insert(capture, style),
+                None => tracing::error!("invalid style `{style}` for `{capture}` in {path:?}"),
+            }
+        }
+    }
+
+    /// override colors with the user config, by field name, and syntax styles in `[syntax]`
+    ///
+    /// ```toml
+    /// background = "#24273A"
+    /// accent = "yellow"
+    ///
+    /// [syntax]
+    /// keyword = "bold #C6A0F6"
+    /// ```
+    fn load_colors(&mut self, file: &str) {
+        let path = crate::config_dir().join(file);
+        let config: toml::Table = match fs::read_to_string(&path) {
+            Ok(config) => match toml::from_str(&config) {
+                Ok(config) => config,
+                Err(err) => {
+                    tracing::error!("failed to load the theme from {path:?}: {err}");
+                    return;
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) => {
+                tracing::error!("failed to load the theme from {path:?}: {err}");
+                return;
+            }
+        };
+
+        for (name, value) in config {
+            if let ("syntax", toml::Value::Table(syntax)) = (name.as_str(), &value) {
+                for (capture, style) in syntax {
+                    match style.as_str().and_then(parse_style) {
+                        Some(style) => _ = self.syntax.insert(capture.clone(), style),
+                        None => {
+                            tracing::error!("invalid style `{style}` for `{capture}` in {path:?}")
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let Some(color) = value.as_str().and_then(|color| Color::from_str(color).ok()) else {
+                tracing::error!("invalid color `{value}` for `{name}` in {path:?}");
+                continue;
+            };
+            match self.color_mut(&name) {
+                Some(field) => *field = color,
+                None => tracing::error!("unknown theme color `{name}` in {path:?}"),
+            }
+        }
+    }
+
+    fn color_mut(&mut self, name: &str) -> Option<&mut Color> {
+        Some(match name {
+            "text" => &mut self.text,
+            "background" => &mut self.background,
+            "background_light" => &mut self.background_light,
+            "cursor_line" => &mut self.cursor_line,
+            "cursor" => &mut self.cursor,
+            "buffer_line" => &mut self.buffer_line,
+            "inactive" => &mut self.inactive,
+            "accent" => &mut self.accent,
+            "diff_added" => &mut self.diff_added,
+            "diff_removed" => &mut self.diff_removed,
+            "diff_changed" => &mut self.diff_changed,
+            "search_match" => &mut self.search_match,
+            "matching_bracket" => &mut self.matching_bracket,
+            _ => return None,
+        })
+    }
+}
+
+fn syntax(styles: &[(&str, u32, Modifier)]) -> HashMap<String, Style> {
+    styles
+        .iter()
+        .map(|&(capture, color, modifier)| {
+            let style = Style::new()
+                .fg(Color::from_u32(color))
+                .add_modifier(modifier);
+            (capture.to_string(), style)
+        })
+        .collect()
+}
+
+/// parse space separated modifiers and a color, like `bold italic #C6A0F6`
+fn parse_style(style: &str) -> Option<Style> {
+    style
+        .split_whitespace()
+        .try_fold(Style::new(), |style, word| {
+            let modifier = match word {
+                "bold" => Modifier::BOLD,
+                "dim" => Modifier::DIM,
+                "italic" => Modifier::ITALIC,
+                "underlined" | "underline" => Modifier::UNDERLINED,
+                "reversed" => Modifier::REVERSED,
+                "crossed-out" | "strikethrough" => Modifier::CROSSED_OUT,
+                color => return Some(style.fg(Color::from_str(color).ok()?)),
+            };
+            Some(style.add_modifier(modifier))
+        })
+}
 
 //
 
-pub const BACKGROUND: Color = Color::Rgb(0x24, 0x27, 0x3A);
-pub const BACKGROUND_LIGHT: Color = Color::Rgb(0x36, 0x3A, 0x4F);
-pub const CURSOR_LINE: Color = Color::Rgb(0x2A, 0x2B, 0x3C);
-pub const CURSOR: Color = Color::Rgb(0xB4, 0xBE, 0xFE);
-pub const BUFFER_LINE: Color = Color::Rgb(0x18, 0x18, 0x25);
-pub const INACTIVE: Color = Color::Rgb(0x45, 0x47, 0x5A);
-pub const ACCENT: Color = Color::from_u32(0xEED49F);
+/// the terminal background, picks the dark or the light theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Background {
+    /// ask the terminal for its background color
+    Auto,
+    Dark,
+    Light,
+}
+
+impl Background {
+    /// resolve [`Background::Auto`] from `COLORFGBG` or by asking the terminal,
+    /// the terminal has to be in raw mode
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        colorfgbg_background()
+            .or_else(query_background)
+            .unwrap_or(Self::Dark)
+    }
+
+    /// the bundled theme, with the user syntax colors from `syntax-dark.ron` or `syntax-light.ron`
+    pub fn theme(self) -> Theme {
+        let (mut theme, config) = match self {
+            Self::Light => (Theme::light(), "syntax-light.ron"),
+            Self::Auto | Self::Dark => (Theme::dark(), "syntax-dark.ron"),
+        };
+        theme.load_syntax(config);
+        theme.load_colors("theme.toml");
+        theme
+    }
+}
+
+/// `COLORFGBG` is like `15;0`, the last number is the background palette index
+fn colorfgbg_background() -> Option<Background> {
+    let colorfgbg = env::var("COLORFGBG").ok()?;
+    let bg: u8 = colorfgbg.rsplit(';').next()?.parse().ok()?;
+    Some(match bg {
+        7 | 9..=15 => Background::Light,
+        _ => Background::Dark,
+    })
+}
+
+/// ask the terminal for the background color with OSC 11
+fn query_background() -> Option<Background> {
+    // every terminal answers the device attributes query, so the reply
+    // is known to be complete even if the background query is ignored
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    let mut reply = Vec::new();
+    if !read_query_reply(&mut reply, BACKGROUND_QUERY_TIMEOUT) {
+        QUERY_REPLY_PENDING.store(true, Ordering::Relaxed);
+    }
+    let (r, g, b) = parse_osc11(&reply)?;
+
+    // perceived lightness
+    let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    Some(if luma > 500 * 0xFFFF {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+/// throw away the rest of a background query reply that came too late,
+/// before crossterm starts reading keys, or the reply would run as keys
+pub fn drain_query_reply() {
+    if QUERY_REPLY_PENDING.swap(false, Ordering::Relaxed) {
+        read_query_reply(&mut Vec::new(), BACKGROUND_QUERY_TIMEOUT);
+    }
+}
+
+/// read the terminal's reply from stdin until the device attributes reply, or until `timeout`,
+/// returns true if the whole reply was read
+///
+/// it is read one byte at a time, so the keys typed after it stay for crossterm
+#[cfg(unix)]
+fn read_query_reply(reply: &mut Vec<u8>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // the device attributes reply ends with `c`
+        if reply.last() == Some(&b'c') && reply.windows(3).any(|w| w == b"\x1b[?") {
+            return true;
+        }
+
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return false;
+        }
+
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = left.as_millis().clamp(1, i32::MAX as u128) as i32;
+        // SAFETY: `fd` is one valid pollfd
+        let ready = unsafe { libc::poll(&mut fd, 1, timeout_ms) };
+        if ready < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        if ready <= 0 {
+            return false;
+        }
+
+        let mut byte = 0u8;
+        // SAFETY: reads at most one byte into `byte`
+        if unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } != 1 {
+            return false;
+        }
+        reply.push(byte);
+    }
+}
+
+#[cfg(not(unix))]
+fn read_query_reply(_: &mut Vec<u8>, _: Duration) -> bool {
+    true
+}
+
+/// parse `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` followed by `BEL` or `ESC \`
+fn parse_osc11(reply: &[u8]) -> Option<(u16, u16, u16)> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let (_, rgb) = reply.split_once("]11;rgb:")?;
+    let rgb = rgb.split(['\x07', '\x1b']).next()?;
+
+    let mut channels = rgb.split('/').map(|channel| {
+        // channels have 1 to 4 hex digits, scale them all to 16 bits
+        let value = u16::from_str_radix(channel, 16).ok()?;
+        let max = (1u32 << (4 * channel.len().clamp(1, 4))) - 1;
+        Some((value as u32 * 0xFFFF / max) as u16)
+    });
+    Some((channels.next()??, channels.next()??, channels.next()??))
+}
+
+//
+
+/// how many colors the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorSupport {
+    /// detect from `COLORTERM`, `TERM` and `NO_COLOR`
+    Auto,
+    /// no colors, highlights are shown with reversed video
+    Never,
+    /// the 256 color palette
+    #[value(name = "256")]
+    Ansi256,
+    /// 24-bit colors
+    Truecolor,
+}
+
+impl ColorSupport {
+    /// resolve [`ColorSupport::Auto`] from the environment
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let var = |name| env::var(name).unwrap_or_default();
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) || var("TERM") == "dumb" {
+            return Self::Never;
+        }
+        if matches!(var("COLORTERM").as_str(), "truecolor" | "24bit") {
+            return Self::Truecolor;
+        }
+        Self::Ansi256
+    }
+
+    /// convert every color in a rendered frame to ones the terminal supports
+    pub fn downgrade(self, buf: &mut Buffer) {
+        if matches!(self, Self::Auto | Self::Truecolor) {
+            return;
+        }
+
+        for cell in buf.content.iter_mut() {
+            if self == Self::Never {
+                // light backgrounds are highlights, like the cursor or a selected entry
+                if is_light(cell.bg) {
+                    cell.modifier.insert(Modifier::REVERSED);
+                }
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+                continue;
+            }
+
+            cell.fg = to_ansi256(cell.fg);
+            cell.bg = to_ansi256(cell.bg);
+        }
+    }
+}
+
+fn is_light(color: Color) -> bool {
+    match color {
+        Color::Rgb(r, g, b) => 2 * r as u32 + 7 * g as u32 + b as u32 > 10 * 128,
+        Color::White | Color::Gray => true,
+        _ => false,
+    }
+}
+
+fn to_ansi256(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+
+    // the closest color in the 6x6x6 cube and in the grayscale ramp
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |c: u8| match c {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (c - 35) / 40,
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (
+        LEVELS[ri as usize],
+        LEVELS[gi as usize],
+        LEVELS[bi as usize],
+    );
+
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (avg.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 8 + 10 * gray_index;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(cr, r) + d(cg, g) + d(cb, b)
+    };
+
+    if distance((gray, gray, gray)) < distance(cube) {
+        Color::Indexed(232 + gray_index)
+    } else {
+        Color::Indexed(16 + 36 * ri + 6 * gi + bi)
+    }
+}
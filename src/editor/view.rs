@@ -1,29 +1,50 @@
-use std::{cmp::Ordering, env};
+use std::{cmp::Reverse, collections::BTreeSet, env, iter, ops::Range};
 
 use ratatui::{
     layout::{Constraint, Layout, Rect},
-    style::{Color, Style},
+    style::Style,
     text::Line,
     widgets::{Block, Paragraph, Widget},
     Frame,
 };
-// use unicode_segmentation::GraphemeCursor;
+use ropey::RopeSlice;
+use tree_sitter::{Node, Query, QueryCursor, StreamingIterator};
+use unicode_width::UnicodeWidthChar;
 
 use crate::{
-    buffer::{Buffer, BufferInner},
+    buffer::{Buffer, BufferInner, FileFormat, Syntax},
     mode::Mode,
 };
 
-use super::theme;
+use super::{
+    search,
+    theme::{self, Theme},
+};
+
+//
+
+/// the columns a wrapped row is indented by, the wrap marker is in them
+const WRAP_INDENT: usize = 2;
+/// how many lines away a blank line looks for the indent guides to continue
+const GUIDE_SEARCH: usize = 100;
+/// the bracket pairs `%` jumps between and the cursor highlights
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
 
 //
 
+#[derive(Clone)]
 pub struct BufferView {
     pub buffer_index: usize,
     pub cursor: usize,
     /// where the cursor X would be if the line wasn't too short
     pub cursor_x_unclamp: usize,
+    /// the cursor `cursor_x_unclamp` was kept for, moving the cursor anywhere else resets it
+    cursor_x_unclamp_at: usize,
     pub view_line: usize,
+    /// the first visible display column, long lines scroll sideways when they don't wrap
+    pub view_col: usize,
+    /// the widest line numbers seen, the gutter only grows so the text doesn't shift
+    number_width: u16,
 }
 
 impl BufferView {
@@ -32,7 +53,10 @@ impl BufferView {
             buffer_index,
             cursor: 0,
             cursor_x_unclamp: 0,
+            cursor_x_unclamp_at: 0,
             view_line: 0,
+            view_col: 0,
+            number_width: 0,
         }
     }
 
@@ -40,17 +64,36 @@ impl BufferView {
         &mut self,
         buffer: &Buffer,
         mode: &Mode,
+        search: &str,
         area: Rect,
         frame: &mut ratatui::prelude::Frame,
+        focused: bool,
     ) -> (usize, usize) {
         let [buffer_area, bufferline_area] =
             Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
 
-        let ((row, col), real_cursor) =
-            self.render_buffer(buffer, buffer_area, frame, mode.is_insert());
+        // the mode and the cursor belong to the focused split only
+        let (selection, linewise) = match *mode {
+            Mode::Visual { anchor, linewise } if focused => (
+                Some(visual_range(buffer, anchor, self.cursor, linewise)),
+                linewise,
+            ),
+            _ => (None, false),
+        };
+
+        let ((row, col), real_cursor) = self.render_buffer(
+            buffer,
+            buffer_area,
+            frame,
+            focused.then_some(mode.is_insert()),
+            selection,
+            linewise,
+            search,
+        );
 
         // render the buffer line
-        self.render_bufferline(buffer, bufferline_area, frame, mode.as_str(), col, row);
+        let mode = if focused { mode.as_str() } else { "" };
+        self.render_bufferline(buffer, bufferline_area, frame, mode, col, row);
 
         real_cursor
     }
@@ -60,20 +103,71 @@ impl BufferView {
         buffer: &Buffer,
         area: Rect,
         frame: &mut Frame,
-        is_insert_mode: bool,
+        // `None` if the cursor isn't drawn
+        is_insert_mode: Option<bool>,
+        selection: Option<Range<usize>>,
+        linewise: bool,
+        search: &str,
     ) -> ((usize, usize), (usize, usize)) {
         let lines = buffer.contents.len_lines();
-
-        let [_, line_numbers_area, _, buffer_area] = Layout::horizontal([
-            Constraint::Length(2),
-            Constraint::Length(lines.ilog10() as u16 + 1),
-            Constraint::Length(2),
+        self.number_width = self.number_width.max(number_width(lines));
+        let gutter = self
+            .number_width
+            .max(buffer.options.numberwidth.min(u16::MAX as usize) as u16);
+
+        let padding = |columns: usize| Constraint::Length(columns.min(u16::MAX as usize) as u16);
+        let [left_padding_area, line_numbers_area, _, buffer_area] = Layout::horizontal([
+            padding(buffer.options.gutterleft),
+            Constraint::Length(gutter),
+            padding(buffer.options.gutterright),
             Constraint::Min(0),
         ])
         .areas(area);
 
+        let wrap = buffer.options.wrap.then_some(buffer_area.width as usize);
+        let height = buffer_area.height as usize;
+
+        let tabstop = buffer.options.tabstop;
         let row = buffer.contents.char_to_line(self.cursor);
-        let col = self.cursor - buffer.contents.line_to_char(row);
+        let line_start = buffer.contents.line_to_char(row);
+        // the bufferline counts graphemes, an emoji or an accented letter is one column
+        let mut col = 0;
+        let mut at = line_start;
+        while at < self.cursor {
+            at = buffer.next_grapheme(at);
+            col += 1;
+        }
+
+        // the wrapped row of the cursor line the cursor is on, and its column there
+        let cursor_starts = line_starts(buffer, row, wrap);
+        let cursor_sub_row =
+            cursor_starts.partition_point(|&start| start <= self.cursor - line_start) - 1;
+        let row_start = line_start + cursor_starts[cursor_sub_row];
+        let indent = if cursor_sub_row > 0 { WRAP_INDENT } else { 0 };
+        let cursor_x = columns_after(
+            buffer.contents.slice(row_start..self.cursor).chars(),
+            indent,
+            tabstop,
+        );
+        // the cursor covers the whole grapheme under it, a wide char takes 2 columns
+        let width = (columns_after(
+            buffer
+                .contents
+                .slice(self.cursor..buffer.next_grapheme(self.cursor))
+                .chars(),
+            cursor_x,
+            tabstop,
+        ) - cursor_x)
+            .max(1);
+
+        // scroll sideways until the whole cursor grapheme fits
+        if wrap.is_some() {
+            self.view_col = 0;
+        } else {
+            let min = (cursor_x + width).saturating_sub(buffer_area.width as usize);
+            self.view_col = self.view_col.clamp(min.min(cursor_x), cursor_x);
+        }
+        let cursor_x = cursor_x - self.view_col;
 
         // keep the cursor within view
         // tracing::debug!(
@@ -81,10 +175,40 @@ impl BufferView {
         //     self.view_line,
         //     buffer_area.height
         // );
-        let min = (row + 3).saturating_sub(buffer_area.height as usize);
+        let min = (row + 3).saturating_sub(height);
         let max = row;
         let (min, max) = (min.min(max), min.max(max));
         self.view_line = self.view_line.clamp(min, max);
+        // wrapped lines take more rows, so scroll further until the cursor row fits
+        if wrap.is_some() {
+            let mut above = (self.view_line..row)
+                .map(|line| line_starts(buffer, line, wrap).len())
+                .sum::<usize>()
+                + cursor_sub_row;
+            while above + 3 > height && self.view_line < row {
+                above -= line_starts(buffer, self.view_line, wrap).len();
+                self.view_line += 1;
+            }
+        }
+
+        // the buffer line and which of its wrapped rows is on each row of the view
+        let mut rows = Vec::with_capacity(height);
+        for line in self.view_line.. {
+            if rows.len() >= height {
+                break;
+            }
+            let count = if line < lines {
+                line_starts(buffer, line, wrap).len()
+            } else {
+                1
+            };
+            rows.extend((0..count).map(|sub_row| (line, sub_row)));
+        }
+        rows.truncate(height);
+        let cursor_y = rows
+            .iter()
+            .position(|&view_row| view_row == (row, cursor_sub_row))
+            .unwrap_or(0);
         // if row < self.view_line {
         //     self.view_line = row;
         // }
@@ -98,17 +222,31 @@ impl BufferView {
         // );
 
         // render line numbers
+        // the empty line after the last newline isn't a line of its own
+        let text_lines = if lines > 1 && buffer.contents.line(lines - 1).len_chars() == 0 {
+            lines - 1
+        } else {
+            lines
+        };
         let line_numbers = LineNumbers {
-            line: self.view_line,
+            rows: &rows,
             row,
-            lines,
+            lines: text_lines,
+            eob: buffer.options.eob,
+            bookmarks: &buffer.bookmarks,
         };
-        frame.render_widget(line_numbers, line_numbers_area);
+        // the bookmark glyphs go in the left padding
+        frame.render_widget(line_numbers, line_numbers_area.union(left_padding_area));
 
         // render the text buffer
         let buffer_widget = BufferWidget {
             buffer,
             line: self.view_line,
+            col: self.view_col,
+            selection,
+            linewise,
+            search,
+            wrap,
         };
         frame.render_widget(buffer_widget, buffer_area);
 
@@ -116,17 +254,28 @@ impl BufferView {
             self.render_welcome(buffer_area, frame);
         }
 
-        // render the cursor and cursor crosshair
-        let cursor = Cursor {
-            line: self.view_line,
-            row,
-            col,
-            is_insert_mode,
-        };
-        frame.render_widget(cursor, buffer_area);
+        // render the cursor and cursor crosshair, only in the focused split
+        if let Some(is_insert_mode) = is_insert_mode {
+            // huge buffers skip it, an unmatched bracket would scan to the end every frame
+            let bracket = (!buffer.huge)
+                .then(|| matching_bracket(buffer, self.cursor))
+                .flatten()
+                .and_then(|at| self.view_position(buffer, &rows, at, wrap));
+            let cursor = Cursor {
+                y: cursor_y,
+                x: cursor_x,
+                width,
+                is_insert_mode,
+                line: buffer.options.cursorline,
+                column: buffer.options.cursorcolumn,
+                colorcolumn: buffer.options.colorcolumn,
+                bracket,
+            };
+            frame.render_widget(cursor, buffer_area);
+        }
 
-        let real_cursor_row = row - self.view_line + buffer_area.y as usize;
-        let real_cursor_col = col + buffer_area.x as usize;
+        let real_cursor_row = cursor_y + buffer_area.y as usize;
+        let real_cursor_col = cursor_x + buffer_area.x as usize;
 
         if let Some(cursor_node) = buffer.syntax.as_ref().and_then(|syntax| {
             syntax
@@ -145,7 +294,32 @@ impl BufferView {
         ((row, col), (real_cursor_row, real_cursor_col))
     }
 
+    /// the row and column of char `at` in the view, `None` if it is scrolled out of it
+    fn view_position(
+        &self,
+        buffer: &Buffer,
+        rows: &[(usize, usize)],
+        at: usize,
+        wrap: Option<usize>,
+    ) -> Option<(usize, usize)> {
+        let line = buffer.contents.char_to_line(at);
+        let line_start = buffer.contents.line_to_char(line);
+        let starts = line_starts(buffer, line, wrap);
+        let sub_row = starts.partition_point(|&start| start <= at - line_start) - 1;
+        let y = rows.iter().position(|&row| row == (line, sub_row))?;
+
+        let row_start = line_start + starts[sub_row];
+        let indent = if sub_row > 0 { WRAP_INDENT } else { 0 };
+        let x = columns_after(
+            buffer.contents.slice(row_start..at).chars(),
+            indent,
+            buffer.options.tabstop,
+        );
+        Some((y, x.checked_sub(self.view_col)?))
+    }
+
     fn render_welcome(&mut self, area: Rect, frame: &mut Frame) {
+        let theme = theme::current();
         let [_, area, _] = Layout::vertical([
             Constraint::Fill(1),
             Constraint::Length(11),
@@ -167,7 +341,7 @@ impl BufferView {
             Line::from_iter(["have a nice day"]),
         ])
         .centered()
-        .style(Style::new().bg(theme::BACKGROUND).fg(theme::CURSOR));
+        .style(Style::new().bg(theme.background).fg(theme.cursor));
 
         frame.render_widget(widget, area);
     }
@@ -181,17 +355,30 @@ impl BufferView {
         col: usize,
         row: usize,
     ) {
+        let theme = theme::current();
         let cursor_pos = format!("{row}:{col}");
-        let left = if buffer.modified {
-            Line::from_iter([" ", mode, "   ", buffer.name.as_ref(), " [+]"])
-        } else {
-            Line::from_iter([" ", mode, "   ", buffer.name.as_ref()])
-        };
-        let right = Line::from_iter([cursor_pos.as_str(), " "]);
+        let mut left = Line::from_iter([" ", mode, "   ", buffer.name.as_ref()]);
+        if buffer.modified {
+            left.push_span(" [+]");
+        }
+        if buffer.huge {
+            left.push_span(" [huge, read-only]");
+        }
+        // unix is the usual one, so only the others are shown
+        if let Some(format @ (FileFormat::Dos | FileFormat::Mac)) = buffer.fileformat() {
+            left.push_span(format!(" [{}]", format.as_str()));
+        }
+        let mut right = Line::default();
+        if let Some(lang) = buffer.lang() {
+            right.push_span(lang.name());
+            right.push_span("  ");
+        }
+        right.push_span(cursor_pos.as_str());
+        right.push_span(" ");
         let info = Block::new()
             .title(left.left_aligned())
             .title(right.right_aligned())
-            .style(Style::new().bg(theme::BUFFER_LINE));
+            .style(Style::new().bg(theme.buffer_line));
         frame.render_widget(info, area);
     }
 
@@ -242,31 +429,102 @@ impl BufferView {
             .position(pred)
     }
 
-    /// find the next word boundary starting and including `from`
-    pub fn find_boundary(&self, buffer: &Buffer, from: usize) -> usize {
-        buffer
-            .contents
+    /// the bracket matching the bracket at `from`, or the first bracket after it on the same line
+    pub fn match_bracket(&self, buffer: &Buffer, from: usize) -> Option<usize> {
+        let contents = &buffer.contents;
+        let line_end = contents.line_to_char(contents.char_to_line(from) + 1);
+        let at = contents
             .chars_at(from)
-            .scan(None, |first, ch| {
-                let ty = ch.is_alphanumeric();
-                (*first.get_or_insert(ty) == ty).then_some(())
-            })
-            .skip(1)
-            .count()
+            .take(line_end - from)
+            .position(is_bracket)?;
+        matching_bracket(buffer, from + at)
     }
 
-    /// reverse find the next word boundary starting and including `from`
-    pub fn rfind_boundary(&self, buffer: &Buffer, from: usize) -> usize {
-        buffer
-            .contents
-            .chars_at(from + 1)
-            .reversed()
-            .scan(None, |first, ch| {
-                let ty = ch.is_alphanumeric();
-                (*first.get_or_insert(ty) == ty).then_some(())
-            })
-            .skip(1)
-            .count()
+    /// the start of the word after `from`, like vim `w`
+    ///
+    /// words are runs of letters, digits and `_`, or runs of other non-blank chars,
+    /// an empty line is a word too, but a line of only whitespace isn't
+    pub fn next_word_beg(&self, buffer: &Buffer, from: usize) -> usize {
+        let contents = &buffer.contents;
+        let len = contents.len_chars();
+        if from + 1 >= len {
+            return from;
+        }
+
+        let mut at = from;
+        let class = word_class(contents.char(at));
+        if class != WordClass::Blank {
+            while at < len && word_class(contents.char(at)) == class {
+                at += 1;
+            }
+        }
+
+        while at < len {
+            match contents.char(at) {
+                '\n' => {
+                    at += 1;
+                    if at < len && contents.char(at) == '\n' {
+                        return at;
+                    }
+                }
+                ch if ch.is_whitespace() => at += 1,
+                _ => return at,
+            }
+        }
+        len - 1
+    }
+
+    /// the end of the word after `from`, like vim `e`, empty lines are skipped
+    pub fn next_word_end(&self, buffer: &Buffer, from: usize) -> usize {
+        let contents = &buffer.contents;
+        let len = contents.len_chars();
+        if from + 1 >= len {
+            return from;
+        }
+
+        let mut at = from + 1;
+        while at < len && contents.char(at).is_whitespace() {
+            at += 1;
+        }
+        if at >= len {
+            return len - 1;
+        }
+
+        let class = word_class(contents.char(at));
+        while at + 1 < len && word_class(contents.char(at + 1)) == class {
+            at += 1;
+        }
+        at
+    }
+
+    /// the start of the word before `from`, like vim `b`, empty lines are words
+    pub fn prev_word_beg(&self, buffer: &Buffer, from: usize) -> usize {
+        let contents = &buffer.contents;
+        let mut at = from.min(contents.len_chars());
+        if at == 0 {
+            return 0;
+        }
+        at -= 1;
+
+        loop {
+            match contents.char(at) {
+                // the newline of an empty line
+                '\n' if at == 0 || contents.char(at - 1) == '\n' => return at,
+                ch if ch.is_whitespace() => {
+                    if at == 0 {
+                        return 0;
+                    }
+                    at -= 1;
+                }
+                _ => break,
+            }
+        }
+
+        let class = word_class(contents.char(at));
+        while at > 0 && word_class(contents.char(at - 1)) == class {
+            at -= 1;
+        }
+        at
     }
 
     pub fn jump_cursor(&mut self, buffer: &Buffer, delta_x: isize, delta_y: isize) {
@@ -293,7 +551,16 @@ impl BufferView {
         // figure out what X position the cursor is moved to
         let cursor_line = buffer.contents.char_to_line(self.cursor);
         let line_start = buffer.contents.line_to_char(cursor_line);
-        let cursor_x = self.cursor - line_start;
+        let tabstop = buffer.options.tabstop;
+        let cursor_x = columns_after(
+            buffer.contents.slice(line_start..self.cursor).chars(),
+            0,
+            tabstop,
+        );
+        // the cursor was moved some other way since the last vertical move
+        if self.cursor != self.cursor_x_unclamp_at {
+            self.cursor_x_unclamp = 0;
+        }
         self.cursor_x_unclamp = self.cursor_x_unclamp.max(cursor_x);
 
         let target_line = cursor_line
@@ -305,15 +572,68 @@ impl BufferView {
             .len_chars()
             .saturating_sub(1);
 
-        // place the cursor on the same X position or on the last char on the line
+        // place the cursor on the char covering the same column or on the last char on the line
         let target_line_start = buffer.contents.line_to_char(target_line);
-        self.cursor = target_line_start + target_line_len.min(self.cursor_x_unclamp);
+        let mut col = 0;
+        let mut offset = 0;
+        for ch in buffer
+            .contents
+            .line(target_line)
+            .chars()
+            .take(target_line_len)
+        {
+            col += char_width_at(ch, col, tabstop);
+            if col > self.cursor_x_unclamp {
+                break;
+            }
+            offset += 1;
+        }
+        self.cursor = target_line_start + offset;
+        // never land in the middle of a grapheme, like on a combining mark
+        self.cursor = buffer.grapheme_start(self.cursor);
+        self.cursor_x_unclamp_at = self.cursor;
+    }
+
+    /// move the cursor by `delta` grapheme clusters, a char with its combining marks is one
+    pub fn jump_graphemes(&mut self, buffer: &Buffer, delta: isize) {
+        for _ in 0..delta.unsigned_abs() {
+            let next = if delta < 0 {
+                buffer.prev_grapheme(self.cursor)
+            } else {
+                buffer.next_grapheme(self.cursor)
+            };
+            if next == self.cursor {
+                break;
+            }
+            self.cursor = next;
+        }
+        self.cursor_x_unclamp = 0;
     }
 
     pub fn jump_line_beg(&mut self, buffer: &Buffer) {
         self.cursor = buffer
             .contents
             .line_to_char(buffer.contents.char_to_line(self.cursor));
+        self.cursor_x_unclamp = 0;
+    }
+
+    /// move the cursor after the grapheme under it, where `a` types,
+    /// but never past the end of the line
+    pub fn jump_append(&mut self, buffer: &Buffer) {
+        let line = buffer.contents.char_to_line(self.cursor);
+        self.cursor = buffer
+            .next_grapheme(self.cursor)
+            .min(line_text_end(buffer, line));
+        self.cursor_x_unclamp = 0;
+    }
+
+    /// move the cursor after the last char of the line, where `A` types
+    ///
+    /// moving up or down from there goes to the end of those lines too
+    pub fn jump_line_append(&mut self, buffer: &Buffer) {
+        let line = buffer.contents.char_to_line(self.cursor);
+        self.cursor = line_text_end(buffer, line);
+        self.stick_to_line_end();
     }
 
     pub fn jump_line_end(&mut self, buffer: &Buffer) {
@@ -330,6 +650,13 @@ impl BufferView {
             .contents
             .len_chars()
             .min(buffer.contents.line_to_char(line_idx) + line.len_chars().saturating_sub(1));
+        self.stick_to_line_end();
+    }
+
+    /// the next vertical moves go to the line ends, like after `$`
+    fn stick_to_line_end(&mut self) {
+        self.cursor_x_unclamp = usize::MAX;
+        self.cursor_x_unclamp_at = self.cursor;
     }
 
     pub fn jump_beg(&mut self) {
@@ -346,10 +673,22 @@ impl BufferView {
 struct BufferWidget<'a> {
     buffer: &'a Buffer,
     line: usize,
+    /// the first visible display column
+    col: usize,
+    /// chars selected in visual mode
+    selection: Option<Range<usize>>,
+    /// the selection is whole lines, highlighted across the full width
+    linewise: bool,
+    /// the last search, every match is highlighted
+    search: &'a str,
+    /// the width long lines wrap at, `None` if they don't
+    wrap: Option<usize>,
 }
 
 impl Widget for BufferWidget<'_> {
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let theme = theme::current();
+        let tabstop = self.buffer.options.tabstop;
         // buf.content.fill(
         //     ratatui::buffer::Cell::new(" ")
         //         .set_fg(Color::Reset)
@@ -364,16 +703,80 @@ impl Widget for BufferWidget<'_> {
 
         // let last_byte = len - 1;
 
-        'lines: for y in 0..area.height as usize {
-            let Ok(start_byte) = self.buffer.contents.try_line_to_byte(self.line + y) else {
+        // the highlight query runs once over the visible lines
+        let visible_bytes = self
+            .buffer
+            .contents
+            .line_to_byte(self.line.min(self.buffer.contents.len_lines()))
+            ..self.buffer.contents.line_to_byte(
+                (self.line + area.height as usize).min(self.buffer.contents.len_lines()),
+            );
+        let highlights = self.buffer.syntax.as_ref().and_then(|syntax| {
+            let query = syntax.highlights?;
+            Some(query_highlights(
+                &theme,
+                syntax,
+                query,
+                self.buffer,
+                visible_bytes,
+            ))
+        });
+
+        // the row the next line starts on, wrapped lines take more than one
+        let mut next_y = 0;
+        // the first row of each drawn line, for the indent guides
+        let mut line_rows = Vec::new();
+        'lines: for line_idx in self.line.. {
+            let mut y = next_y;
+            if y >= area.height as usize {
+                break;
+            }
+            let Ok(start_byte) = self.buffer.contents.try_line_to_byte(line_idx) else {
                 break;
             };
-            let Some(line) = self.buffer.contents.get_line(self.line + y) else {
+            let Some(line) = self.buffer.contents.get_line(line_idx) else {
                 break;
             };
+            line_rows.push((line_idx, y));
+            let starts = match self.wrap {
+                Some(width) => wrap_line(line, width, tabstop),
+                None => vec![0],
+            };
+            let mut next_start = 1;
+            next_y += starts.len();
+
+            let mut char_idx = self.buffer.contents.line_to_char(line_idx);
+            let line_start = char_idx;
+
+            // selected lines are highlighted past their end too
+            if self.linewise
+                && self
+                    .selection
+                    .as_ref()
+                    .is_some_and(|selection| selection.contains(&line_start))
+            {
+                let rows = area.y + y as u16..(area.y as usize + next_y) as u16;
+                for y in rows.start..rows.end.min(area.bottom()).min(buf.area.bottom()) {
+                    for x in area.left()..area.right().min(buf.area.right()) {
+                        buf[(x, y)].set_bg(theme.cursor_line);
+                    }
+                }
+            }
+            let matches: Vec<Range<usize>> = search::matches_in_line(line, self.search)
+                .into_iter()
+                .map(|m| line_start + m.start..line_start + m.end)
+                .collect();
             if line.len_bytes() == 0 {
                 continue;
             }
+            // only the few highlights on this line are checked for each char
+            let line_bytes = start_byte..start_byte + line.len_bytes();
+            let line_highlights: Option<Vec<&Highlight>> = highlights.as_ref().map(|highlights| {
+                highlights
+                    .iter()
+                    .filter(|h| h.range.start < line_bytes.end && h.range.end > line_bytes.start)
+                    .collect()
+            });
             // let end_byte = line.len_bytes() - 1 + start_byte;
 
             // let mut line_grapheme_cursor = GraphemeCursor::new(0, line.len_bytes(), true);
@@ -383,136 +786,634 @@ impl Widget for BufferWidget<'_> {
             let Some((chunks, mut chunk_byte_idx, _, _)) = line.get_chunks_at_byte(0) else {
                 break;
             };
+            // the display column, control chars take 2 columns
+            let mut col: usize = 0;
 
             for chunk in chunks {
                 // chunk_char_idx += chunk.chars().count();
                 // line_grapheme_cursor.next_boundary(chunk, chunk_start)
 
                 for (byte_offs, ch) in chunk.char_indices() {
+                    let at = char_idx;
+                    char_idx += 1;
+
+                    // continue on the next row, after the wrap marker
+                    if starts.get(next_start) == Some(&(at - line_start)) {
+                        next_start += 1;
+                        y += 1;
+                        col = WRAP_INDENT;
+                        if y >= area.height as usize
+                            || area.y as usize + y >= buf.area.height as usize
+                        {
+                            break 'lines;
+                        }
+                        buf[(area.x, area.y + y as u16)]
+                            .set_char('↪')
+                            .set_style(Style::new().fg(theme.inactive));
+                    }
+
                     if ch == '\n' || ch == '\r' {
                         continue;
                     }
 
-                    if area.x as usize + byte_offs + chunk_byte_idx >= buf.area.width as usize {
+                    let width = char_width_at(ch, col, tabstop);
+                    let x = col;
+                    col += width;
+                    // scrolled out on the left, a partly hidden wide char is left out too
+                    if x < self.col {
+                        continue;
+                    }
+                    let x = x - self.col;
+                    if x + width > area.width as usize
+                        || area.x as usize + x + width > buf.area.width as usize
+                    {
+                        // the line goes on past the right edge
+                        let last = area.right().min(buf.area.right()).saturating_sub(1);
+                        let row = area.y + y as u16;
+                        if last >= area.x && row < buf.area.bottom() {
+                            buf[(last, row)]
+                                .set_char('→')
+                                .set_style(Style::new().fg(theme.inactive));
+                        }
                         continue 'lines;
                     }
                     if area.y as usize + y >= buf.area.height as usize {
                         break 'lines;
                     }
 
-                    let fg: Color = self
-                        .buffer
-                        .syntax
+                    let byte = start_byte + byte_offs + chunk_byte_idx;
+                    let style = if let Some(line_highlights) = &line_highlights {
+                        // the innermost capture wins, then the earliest pattern in the query
+                        line_highlights
+                            .iter()
+                            .filter(|h| h.range.contains(&byte))
+                            .max_by_key(|h| {
+                                (h.range.start, Reverse(h.range.end), Reverse(h.pattern))
+                            })
+                            .map(|h| h.style)
+                    } else {
+                        self.buffer
+                            .syntax
+                            .as_ref()
+                            .and_then(|syntax| {
+                                syntax
+                                    .tree
+                                    .root_node()
+                                    .descendant_for_byte_range(byte, byte)
+                            })
+                            .and_then(|node| syntax_style(&theme, node))
+                    };
+                    // the background is set last, so the selection and the cursor
+                    // line only replace the background and keep the modifiers
+                    let style = Style::new().fg(theme.text).patch(style.unwrap_or_default());
+
+                    let selected = self
+                        .selection
                         .as_ref()
-                        .map(|syntax| syntax.tree.root_node())
-                        .and_then(|root_node| {
-                            root_node.descendant_for_byte_range(
-                                start_byte + byte_offs + chunk_byte_idx,
-                                start_byte + byte_offs + chunk_byte_idx,
-                            )
-                        })
-                        .map_or(Color::Reset, |node| {
-                            // node.descendant_for_byte_range(start, end)
-
-                            Color::Indexed((node.kind_id() & 255) as u8)
-                        });
-
-                    buf[(
-                        area.x + byte_offs as u16 + chunk_byte_idx as u16,
-                        area.y + y as u16,
-                    )]
-                        .set_char(ch)
-                        .set_fg(fg)
-                        .set_bg(theme::BACKGROUND);
+                        .is_some_and(|selection| selection.contains(&at));
+                    let matched = matches.iter().any(|m| m.contains(&at));
+
+                    let bg = if selected {
+                        theme.cursor_line
+                    } else if matched {
+                        theme.search_match
+                    } else {
+                        theme.background
+                    };
+
+                    let (x, y) = (area.x + x as u16, area.y + y as u16);
+                    match visible(ch) {
+                        // combining marks and other zero width chars join the previous cell
+                        Visible::Char(ch) if width == 0 => {
+                            if x > area.x {
+                                let cell = &mut buf[(x - 1, y)];
+                                let symbol = format!("{}{ch}", cell.symbol());
+                                cell.set_symbol(&symbol);
+                            }
+                        }
+                        // tabs are drawn as spaces up to the next tab stop
+                        Visible::Char('\t') => {
+                            for x in x..x + width as u16 {
+                                buf[(x, y)].set_char(' ').set_style(style).set_bg(bg);
+                            }
+                        }
+                        Visible::Char(ch) => {
+                            buf[(x, y)].set_char(ch).set_style(style).set_bg(bg);
+                            // the terminal draws wide chars over the next cell too
+                            for x in x + 1..x + width as u16 {
+                                buf[(x, y)].reset();
+                                buf[(x, y)].set_bg(bg);
+                            }
+                        }
+                        Visible::Caret(ch) => {
+                            let style = Style::new().fg(theme.inactive);
+                            buf[(x, y)].set_char('^').set_style(style).set_bg(bg);
+                            buf[(x + 1, y)].set_char(ch).set_style(style).set_bg(bg);
+                        }
+                        Visible::Replacement => {
+                            let style = Style::new().fg(theme.inactive);
+                            buf[(x, y)].set_char('\u{FFFD}').set_style(style).set_bg(bg);
+                        }
+                    }
                 }
 
                 chunk_byte_idx += chunk.len();
             }
         }
+
+        if self.buffer.options.indentguides {
+            self.render_indent_guides(&line_rows, &theme, area, buf);
+        }
+    }
+}
+
+impl BufferWidget<'_> {
+    /// draw a `│` at each indent level in the leading whitespace,
+    /// blank lines continue the guides of the lines around them
+    fn render_indent_guides(
+        &self,
+        line_rows: &[(usize, usize)],
+        theme: &Theme,
+        area: Rect,
+        buf: &mut ratatui::prelude::Buffer,
+    ) {
+        let contents = &self.buffer.contents;
+        let tabstop = self.buffer.options.tabstop;
+        let step = match self.buffer.options.shiftwidth {
+            0 => tabstop,
+            shiftwidth => shiftwidth,
+        };
+        let indent_of = |line: usize| indent_width(contents.line(line), tabstop);
+
+        for &(line, y) in line_rows {
+            let row = area.y + y as u16;
+            if row >= buf.area.bottom() {
+                break;
+            }
+
+            let indent = indent_of(line).unwrap_or_else(|| {
+                let above = (0..line).rev().take(GUIDE_SEARCH).find_map(indent_of);
+                let below = (line + 1..contents.len_lines())
+                    .take(GUIDE_SEARCH)
+                    .find_map(indent_of);
+                above.unwrap_or(0).min(below.unwrap_or(0))
+            });
+
+            for col in (0..indent).step_by(step).skip_while(|&col| col < self.col) {
+                let col = col - self.col;
+                let x = area.x as usize + col;
+                if col >= area.width as usize || x >= buf.area.right() as usize {
+                    break;
+                }
+                // only over whitespace, the selection and cursor line keep their background
+                let cell = &mut buf[(x as u16, row)];
+                if cell.symbol() == " " {
+                    cell.set_char('│').set_fg(theme.inactive);
+                }
+            }
+        }
+    }
+}
+
+/// how a char is drawn, control chars would corrupt the terminal otherwise
+pub enum Visible {
+    Char(char),
+    /// `^A` for 0x01, `^?` for DEL
+    Caret(char),
+    /// other control chars, drawn as `�`
+    Replacement,
+}
+
+pub fn visible(ch: char) -> Visible {
+    match ch {
+        '\t' => Visible::Char(ch),
+        '\0'..='\x1F' => Visible::Caret((ch as u8 + b'@') as char),
+        '\x7F' => Visible::Caret('?'),
+        _ if ch.is_control() => Visible::Replacement,
+        _ => Visible::Char(ch),
+    }
+}
+
+/// the chars selected in visual mode, both ends included,
+/// and with `linewise` the whole lines with their newlines
+pub fn visual_range(buffer: &Buffer, anchor: usize, cursor: usize, linewise: bool) -> Range<usize> {
+    let contents = &buffer.contents;
+    let len = contents.len_chars();
+    let start = anchor.min(cursor).min(len);
+    let end = (anchor.max(cursor) + 1).min(len);
+    if !linewise {
+        return start..end;
+    }
+
+    let first = contents.char_to_line(start);
+    let last = contents.char_to_line(end.saturating_sub(1).max(start));
+    contents.line_to_char(first)..contents.line_to_char(last + 1)
+}
+
+/// the display columns a char takes in the buffer, 0 for combining marks and 2 for wide chars
+///
+/// tabs depend on their column, see [`char_width_at`]
+pub fn char_width(ch: char) -> usize {
+    match visible(ch) {
+        Visible::Caret(_) => 2,
+        Visible::Char('\t') | Visible::Replacement => 1,
+        Visible::Char(ch) => ch.width().unwrap_or(1),
+    }
+}
+
+/// the display columns a char at column `col` takes, a tab reaches the next multiple of `tabstop`
+pub fn char_width_at(ch: char, col: usize, tabstop: usize) -> usize {
+    match ch {
+        '\t' => tabstop - col % tabstop,
+        _ => char_width(ch),
+    }
+}
+
+/// the column after drawing `chars` starting at column `col`
+pub fn columns_after(chars: impl Iterator<Item = char>, col: usize, tabstop: usize) -> usize {
+    chars.fold(col, |col, ch| col + char_width_at(ch, col, tabstop))
+}
+
+#[derive(PartialEq)]
+enum WordClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn word_class(ch: char) -> WordClass {
+    if ch.is_whitespace() {
+        WordClass::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        WordClass::Word
+    } else {
+        WordClass::Punctuation
+    }
+}
+
+/// the char offsets in `line` where each of its rows starts, when wrapped at `width` columns
+///
+/// the rows after the first are indented by [`WRAP_INDENT`],
+/// a char too wide for an empty row still goes on it
+fn wrap_line(line: RopeSlice, width: usize, tabstop: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut col = 0;
+    for (i, ch) in line.chars().enumerate() {
+        let ch_width = match ch {
+            '\n' | '\r' => 0,
+            _ => char_width_at(ch, col, tabstop),
+        };
+        let indent = if starts.len() > 1 { WRAP_INDENT } else { 0 };
+        // zero width chars stay with the char before them
+        if ch_width > 0 && col + ch_width > width && col > indent {
+            starts.push(i);
+            col = WRAP_INDENT;
+        }
+        // a tab at the start of the new row reaches a different tab stop
+        col += match ch {
+            '\n' | '\r' => 0,
+            _ => char_width_at(ch, col, tabstop),
+        };
+    }
+    starts
+}
+
+/// the char index after the last char of `line`, before its line ending
+pub fn line_text_end(buffer: &Buffer, line: usize) -> usize {
+    let text = buffer.contents.line(line);
+    let mut len = text.len_chars();
+    while len > 0 && matches!(text.char(len - 1), '\n' | '\r') {
+        len -= 1;
+    }
+    buffer.contents.line_to_char(line) + len
+}
+
+fn is_bracket(ch: char) -> bool {
+    BRACKETS
+        .iter()
+        .any(|&(open, close)| ch == open || ch == close)
+}
+
+/// the char index of the bracket matching the bracket at `at`, `None` if it isn't a bracket
+fn matching_bracket(buffer: &Buffer, at: usize) -> Option<usize> {
+    let contents = &buffer.contents;
+    let ch = contents.get_char(at)?;
+    let &(open, close) = BRACKETS
+        .iter()
+        .find(|&&(open, close)| ch == open || ch == close)?;
+
+    // the syntax tree knows which brackets are in strings and comments
+    let matched = buffer.syntax.as_ref().and_then(|syntax| {
+        let byte = contents.char_to_byte(at);
+        syntax_bracket_pair(syntax.tree.root_node(), byte)
+    });
+    if let Some(byte) = matched {
+        return Some(contents.byte_to_char(byte));
+    }
+
+    // count the nesting depth without a syntax tree
+    let mut depth = 0usize;
+    if ch == open {
+        for (i, c) in contents.chars_at(at).enumerate() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(at + i);
+                }
+            }
+        }
+    } else {
+        let mut chars = contents.chars_at(at + 1);
+        let mut i = at + 1;
+        while let Some(c) = chars.prev() {
+            i -= 1;
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// the start byte of the other bracket of the syntax node delimited by the bracket at `byte`
+fn syntax_bracket_pair(root: Node, byte: usize) -> Option<usize> {
+    let node = root
+        .descendant_for_byte_range(byte, byte + 1)
+        .filter(|node| node.start_byte() == byte)?;
+    let parent = node.parent()?;
+    let first = parent.child(0)?;
+    let last = parent.child(parent.child_count() - 1)?;
+
+    let is_pair = BRACKETS
+        .iter()
+        .any(|&(open, close)| first.kind().starts_with(open) && last.kind().starts_with(close));
+    if !is_pair || first == last {
+        return None;
+    }
+    if node == first {
+        Some(last.start_byte())
+    } else if node == last {
+        Some(first.start_byte())
+    } else {
+        None
     }
 }
 
+/// the wrapped row starts of a buffer line, just `[0]` without wrapping
+fn line_starts(buffer: &Buffer, line: usize, wrap: Option<usize>) -> Vec<usize> {
+    match wrap {
+        Some(width) => wrap_line(buffer.contents.line(line), width, buffer.options.tabstop),
+        None => vec![0],
+    }
+}
+
+/// the display columns of the leading whitespace, `None` for blank lines
+fn indent_width(line: RopeSlice, tabstop: usize) -> Option<usize> {
+    let mut col = 0;
+    for ch in line.chars() {
+        match ch {
+            ' ' | '\t' => col += char_width_at(ch, col, tabstop),
+            '\n' | '\r' => return None,
+            _ => return Some(col),
+        }
+    }
+    None
+}
+
+/// the columns line numbers up to `lines` take, at least 1
+pub fn number_width(lines: usize) -> u16 {
+    // an empty rope still has 1 line, but 0 must not panic either
+    lines.max(1).ilog10() as u16 + 1
+}
+
+/// a byte range styled by a highlight query capture
+struct Highlight {
+    range: Range<usize>,
+    /// earlier patterns in the query take priority
+    pattern: usize,
+    style: Style,
+}
+
+/// the captures of the highlight query in `bytes` the theme has a style for
+fn query_highlights(
+    theme: &Theme,
+    syntax: &Syntax,
+    query: &Query,
+    buffer: &Buffer,
+    bytes: Range<usize>,
+) -> Vec<Highlight> {
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(bytes);
+
+    // predicates like `#match?` read the node text from the rope
+    let text = |node: Node| {
+        buffer
+            .contents
+            .byte_slice(node.byte_range())
+            .chunks()
+            .map(str::as_bytes)
+    };
+    let mut captures = cursor.captures(query, syntax.tree.root_node(), text);
+
+    let mut highlights = Vec::new();
+    while let Some((query_match, index)) = captures.next() {
+        let capture = query_match.captures[*index];
+        let name = query.capture_names()[capture.index as usize];
+        let Some(style) = theme.capture(name) else {
+            continue;
+        };
+
+        highlights.push(Highlight {
+            range: capture.node.byte_range(),
+            pattern: query_match.pattern_index,
+            style,
+        });
+    }
+    highlights
+}
+
+/// the style of a syntax tree node, for languages without a highlight query
+///
+/// the capture is guessed from the node kind,
+/// the whole kind is looked up first and then each word in it, `line_comment` is a `comment`
+fn syntax_style(theme: &Theme, node: Node) -> Option<Style> {
+    if !node.is_named() {
+        if node
+            .kind()
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || ch == '_')
+        {
+            return theme.capture("keyword");
+        }
+        // punctuation takes the style of what it is a part of, like the quotes of a string
+        return node
+            .parent()
+            .and_then(|parent| kind_style(theme, parent.kind()))
+            .or_else(|| theme.capture("punctuation"));
+    }
+
+    kind_style(theme, node.kind())
+}
+
+fn kind_style(theme: &Theme, kind: &str) -> Option<Style> {
+    iter::once(kind)
+        .chain(kind.split('_'))
+        .find_map(|capture| theme.capture(capture))
+}
+
 pub struct Cursor {
-    line: usize,
-    row: usize,
-    col: usize,
+    /// the row in the view
+    y: usize,
+    /// the column in the view
+    x: usize,
+    /// display columns of the grapheme under the cursor
+    width: usize,
     is_insert_mode: bool,
+    /// highlight the cursor row
+    line: bool,
+    /// highlight the cursor column
+    column: bool,
+    /// the 1-based column of the line length marker, 0 for none
+    colorcolumn: usize,
+    /// the row and column of the bracket matching the one under the cursor
+    bracket: Option<(usize, usize)>,
 }
 
 impl Widget for Cursor {
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
-        if self.row - self.line > area.height as usize || self.col > area.width as usize {
+        let theme = theme::current();
+        if self.y >= area.height as usize || self.x >= area.width as usize {
             return;
         }
-        let row = area.top() + (self.row - self.line) as u16;
-        let col = area.left() + self.col as u16;
+        let row = area.top() + self.y as u16;
+        let col = area.left() + self.x as u16;
 
         // highlight the current row
-        for x in area.left()..area.right() {
-            buf[(x, row)].set_bg(theme::CURSOR_LINE);
+        if self.line {
+            for x in area.left()..area.right() {
+                buf[(x, row)].set_bg(theme.cursor_line);
+            }
         }
         // highlight the current column
-        for y in area.top()..area.bottom() {
-            buf[(col, y)].set_bg(theme::CURSOR_LINE);
+        if self.column {
+            for y in area.top()..area.bottom() {
+                buf[(col, y)].set_bg(theme.cursor_line);
+            }
         }
-        // highlight the 80th char column
-        if 80 <= area.right() {
+        // highlight the line length marker column
+        if self.colorcolumn != 0 && self.colorcolumn <= area.width as usize {
+            let marker = area.left() + self.colorcolumn as u16 - 1;
             for y in area.top()..area.bottom() {
-                buf[(80, y)].set_bg(theme::CURSOR_LINE);
+                buf[(marker, y)].set_bg(theme.cursor_line);
+            }
+        }
+        // highlight the matching bracket
+        if let Some((y, x)) = self.bracket {
+            if y < area.height as usize && x < area.width as usize {
+                buf[(area.left() + x as u16, area.top() + y as u16)].set_bg(theme.matching_bracket);
             }
         }
         // highlight the cursor itself
         if !self.is_insert_mode {
-            buf[(col, row)]
-                .set_bg(theme::CURSOR)
-                .set_fg(theme::BACKGROUND);
+            for x in col..(col + self.width as u16).min(area.right()) {
+                buf[(x, row)].set_bg(theme.cursor).set_fg(theme.background);
+            }
         }
     }
 }
 
-pub struct LineNumbers {
-    /// viewport first line
-    line: usize,
-    /// viewport line count
+pub struct LineNumbers<'a> {
+    /// the buffer line and its wrapped row on each row of the view
+    rows: &'a [(usize, usize)],
+    /// buffer line count
     lines: usize,
     /// cursor row
     row: usize,
+    /// marker for the rows past the end of the buffer
+    eob: char,
+    /// lines with a glyph in the first column
+    bookmarks: &'a BTreeSet<usize>,
 }
 
-impl Widget for LineNumbers {
+impl Widget for LineNumbers<'_> {
     fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let theme = theme::current();
         use std::fmt::Write;
 
         let mut text = String::with_capacity(area.width as usize * area.height as usize); // TODO: cache this memory
 
-        for y in 0..area.height {
-            match (y as usize + self.line + 1).cmp(&self.lines) {
-                Ordering::Equal => {
-                    _ = writeln!(&mut text, "{:>width$}", "~", width = area.width as usize);
-                }
-                Ordering::Less => {
-                    let num = self.line + y as usize;
-                    let num = if num == self.row {
-                        num + 1
-                    } else {
-                        // relative numbering
-                        num.abs_diff(self.row)
-                    };
-
-                    _ = writeln!(&mut text, "{:>width$}", num, width = area.width as usize);
-                }
-                _ => {}
+        for y in 0..area.height as usize {
+            let Some(&(num, sub_row)) = self.rows.get(y).filter(|(num, _)| *num < self.lines)
+            else {
+                // every row below the end of the buffer, the scroll keeps the cursor row
+                // visible so these only show when the buffer ends above the bottom
+                _ = writeln!(
+                    &mut text,
+                    "{:>width$}",
+                    self.eob,
+                    width = area.width as usize
+                );
+                continue;
+            };
+            // wrapped rows have no number of their own
+            if sub_row > 0 {
+                text.push('\n');
+                continue;
             }
+
+            let num = if num == self.row {
+                num + 1
+            } else {
+                // relative numbering
+                num.abs_diff(self.row)
+            };
+
+            _ = writeln!(&mut text, "{:>width$}", num, width = area.width as usize);
         }
 
         Paragraph::new(text).render(area, buf);
 
-        for y in area.top()..area.bottom() {
+        for (y, &(line, _)) in (area.top()..area.bottom()).zip(self.rows) {
             for x in area.left()..area.right() {
-                if y as usize + self.line != self.row {
-                    buf[(x, y)].set_fg(theme::INACTIVE);
+                if line != self.row {
+                    buf[(x, y)].set_fg(theme.inactive);
                 }
             }
         }
+
+        if area.width == 0 {
+            return;
+        }
+        for (y, &(line, sub_row)) in (area.top()..area.bottom()).zip(self.rows) {
+            if sub_row == 0 && self.bookmarks.contains(&line) {
+                buf[(area.left(), y)].set_char('●').set_fg(theme.accent);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_width_of_an_empty_buffer() {
+        assert_eq!(number_width(0), 1);
+        assert_eq!(number_width(1), 1);
+    }
+
+    #[test]
+    fn number_width_grows_with_each_digit() {
+        assert_eq!(number_width(9), 1);
+        assert_eq!(number_width(10), 2);
+        assert_eq!(number_width(999), 3);
+        assert_eq!(number_width(1000), 4);
     }
 }
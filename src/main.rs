@@ -1,12 +1,21 @@
 use std::{
     env,
     fs::{self, File, OpenOptions},
+    io,
     path::PathBuf,
     sync::OnceLock,
 };
 
 use self::{args::Args, buffer::Buffer, editor::Editor};
 use clap::Parser;
+use crossterm::{
+    event::{
+        DisableFocusChange, EnableFocusChange, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute,
+    terminal::supports_keyboard_enhancement,
+};
 use eyre::Result;
 use tree_sitter::LogType;
 
@@ -26,8 +35,11 @@ fn main() -> Result<()> {
     let args: Args = Args::parse();
 
     let (_guard, terminal) = AlternativeScreenGuard::enter();
+    // the terminal answers the background query only in raw mode
+    editor::theme::set(args.theme.detect().theme());
 
     let mut editor = Editor::new(Buffer::new_welcome());
+    editor.colors = args.color.detect();
     if let Some(path) = args.file {
         editor.open(path);
     }
@@ -43,6 +55,16 @@ fn ts_logger() -> Option<Box<dyn FnMut(LogType, &str)>> {
     }))
 }
 
+fn config_dir() -> PathBuf {
+    if let Some(xdg_config_home) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(xdg_config_home).join("text-editor")
+    } else if let Some(home) = env::var_os("HOME") {
+        PathBuf::from(home).join(".config/text-editor")
+    } else {
+        PathBuf::from(".config/text-editor")
+    }
+}
+
 fn tmpdir() -> PathBuf {
     if let Some(xdg_runtime_dir) = env::var_os("XDG_RUNTIME_DIR") {
         PathBuf::from(xdg_runtime_dir).join("text-editor")
@@ -135,16 +157,53 @@ fn logger_init() -> Result<()> {
 
 static LOG_FILE: OnceLock<File> = OnceLock::new();
 
-struct AlternativeScreenGuard;
+/// restores the terminal when dropped
+struct AlternativeScreenGuard {
+    /// the kitty keyboard protocol was enabled, and has to be disabled again
+    keyboard_enhancement: bool,
+}
 
 impl AlternativeScreenGuard {
     pub fn enter() -> (Self, ratatui::DefaultTerminal) {
-        (Self, ratatui::init())
+        let terminal = ratatui::init();
+
+        // the kitty keyboard protocol tells `C-i`, `C-m` and `C-[` apart from `tab`, `enter`
+        // and `esc`, other terminals keep sending the same bytes for both
+        let keyboard_enhancement = match supports_keyboard_enhancement() {
+            Ok(true) => execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )
+            .inspect_err(|err| tracing::warn!("failed to enable keyboard enhancement: {err}"))
+            .is_ok(),
+            Ok(false) => false,
+            Err(err) => {
+                tracing::warn!("failed to detect keyboard enhancement support: {err}");
+                false
+            }
+        };
+        tracing::debug!("keyboard enhancement: {keyboard_enhancement}");
+
+        // focus events tell when to check if another program changed the open file
+        if let Err(err) = execute!(io::stdout(), EnableFocusChange) {
+            tracing::warn!("failed to enable focus events: {err}");
+        }
+
+        (
+            Self {
+                keyboard_enhancement,
+            },
+            terminal,
+        )
     }
 }
 
 impl Drop for AlternativeScreenGuard {
     fn drop(&mut self) {
+        if self.keyboard_enhancement {
+            _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        _ = execute!(io::stdout(), DisableFocusChange);
         ratatui::restore();
     }
 }
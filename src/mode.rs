@@ -11,8 +11,17 @@ pub enum Mode {
     Normal,
     Insert {
         append: bool,
+        /// typed characters overwrite the ones under the cursor
+        replace: bool,
     },
     Command,
+    /// text between the anchor and the cursor is selected
+    Visual {
+        /// the char index where the selection started
+        anchor: usize,
+        /// whole lines are selected, from the anchor line to the cursor line
+        linewise: bool,
+    },
     Action {
         layer: Arc<dyn Layer>,
         prev: ModeSubset,
@@ -23,8 +32,11 @@ impl Mode {
     pub const fn as_str(&self) -> &'static str {
         match self {
             Mode::Normal => "NOR",
+            Mode::Insert { replace: true, .. } => "REP",
             Mode::Insert { .. } => "INS",
             Mode::Command => "CMD",
+            Mode::Visual { linewise: true, .. } => "LIN",
+            Mode::Visual { .. } => "VIS",
             Mode::Action { .. } => "ACT",
         }
     }
@@ -32,8 +44,15 @@ impl Mode {
     pub const fn prev(&self) -> ModeSubset {
         match self {
             Mode::Normal => ModeSubset::Normal,
-            Mode::Insert { append } => ModeSubset::Insert { append: *append },
+            Mode::Insert { append, replace } => ModeSubset::Insert {
+                append: *append,
+                replace: *replace,
+            },
             Mode::Command => ModeSubset::Command,
+            Mode::Visual { anchor, linewise } => ModeSubset::Visual {
+                anchor: *anchor,
+                linewise: *linewise,
+            },
             Mode::Action { prev, .. } => *prev,
         }
     }
@@ -41,8 +60,10 @@ impl Mode {
     pub const fn cursor_style(&self) -> SetCursorStyle {
         match self {
             Mode::Normal => SetCursorStyle::SteadyBlock,
+            Mode::Insert { replace: true, .. } => SetCursorStyle::SteadyUnderScore,
             Mode::Insert { .. } => SetCursorStyle::SteadyBar,
             Mode::Command => SetCursorStyle::SteadyBar,
+            Mode::Visual { .. } => SetCursorStyle::SteadyBlock,
             Mode::Action { .. } => SetCursorStyle::SteadyBlock,
         }
     }
@@ -71,6 +92,14 @@ impl Mode {
         matches!(self, Self::Command)
     }
 
+    /// Returns `true` if the mode is [`Visual`].
+    ///
+    /// [`Visual`]: Mode::Visual
+    #[must_use]
+    pub fn is_visual(&self) -> bool {
+        matches!(self, Self::Visual { .. })
+    }
+
     /// Returns `true` if the mode is [`Action`].
     ///
     /// [`Action`]: Mode::Action
@@ -82,19 +111,32 @@ impl Mode {
 
 //
 
+/// the last visual selection, selected again with `gv`
+#[derive(Clone, Copy)]
+pub struct LastVisual {
+    pub buffer_index: usize,
+    pub anchor: usize,
+    pub cursor: usize,
+    pub linewise: bool,
+}
+
+//
+
 #[derive(Clone, Copy)]
 pub enum ModeSubset {
     Normal,
-    Insert { append: bool },
+    Insert { append: bool, replace: bool },
     Command,
+    Visual { anchor: usize, linewise: bool },
 }
 
 impl ModeSubset {
     pub const fn mode(self) -> Mode {
         match self {
             ModeSubset::Normal => Mode::Normal,
-            ModeSubset::Insert { append } => Mode::Insert { append },
+            ModeSubset::Insert { append, replace } => Mode::Insert { append, replace },
             ModeSubset::Command => Mode::Command,
+            ModeSubset::Visual { anchor, linewise } => Mode::Visual { anchor, linewise },
         }
     }
 }
@@ -8,11 +8,13 @@ use std::{
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{channel, Sender},
-        Arc, Mutex, RwLock,
+        Arc, Mutex, MutexGuard, RwLock,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
+use zeroize::Zeroizing;
 
 //
 
@@ -25,27 +27,98 @@ pub enum Part {
 }
 
 impl Part {
+    /// protocol names understood by [`Part::parse`]
+    pub const PROTOCOLS: &[&str] = &["ssh", "docker", "sudo", "bash", "sh"];
+
+    /// returns true if `path` starts with a known `proto:` prefix
+    pub fn is_remote(path: &str) -> bool {
+        Self::split_path(path).is_some()
+    }
+
+    /// split `proto:args|proto:args:path` into the hop chain and the file path
+    ///
+    /// only the arguments each protocol actually takes are consumed,
+    /// so the file path itself is allowed to contain `:` and `|`
+    pub fn split_path(s: &str) -> Option<(&str, &str)> {
+        // the end of an argument or a protocol name
+        let arg_end = |from: usize| from + s[from..].find([':', '|']).unwrap_or(s.len() - from);
+
+        let mut hop = 0;
+        loop {
+            let mut end = arg_end(hop);
+            let proto = &s[hop..end];
+            if !Self::PROTOCOLS.contains(&proto) {
+                return None;
+            }
+
+            let (required, optional) = match proto {
+                // destination and port
+                "ssh" => (1, 1),
+                // container
+                "docker" => (1, 0),
+                _ => (0, 0),
+            };
+
+            for n in 0..required + optional {
+                if s.as_bytes().get(end) != Some(&b':') {
+                    break;
+                }
+
+                let next_end = arg_end(end + 1);
+                let followed_by_more = next_end < s.len();
+                let is_optional = n >= required;
+
+                if is_optional
+                    && (!followed_by_more || s[end + 1..next_end].parse::<u16>().is_err())
+                {
+                    // the optional ssh port was left out, this is already the path
+                    break;
+                }
+                if !followed_by_more {
+                    // a required argument without a path after it
+                    return None;
+                }
+
+                end = next_end;
+            }
+
+            match s.as_bytes().get(end) {
+                Some(b'|') => hop = end + 1,
+                Some(b':') => return Some((&s[..end], &s[end + 1..])),
+                _ => return None,
+            }
+        }
+    }
+
+    /// the inverse of [`Part::split_path`] and [`Part::parse`]
     pub fn print(pool: &str, parts: &[Self], path: &str) -> String {
         let mut buf = String::new();
         use std::fmt::Write;
 
-        for part in parts {
+        for (i, part) in parts.iter().enumerate() {
+            if i != 0 {
+                buf.push('|');
+            }
+
             match part {
                 Part::Ssh { destination, port } => {
-                    _ = write!(&mut buf, "ssh:{}:{port}:", destination.as_str(pool),);
+                    _ = write!(&mut buf, "ssh:{}:{port}", destination.as_str(pool),);
                 }
                 Part::Docker { container } => {
-                    _ = write!(&mut buf, "ssh:{}:", container.as_str(pool),);
+                    _ = write!(&mut buf, "docker:{}", container.as_str(pool),);
                 }
                 Part::Sudo {} => {
-                    _ = write!(&mut buf, "sudo:");
+                    _ = write!(&mut buf, "sudo");
                 }
                 Part::Bash {} => {
-                    _ = write!(&mut buf, "bash:");
+                    _ = write!(&mut buf, "bash");
                 }
             }
         }
 
+        if !parts.is_empty() {
+            buf.push(':');
+        }
         buf.push_str(path);
 
         buf
@@ -81,12 +154,32 @@ impl Part {
 
                 Ok(Self::Docker { container })
             }
-            "bash" => Ok(Self::Bash {}),
+            // a plain local shell without any hops,
+            // mostly useful for testing the remote pipeline without a network
+            "bash" | "sh" => Ok(Self::Bash {}),
             _ => bail!("unknown protocol"),
         }
     }
 }
 
+/// POSIX shell single quoting, so that arguments can't break out of the command
+#[derive(Debug, Clone, Copy)]
+pub struct Quoted<'a>(pub &'a str);
+
+impl fmt::Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("'")?;
+        for (i, part) in self.0.split('\'').enumerate() {
+            if i != 0 {
+                // close the quotes, add an escaped quote and reopen the quotes
+                f.write_str("'\\''")?;
+            }
+            f.write_str(part)?;
+        }
+        f.write_str("'")
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Str {
     start: u32,
@@ -141,11 +234,71 @@ impl Drop for ConnectionHandle<'_> {
     }
 }
 
+/// shared state that lets the UI thread observe and abort slow remote commands
+///
+/// the number of running commands and the cancel flag change together in one atomic,
+/// so a cancel can't land after the commands it was for are done
+#[derive(Debug, Default)]
+pub struct Interrupt {
+    /// the running commands times 2, and the lowest bit is set if they are cancelled
+    state: AtomicUsize,
+}
+
+impl Interrupt {
+    const CANCELLED: usize = 1;
+    const ONE_RUNNING: usize = 2;
+
+    /// returns true if some connection is currently waiting for a remote command
+    pub fn is_busy(&self) -> bool {
+        self.state.load(Ordering::Acquire) >= Self::ONE_RUNNING
+    }
+
+    /// abort the currently running remote command(s), if any
+    pub fn cancel(&self) {
+        _ = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state >= Self::ONE_RUNNING).then_some(state | Self::CANCELLED)
+            });
+    }
+
+    fn enter(&self) -> RunningGuard<'_> {
+        self.state.fetch_add(Self::ONE_RUNNING, Ordering::AcqRel);
+        RunningGuard(self)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::Acquire) & Self::CANCELLED != 0
+    }
+}
+
+struct RunningGuard<'a>(&'a Interrupt);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        _ = self
+            .0
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                let state = state - Interrupt::ONE_RUNNING;
+                // nothing is running anymore, so the cancel has nothing left to abort
+                Some(if state < Interrupt::ONE_RUNNING {
+                    0
+                } else {
+                    state
+                })
+            });
+    }
+}
+
 /// a single threaded connection instance
 #[must_use]
 pub struct Connection {
     remote: Arc<[Part]>,
     shell: PtySession,
+    interrupt: Arc<Interrupt>,
+    /// the shell is in an unknown state and the connection should not be reused
+    poisoned: bool,
 }
 
 impl Connection {
@@ -155,16 +308,18 @@ impl Connection {
         pool: &str,
         nth_hop: usize,
         askpw_tx: Sender<(String, Sender<String>)>,
+        passwords: &Passwords,
         destination: &str,
         port: u16,
     ) -> Result<()> {
-        // FIXME: sanitation
         self.run_cmd_askpw_checked(Regex::new("[a-z][-a-z0-9_]*\\$?@(([a-zA-Z]|[a-zA-Z][a-zA-Z0-9\\-]*[a-zA-Z0-9])\\.)*([A-Za-z]|[A-Za-z][A-Za-z0-9\\-]*[A-Za-z0-9])'s password:").unwrap(),
             pool,
             nth_hop,
             askpw_tx,
+            passwords,
             format_args!(
-            "ssh -p {port} -t -t '{destination}' env PS1=__sh_prompt TERM=dumb sh"
+            "ssh -p {port} -t -t {} env PS1=__sh_prompt TERM=dumb sh",
+            Quoted(destination),
         ),)?;
         Ok(())
     }
@@ -175,22 +330,23 @@ impl Connection {
         pool: &str,
         nth_hop: usize,
         askpw_tx: Sender<(String, Sender<String>)>,
+        passwords: &Passwords,
     ) -> Result<()> {
-        // FIXME: sanitation
         self.run_cmd_askpw_checked(
             Regex::new("__sh_pw_prompt").unwrap(),
             pool,
             nth_hop,
             askpw_tx,
+            passwords,
             format_args!("sudo -S -p '__sh_pw_prompt' env PS1=__sh_prompt TERM=dumb sh"),
         )?;
         Ok(())
     }
 
     pub fn hop_docker(&mut self, container: &str) -> Result<()> {
-        // FIXME: sanitation
         self.run_cmd_checked(format_args!(
-            "docker exec -it '{container}' env PS1=__sh_prompt TERM=dumb sh"
+            "docker exec -it {} env PS1=__sh_prompt TERM=dumb sh",
+            Quoted(container),
         ))?;
         self.run_cmd(format_args!("stty -echoctl"))?;
         self.run_cmd(format_args!("stty -echo"))?;
@@ -220,34 +376,57 @@ impl Connection {
         Ok(result)
     }
 
-    /// run a command and test the exit code
+    /// run a command and test the exit code,
+    /// a password that worked is cached for the hops up to `nth_hop`
     pub fn run_cmd_askpw_checked(
         &mut self,
         askpw_needle: Regex,
         pool: &str,
         nth_hop: usize,
         askpw_tx: Sender<(String, Sender<String>)>,
+        passwords: &Passwords,
         cmd: fmt::Arguments,
     ) -> Result<String> {
         let now = Instant::now();
         self.run_cmd(cmd)?;
 
         let mut result = None;
+        let hops: Arc<[Part]> = self.remote[..=nth_hop].into();
+        let mut used = None;
 
-        for _ in 0..3 {
+        for attempt in 0..3 {
             let (_result, askpw) = self.wait(Some(askpw_needle.clone()))?;
             result.get_or_insert(_result);
             if let Some(askpw) = askpw {
-                let parts = &self.remote[..=nth_hop];
-                let dst = Part::print(pool, parts, " ");
+                // asked again, so the cached password didn't work
+                if attempt != 0 {
+                    passwords.remove(&hops);
+                } else if let Some(pw) = passwords.get(&hops) {
+                    tracing::debug!("using a cached password for askpw '{askpw}'");
+                    self.shell.writer.write_fmt(format_args!("{}\n", *pw))?;
+                    self.shell.writer.flush()?;
+                    used = Some(pw);
+                    continue;
+                }
+
+                let dst = Part::print(pool, &hops, " ");
                 tracing::debug!("got askpw '{askpw}' for '{dst}'");
 
                 let (tx, rx) = channel();
                 askpw_tx.send((dst, tx))?;
-                let pw = rx.recv()?;
-
-                self.shell.writer.write_fmt(format_args!("{pw}\n"))?;
+                // the popup drops the sender if it is closed with Esc,
+                // the half connected shell is killed when this connection is dropped
+                let pw = match rx.recv() {
+                    Ok(pw) if !pw.is_empty() => Zeroizing::new(pw),
+                    _ => {
+                        self.poisoned = true;
+                        bail!("password prompt cancelled");
+                    }
+                };
+
+                self.shell.writer.write_fmt(format_args!("{}\n", *pw))?;
                 self.shell.writer.flush()?;
+                used = Some(pw);
             } else {
                 break;
             }
@@ -266,6 +445,10 @@ impl Connection {
             );
         }
 
+        if let Some(pw) = used {
+            passwords.insert(hops, pw);
+        }
+
         tracing::debug!("cmd took {:?}", now.elapsed());
         Ok(result)
     }
@@ -280,9 +463,22 @@ impl Connection {
     }
 
     /// wait for the prompt indicator
+    ///
+    /// the wait can be aborted with [`Interrupt::cancel`], which sends
+    /// Ctrl-C to the remote and poisons this connection
     pub fn wait(&mut self, incorrect: Option<Regex>) -> Result<(String, Option<String>)> {
         tracing::trace!("waiting for __sh_prompt or __sh_pw_prompt");
+        let interrupt = self.interrupt.clone();
+        let _running = interrupt.enter();
+
         for _ in 0..30_000 {
+            if interrupt.is_cancelled() {
+                tracing::debug!("remote command cancelled");
+                self.poisoned = true;
+                _ = self.shell.send_control('c');
+                bail!("cancelled");
+            }
+
             // FIXME: fork rexpect and fix this Vec of Strings by value madness
 
             let res = if let Some(incorrect) = incorrect.clone() {
@@ -303,17 +499,13 @@ impl Connection {
             std::thread::sleep(std::time::Duration::from_millis(1));
         }
 
+        self.poisoned = true;
         bail!("Expected \"__sh_prompt\" but got \"\" (after waiting for 30000 ms)");
     }
 
     pub fn canonicalize(&mut self, path: &Path) -> Result<PathBuf> {
-        // FIXME: sanitation
-        tracing::trace!("running 'realpath {path:?}'");
-        self.shell.writer.write_all(b"realpath ")?;
-        self.shell
-            .writer
-            .write_all(path.as_os_str().as_encoded_bytes())?;
-        let mut result = self.run_cmd_checked(format_args!(""))?;
+        let path = path.to_string_lossy();
+        let mut result = self.run_cmd_checked(format_args!("realpath -- {}", Quoted(&path)))?;
         if result.ends_with('\n') {
             result.pop();
         }
@@ -324,16 +516,12 @@ impl Connection {
     }
 
     pub fn list_files(&mut self, path: &Path) -> Result<String> {
-        tracing::trace!("running 'ls -al {path:?}'");
-        self.shell.writer.write_all(b"ls -al ")?;
-        self.shell
-            .writer
-            .write_all(path.as_os_str().as_encoded_bytes())?;
-        self.run_cmd_checked(format_args!(""))
+        let path = path.to_string_lossy();
+        self.run_cmd_checked(format_args!("ls -al -- {}", Quoted(&path)))
     }
 
     pub fn read_file(&mut self, filename: &str) -> Result<impl io::Read> {
-        let read = self.run_cmd_checked(format_args!("base64 -w 0 {filename}"))?;
+        let read = self.run_cmd_checked(format_args!("base64 -w 0 < {}", Quoted(filename)))?;
 
         Ok(base64::read::DecoderReader::new(
             Cursor::new(read.into_bytes()),
@@ -348,7 +536,7 @@ impl Connection {
         // starts reading the base64 data from stdin
         if false {
             self.run_cmd(format_args!("stty -echoctl"))?;
-            self.run_cmd(format_args!("base64 -d - > {filename}"))?;
+            self.run_cmd(format_args!("base64 -d - > {}", Quoted(filename)))?;
         } else {
             self.shell.writer.write_all(b"echo '")?;
         }
@@ -369,7 +557,7 @@ impl Connection {
             self.shell.send_control('d')?;
         } else {
             tracing::trace!("running 'echo '...");
-            self.run_cmd_checked(format_args!("' | base64 -d - > {filename}"))?;
+            self.run_cmd_checked(format_args!("' | base64 -d - > {}", Quoted(filename)))?;
         }
         Ok(())
     }
@@ -396,10 +584,48 @@ impl Drop for Connection {
 //     file_cache: HashMap<Box<str>, File>,
 // }
 
+/// how long a remote directory listing is reused before running `ls` again
+const LISTING_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct Listing {
+    at: Instant,
+    cwd: PathBuf,
+    files: Arc<str>,
+}
+
+/// passwords that worked, by the hops up to the one that asked for it,
+/// only kept in memory for the session and zeroed when dropped
+#[derive(Default)]
+pub struct Passwords {
+    inner: Mutex<HashMap<Arc<[Part]>, Zeroizing<String>>>,
+}
+
+impl Passwords {
+    fn get(&self, hops: &[Part]) -> Option<Zeroizing<String>> {
+        self.lock().get(hops).cloned()
+    }
+
+    fn insert(&self, hops: Arc<[Part]>, password: Zeroizing<String>) {
+        self.lock().insert(hops, password);
+    }
+
+    fn remove(&self, hops: &[Part]) {
+        self.lock().remove(hops);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Arc<[Part]>, Zeroizing<String>>> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
 /// a cache for connections
 pub struct ConnectionPool {
     string_pool: RwLock<String>,
     connections: Mutex<HashMap<Arc<[Part]>, Vec<Connection>>>,
+    listings: Mutex<HashMap<(Arc<[Part]>, PathBuf), Listing>>,
+    passwords: Passwords,
+    interrupt: Arc<Interrupt>,
 }
 
 impl ConnectionPool {
@@ -407,9 +633,68 @@ impl ConnectionPool {
         Self {
             string_pool: RwLock::new(String::new()),
             connections: Mutex::new(HashMap::new()),
+            listings: Mutex::new(HashMap::new()),
+            passwords: Passwords::default(),
+            interrupt: Arc::new(Interrupt::default()),
         }
     }
 
+    /// canonicalize `path` and list its files with `ls -al`,
+    /// recently listed directories are served from a cache
+    pub fn list_dir(
+        &self,
+        remote: Arc<[Part]>,
+        path: &Path,
+        askpw_tx: Sender<(String, Sender<String>)>,
+    ) -> Result<(PathBuf, Arc<str>)> {
+        let key = (remote, path.to_path_buf());
+
+        let listings = self.listings.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(listing) = listings.get(&key) {
+            if listing.at.elapsed() < LISTING_TTL {
+                return Ok((listing.cwd.clone(), listing.files.clone()));
+            }
+        }
+        drop(listings);
+
+        let mut conn = self.connect_to(key.0.clone(), askpw_tx)?;
+        let cwd = conn.canonicalize(path)?;
+        let files: Arc<str> = conn.list_files(&cwd)?.into();
+        drop(conn);
+
+        let listing = Listing {
+            at: Instant::now(),
+            cwd: cwd.clone(),
+            files: files.clone(),
+        };
+
+        let mut listings = self.listings.lock().unwrap_or_else(|err| err.into_inner());
+        listings.retain(|_, listing| listing.at.elapsed() < LISTING_TTL);
+        listings.insert((key.0.clone(), cwd.clone()), listing.clone());
+        listings.insert(key, listing);
+
+        Ok((cwd, files))
+    }
+
+    /// forget the cached listings of `dir`,
+    /// after a file in it was created, deleted or renamed
+    pub fn invalidate(&self, remote: &[Part], dir: &Path) {
+        let mut listings = self.listings.lock().unwrap_or_else(|err| err.into_inner());
+        listings.retain(|(listing_remote, path), listing| {
+            &**listing_remote != remote || (path != dir && listing.cwd != dir)
+        });
+    }
+
+    /// returns true if some remote command is still running
+    pub fn is_busy(&self) -> bool {
+        self.interrupt.is_busy()
+    }
+
+    /// abort all currently running remote commands
+    pub fn cancel(&self) {
+        self.interrupt.cancel();
+    }
+
     pub fn path_of(&self, remote: &[Part], path: &str) -> String {
         let string_pool = self
             .string_pool
@@ -444,29 +729,35 @@ impl ConnectionPool {
         remote: Arc<[Part]>,
         askpw_tx: Sender<(String, Sender<String>)>,
     ) -> Result<ConnectionHandle<'a>> {
-        let mut connections = self
+        // not locked while connecting, the password prompt waits for the ui thread,
+        // which might need the pool too
+        let cached = self
             .connections
             .lock()
-            .unwrap_or_else(|err| err.into_inner());
-        if let Some(cache) = connections.get_mut(&remote) {
-            if let Some(conn) = cache.pop() {
-                return Ok(ConnectionHandle {
-                    inner: ManuallyDrop::new(conn),
-                    pool: self,
-                });
-            }
+            .unwrap_or_else(|err| err.into_inner())
+            .get_mut(&remote)
+            .and_then(Vec::pop);
+        if let Some(conn) = cached {
+            return Ok(ConnectionHandle {
+                inner: ManuallyDrop::new(conn),
+                pool: self,
+            });
         }
 
         let mut conn = Connection {
             remote,
             shell: rexpect::spawn("env PS1=__sh_prompt TERM=dumb sh", Some(0))?,
+            interrupt: self.interrupt.clone(),
+            poisoned: false,
         };
         conn.wait(None)?;
 
+        // a copy, so parsing other paths doesn't wait for the password prompt either
         let string_pool = self
             .string_pool
             .read()
-            .unwrap_or_else(|err| err.into_inner());
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
 
         for (nth_hop, part) in conn.remote.clone().iter().enumerate() {
             tracing::trace!("hop: {part:?}");
@@ -477,12 +768,13 @@ impl ConnectionPool {
                         &string_pool,
                         nth_hop,
                         askpw_tx.clone(),
+                        &self.passwords,
                         destination.as_str(&string_pool),
                         *port,
                     )?;
                 }
                 Part::Sudo {} => {
-                    conn.hop_sudo(&string_pool, nth_hop, askpw_tx.clone())?;
+                    conn.hop_sudo(&string_pool, nth_hop, askpw_tx.clone(), &self.passwords)?;
                 }
                 Part::Docker { container } => {
                     conn.hop_docker(container.as_str(&string_pool))?;
@@ -500,6 +792,11 @@ impl ConnectionPool {
     }
 
     fn recycle(&self, conn: Connection) {
+        if conn.poisoned {
+            tracing::debug!("dropping a poisoned connection");
+            return;
+        }
+
         let mut connections = self
             .connections
             .lock()
@@ -516,3 +813,219 @@ impl Default for ConnectionPool {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, thread};
+
+    use ropey::Rope;
+
+    use super::*;
+    use crate::buffer::{Buffer, BufferInner};
+
+    /// print `parts` and `path` as a file name, then split and parse it back
+    fn round_trip(pool: &mut String, parts: &[Part], path: &str) -> (Vec<Part>, String) {
+        let printed = Part::print(pool, parts, path);
+        let (hops, file) = Part::split_path(&printed).unwrap();
+        let parts = hops
+            .split('|')
+            .map(|hop| Part::parse(pool, hop))
+            .collect::<Result<_>>()
+            .unwrap();
+        (parts, file.to_string())
+    }
+
+    #[test]
+    fn multi_hop_paths_round_trip() {
+        let mut pool = String::new();
+        let parts = [
+            Part::Ssh {
+                destination: Str::new(&mut pool, "user@host"),
+                port: 2222,
+            },
+            Part::Sudo {},
+            Part::Docker {
+                container: Str::new(&mut pool, "web"),
+            },
+        ];
+
+        for path in [
+            "/etc/hosts",
+            "/tmp/a:b",
+            "/tmp/a|b c",
+            "/tmp/22:x",
+            "relative:",
+        ] {
+            let (parsed, file) = round_trip(&mut pool, &parts, path);
+            assert_eq!(parsed, parts);
+            assert_eq!(file, path);
+        }
+    }
+
+    #[test]
+    fn every_part_round_trips_through_path_of() {
+        let pool = ConnectionPool::new();
+        let parts = {
+            let mut string_pool = pool.string_pool.write().unwrap();
+            [
+                Part::Ssh {
+                    destination: Str::new(&mut string_pool, "host"),
+                    port: 22,
+                },
+                Part::Docker {
+                    container: Str::new(&mut string_pool, "db"),
+                },
+                Part::Sudo {},
+                Part::Bash {},
+            ]
+        };
+
+        let chains = parts.iter().map(|part| vec![*part]).chain([parts.to_vec()]);
+        for chain in chains {
+            let printed = pool.path_of(&chain, "/srv/file");
+            let (hops, file) = Part::split_path(&printed).unwrap();
+            assert_eq!(file, "/srv/file");
+
+            let mut string_pool = pool.string_pool.write().unwrap();
+            let parsed = hops
+                .split('|')
+                .map(|hop| Part::parse(&mut string_pool, hop))
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(parsed, chain, "{printed}");
+        }
+    }
+
+    #[test]
+    fn split_path_without_the_ssh_port() {
+        assert_eq!(
+            Part::split_path("ssh:host:/etc/hosts"),
+            Some(("ssh:host", "/etc/hosts"))
+        );
+        assert_eq!(
+            Part::split_path("ssh:host:22:/etc/hosts"),
+            Some(("ssh:host:22", "/etc/hosts"))
+        );
+        assert_eq!(
+            Part::split_path("sudo|ssh:host:a:b"),
+            Some(("sudo|ssh:host", "a:b"))
+        );
+    }
+
+    #[test]
+    fn split_path_rejects_local_paths() {
+        assert_eq!(Part::split_path("/tmp/ssh:host:file"), None);
+        assert_eq!(Part::split_path("notes:todo.md"), None);
+        assert_eq!(Part::split_path("ssh:host"), None);
+        assert_eq!(Part::split_path("docker"), None);
+    }
+
+    #[test]
+    fn quoting_escapes_shell_syntax() {
+        assert_eq!(Quoted("a b").to_string(), "'a b'");
+        assert_eq!(Quoted("it's").to_string(), r"'it'\''s'");
+        assert_eq!(Quoted("$HOME `id`").to_string(), "'$HOME `id`'");
+        assert_eq!(Quoted("").to_string(), "''");
+    }
+
+    #[test]
+    fn quoted_arguments_reach_the_shell_unchanged() {
+        let arg = "it's \"$HOME\" `id` $(id) a\\b *";
+        let output = process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf %s {}", Quoted(arg)))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), arg);
+    }
+
+    #[test]
+    fn cancel_without_a_running_command_does_nothing() {
+        let interrupt = Interrupt::default();
+        interrupt.cancel();
+        assert!(!interrupt.is_cancelled());
+
+        let running = interrupt.enter();
+        assert!(interrupt.is_busy());
+        interrupt.cancel();
+        assert!(interrupt.is_cancelled());
+
+        // the cancel doesn't outlive the command it was for
+        drop(running);
+        assert!(!interrupt.is_busy());
+        assert!(!interrupt.is_cancelled());
+    }
+
+    #[test]
+    fn a_cancel_after_the_command_finished_does_not_abort_the_next() {
+        let interrupt = Interrupt::default();
+        drop(interrupt.enter());
+        // the UI saw the command as busy, but it finished before the cancel
+        interrupt.cancel();
+
+        let _next = interrupt.enter();
+        assert!(!interrupt.is_cancelled());
+    }
+
+    #[test]
+    fn cancel_lasts_until_every_running_command_is_done() {
+        let interrupt = Interrupt::default();
+        let first = interrupt.enter();
+        let second = interrupt.enter();
+        interrupt.cancel();
+
+        drop(first);
+        assert!(interrupt.is_busy());
+        assert!(interrupt.is_cancelled());
+
+        drop(second);
+        assert!(!interrupt.is_busy());
+        assert!(!interrupt.is_cancelled());
+    }
+
+    #[test]
+    fn cancel_aborts_a_running_command() {
+        let pool = ConnectionPool::new();
+        let (askpw_tx, _askpw_rx) = channel();
+        let mut conn = pool.connect("bash", askpw_tx).unwrap();
+
+        let start = Instant::now();
+        let result = thread::scope(|s| {
+            let sleep = s.spawn(|| conn.run_cmd_checked(format_args!("sleep 20")));
+            while !pool.is_busy() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            pool.cancel();
+            sleep.join().unwrap()
+        });
+
+        assert!(result.unwrap_err().to_string().contains("cancelled"));
+        assert!(start.elapsed() < Duration::from_secs(20));
+        assert!(!pool.is_busy());
+
+        // the shell is in an unknown state, so it isn't reused
+        drop(conn);
+        assert!(pool.connections.lock().unwrap().values().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn read_and_write_a_file_over_the_bash_transport() {
+        let path = env::temp_dir().join(format!("text-editor-tramp-{}", process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, "hello\n").unwrap();
+        let name = format!("bash:{path}");
+        let (askpw_tx, _askpw_rx) = channel();
+
+        let buf = Buffer::open(&name, usize::MAX, askpw_tx.clone()).unwrap();
+        assert_eq!(buf.text(), "hello\n");
+        let BufferInner::Remote { remote, .. } = &buf.inner else {
+            panic!("bash:{path} opened as a local file");
+        };
+
+        let contents = Rope::from_str("it's $HOME\n");
+        Buffer::write_remote(remote.clone(), &name, &contents, buf.ty, askpw_tx).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "it's $HOME\n");
+
+        fs::remove_file(path).unwrap();
+    }
+}